@@ -0,0 +1,371 @@
+// Async variant of the controller threads, enabled by the `async` feature.
+// The same machines and messages as in threads, but each controller runs as
+// tokio tasks exchanging messages over unbounded channels, so one runtime can
+// host many intersections. Ticks come from an interval timer.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::{mpsc as async_mpsc, watch};
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+use crate::clock::Clock;
+use crate::crosswalk::{CrosswalkFsm, PedestrianCall};
+use crate::event_log::EventLog;
+use crate::fsm::{apply, Ack, Exit, Input, StateMachine, Transition, Trigger};
+use crate::messages::{FromCrosswalk, FromMonitor, FromStoplight, ToCrosswalk, ToMonitor, ToStoplight};
+use crate::monitor::{dispatch, SafetyMonitor};
+use crate::stoplight::{StoplightFsm, StoplightState};
+use crate::threads::{CrosswalkReports, Outbox, StoplightReports, TickSequence, TimerOptions};
+
+/// Clock following the tokio runtime's time, so a paused runtime gives
+/// virtual timestamps in the event log.
+#[derive(Debug)]
+pub struct TokioClock {
+    start: Instant,
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokioClock {
+    // Call from within the runtime, whose clock it then follows
+    pub fn new() -> Self {
+        TokioClock { start: Instant::now() }
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // Blocks the calling thread; tasks wait on the runtime's timers instead
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Sending half of a machine task's channel.
+#[derive(Debug)]
+pub struct TaskSender<T> {
+    tx: async_mpsc::UnboundedSender<T>,
+    flushed: watch::Receiver<u64>, // Bumped by the task on every flush marker
+}
+
+impl<T> Clone for TaskSender<T> {
+    fn clone(&self) -> Self {
+        TaskSender { tx: self.tx.clone(), flushed: self.flushed.clone() }
+    }
+}
+
+/// Receiving half of a machine task's channel, see [`run_machine_async`].
+#[derive(Debug)]
+pub struct TaskReceiver<T> {
+    rx: async_mpsc::UnboundedReceiver<T>,
+    flushed: watch::Sender<u64>,
+}
+
+/// Channel to a machine task. Sending never blocks.
+pub fn task_channel<T>() -> (TaskSender<T>, TaskReceiver<T>) {
+    let (tx, rx) = async_mpsc::unbounded_channel();
+    let (flushed_tx, flushed_rx) = watch::channel(0);
+    (TaskSender { tx, flushed: flushed_rx }, TaskReceiver { rx, flushed: flushed_tx })
+}
+
+impl<T> TaskSender<T> {
+    pub fn send(&self, message: T) -> Result<(), SendError<T>> {
+        self.tx.send(message)
+    }
+
+    /// Send a flush marker and wait, without blocking the runtime, until the
+    /// task has handled everything before it. False if the task has stopped.
+    pub async fn flush(&self, marker: fn(Ack) -> T) -> bool {
+        self.flush_with(|ack| self.tx.send(marker(ack)).map_err(drop), ()).await.is_ok()
+    }
+
+    // Have `send` start a flush that ends in this task's queue, and wait
+    // until `ack` is done there. `lost` if it is dropped instead.
+    pub(crate) async fn flush_with<E>(&self, send: impl FnOnce(Ack) -> Result<(), E>, lost: E) -> Result<(), E> {
+        let mut flushed = self.flushed.clone();
+        flushed.borrow_and_update();
+        let (ack_tx, ack_rx) = mpsc::channel();
+        send(ack_tx.into())?;
+        // Other flushes wake this one too; the ack tells them apart
+        loop {
+            match ack_rx.try_recv() {
+                Ok(()) => return Ok(()),
+                Err(mpsc::TryRecvError::Disconnected) => return Err(lost),
+                Err(mpsc::TryRecvError::Empty) if flushed.changed().await.is_err() => return Err(lost),
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+        }
+    }
+}
+
+impl<T> Outbox for TaskSender<T> {
+    type Message = T;
+    type Error = SendError<T>;
+
+    fn post(&self, message: T) -> Result<(), Self::Error> {
+        self.send(message)
+    }
+}
+
+impl<T> Outbox for async_mpsc::UnboundedSender<T> {
+    type Message = T;
+    type Error = SendError<T>;
+
+    fn post(&self, message: T) -> Result<(), Self::Error> {
+        self.send(message)
+    }
+}
+
+/// Async counterpart of [`crate::fsm::run_machine`].
+pub async fn run_machine_async<M, T, F>(
+    machine: &mut M,
+    mut ctx: M::Context,
+    mut rx: TaskReceiver<T>,
+    mut on_step: F,
+) -> Exit
+where
+    M: StateMachine,
+    M::Event: Copy,
+    T: Into<Input<M::Event, M::Context>>,
+    F: FnMut(&M, Option<Transition<M::State>>, Trigger<'_, M::Event, M::Context>),
+{
+    while let Some(message) = rx.rx.recv().await {
+        let input = message.into();
+        let flush = matches!(input, Input::Flush(_));
+        if let Some(exit) = apply(machine, &mut ctx, input, &mut on_step) {
+            return exit;
+        }
+        if flush {
+            rx.flushed.send_modify(|count| *count += 1);
+        }
+    }
+    Exit::Disconnected
+}
+
+/// Async counterpart of [`crate::threads::timer_thread`], ticking on the
+/// runtime's interval timer.
+pub async fn timer_task(
+    tx_stoplight: TaskSender<ToStoplight>,
+    tx_crosswalk: TaskSender<ToCrosswalk>,
+    options: TimerOptions,
+) {
+    let start = Instant::now();
+    let mut interval = time::interval(options.tick_period);
+    // A late tick is not made up for by shorter ones
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut sequence = TickSequence::new(&options);
+    for tick in 0..options.ticks {
+        interval.tick().await;
+        let messages = sequence.next(tick, start.elapsed());
+        // A lockstep tick ends with a flush of the crosswalk
+        let sent = if options.lockstep {
+            let send = |ack| messages.send(&tx_stoplight, &tx_crosswalk, Some(ack));
+            tx_crosswalk.flush_with(send, "tick not acknowledged").await
+        } else {
+            messages.send(&tx_stoplight, &tx_crosswalk, None)
+        };
+        if let Err(failure) = sent {
+            eprintln!("Timer task: {} on tick {}", failure, tick);
+            break;
+        }
+    }
+
+    if let Err(e) = tx_stoplight.send(ToStoplight::Shutdown) {
+        eprintln!("Timer task: failed to send Shutdown to stoplight: {}", e);
+    }
+    if let Err(e) = tx_crosswalk.send(ToCrosswalk::Shutdown) {
+        eprintln!("Timer task: failed to send Shutdown to crosswalk: {}", e);
+    }
+}
+
+/// Async counterpart of [`crate::threads::stoplight_thread`].
+pub async fn stoplight_task<T: From<FromStoplight>>(
+    mut fsm: StoplightFsm,
+    rx: TaskReceiver<ToStoplight>,
+    tx_main: Option<async_mpsc::UnboundedSender<T>>,
+    tx_crosswalk: Option<TaskSender<ToCrosswalk>>,
+    log: EventLog,
+) {
+    let mut reports = StoplightReports::new("Stoplight task", tx_main, tx_crosswalk, log);
+    reports.start(&fsm);
+    run_machine_async(&mut fsm, PedestrianCall::Idle, rx, |fsm, transition, trigger| reports.step(fsm, transition, trigger))
+        .await;
+}
+
+/// Async counterpart of [`crate::threads::crosswalk_thread`].
+pub async fn crosswalk_task<T: From<FromCrosswalk>>(
+    mut fsm: CrosswalkFsm,
+    rx: TaskReceiver<ToCrosswalk>,
+    tx_main: Option<async_mpsc::UnboundedSender<T>>,
+    tx_stoplight: Option<TaskSender<ToStoplight>>,
+    log: EventLog,
+) {
+    let mut reports = CrosswalkReports::new("Crosswalk task", &fsm, tx_main, tx_stoplight, log);
+    reports.start(&fsm);
+    run_machine_async(&mut fsm, StoplightState::Red, rx, |fsm, transition, trigger| reports.step(fsm, transition, trigger))
+        .await;
+}
+
+/// Async counterpart of [`crate::monitor::monitor_thread`].
+pub async fn monitor_task(
+    mut monitor: SafetyMonitor,
+    mut rx: async_mpsc::UnboundedReceiver<ToMonitor>,
+    tx_stoplight: TaskSender<ToStoplight>,
+    tx_crosswalk: TaskSender<ToCrosswalk>,
+    tx_main: Option<async_mpsc::UnboundedSender<FromMonitor>>,
+) -> SafetyMonitor {
+    let report = |message: FromMonitor| {
+        if let Some(ref sender) = tx_main {
+            if let Err(e) = sender.send(message) {
+                eprintln!("Monitor task: failed to send report to main: {}", e);
+            }
+        }
+    };
+    while let Some(message) = rx.recv().await {
+        if !dispatch(&mut monitor, message, &tx_stoplight, &tx_crosswalk, report) {
+            break;
+        }
+    }
+    monitor
+}
+
+/// The tasks of one intersection, spawned by [`spawn_intersection`].
+#[derive(Debug)]
+pub struct IntersectionTasks {
+    reports: async_mpsc::UnboundedReceiver<FromMonitor>,
+    tasks: JoinSet<()>, // Timer, stoplight and crosswalk
+    monitor: JoinHandle<SafetyMonitor>,
+}
+
+/// Spawn the timer, stoplight, crosswalk and monitor tasks of an
+/// intersection on the current runtime, wired as in `main`.
+pub fn spawn_intersection(
+    stoplight: StoplightFsm,
+    crosswalk: CrosswalkFsm,
+    options: TimerOptions,
+    log: EventLog,
+) -> IntersectionTasks {
+    let (tx_stoplight, rx_stoplight) = task_channel();
+    let (tx_crosswalk, rx_crosswalk) = task_channel();
+    let (tx_status, rx_status) = async_mpsc::unbounded_channel::<ToMonitor>();
+    let (tx_reports, reports) = async_mpsc::unbounded_channel();
+
+    let mut tasks = JoinSet::new();
+    tasks.spawn(timer_task(tx_stoplight.clone(), tx_crosswalk.clone(), options));
+    tasks.spawn(stoplight_task(stoplight, rx_stoplight, Some(tx_status.clone()), Some(tx_crosswalk.clone()), log.clone()));
+    tasks.spawn(crosswalk_task(crosswalk, rx_crosswalk, Some(tx_status), Some(tx_stoplight.clone()), log));
+    let monitor = tokio::spawn(monitor_task(SafetyMonitor::new(), rx_status, tx_stoplight, tx_crosswalk, Some(tx_reports)));
+    IntersectionTasks { reports, tasks, monitor }
+}
+
+impl IntersectionTasks {
+    /// Pass every monitor report to `on_report` until the intersection has
+    /// stopped, and return the monitor. Fails as soon as a task panics; the
+    /// remaining tasks are then aborted.
+    pub async fn run<F: FnMut(FromMonitor)>(mut self, mut on_report: F) -> Result<SafetyMonitor, JoinError> {
+        loop {
+            tokio::select! {
+                report = self.reports.recv() => match report {
+                    Some(report) => on_report(report),
+                    None => break, // The monitor has stopped, so have both machines
+                },
+                Some(result) = self.tasks.join_next() => result?,
+            }
+        }
+        while let Some(result) = self.tasks.join_next().await {
+            result?;
+        }
+        self.monitor.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ButtonSchedule;
+    use crate::crosswalk::CrosswalkState;
+    use crate::event_log::MemorySink;
+    use std::sync::Arc;
+
+    fn timer_options(ticks: u32, buttons: ButtonSchedule) -> TimerOptions {
+        TimerOptions {
+            ticks,
+            tick_period: Duration::from_secs(1),
+            buttons,
+            detectors: ButtonSchedule::Never,
+            buses: ButtonSchedule::Never,
+            preempt: Vec::new(),
+            trains: Vec::new(),
+            schedule: None,
+            lockstep: true,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transition_log_matches_threads() {
+        let sink = Arc::new(MemorySink::new());
+        let log = EventLog::new(Arc::new(TokioClock::new())).with_sink(sink.clone());
+        let tasks = spawn_intersection(
+            StoplightFsm::new(),
+            CrosswalkFsm::new(),
+            timer_options(12, ButtonSchedule::At(vec![2])),
+            log,
+        );
+        let mut reports = Vec::new();
+        let monitor = tasks.run(|report| reports.push(report)).await.unwrap();
+
+        let summary: Vec<String> = sink
+            .records()
+            .iter()
+            .map(|r| format!("{} {} {} {} {} {:?}", r.timestamp_ms, r.tick, r.machine, r.to, r.event, r.reason))
+            .collect();
+        // As in threads::tests::test_transition_log_in_lockstep
+        assert_eq!(
+            summary,
+            vec![
+                "2000 3 Crosswalk Walk WalkGranted None",
+                "5000 6 Crosswalk BlinkingDontWalk TimerTick None",
+                "7000 8 Crosswalk DontWalk TimerTick None",
+                "8000 9 Stoplight Green TimerTick None",
+            ]
+        );
+        assert_eq!(monitor.latched(), None);
+        assert!(reports.contains(&FromMonitor::Crosswalk(CrosswalkState::Walk)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_many_intersections_on_one_runtime() {
+        let clock = Arc::new(TokioClock::new());
+        let log = EventLog::new(clock.clone());
+        let runs: Vec<_> = (0..200)
+            .map(|seed| {
+                let buttons = ButtonSchedule::Random { probability: 0.2, seed };
+                let options = timer_options(120, buttons);
+                let tasks = spawn_intersection(StoplightFsm::new(), CrosswalkFsm::new(), options, log.clone());
+                tokio::spawn(async move {
+                    let mut walks = 0;
+                    let walk = FromMonitor::Crosswalk(CrosswalkState::Walk);
+                    let monitor = tasks.run(|report| walks += usize::from(report == walk)).await.unwrap();
+                    (monitor, walks)
+                })
+            })
+            .collect();
+        for run in runs {
+            let (monitor, walks) = run.await.unwrap();
+            assert_eq!(monitor.latched(), None);
+            assert!(walks > 0);
+        }
+        assert_eq!(clock.now(), Duration::from_secs(119)); // Ticks 0 to 119
+    }
+}
@@ -0,0 +1,473 @@
+// Merged status bus. Every controller machine publishes on the same channel,
+// so a single consumer sees their updates in the order they were sent and
+// learns at once when the last publisher is gone. A Broadcast extends this to
+// any number of subscribers, each with its own queue.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::messages::{FromController, FromCrosswalk, FromStoplight};
+use crate::threads::Outbox;
+
+/// Why a receive on a [`StatusBus`] returned without a message.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BusError {
+    Timeout,
+    Disconnected, // Every publisher has been dropped and the bus is drained
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::Timeout => write!(f, "no status update before the timeout"),
+            BusError::Disconnected => write!(f, "every publisher has disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+/// Receiving end of the status bus: a channel whose publishers are plain
+/// senders, one clone per machine, or a subscription to a [`Broadcast`].
+#[derive(Debug)]
+pub struct StatusBus<T = FromController> {
+    source: Source<T>,
+}
+
+#[derive(Debug)]
+enum Source<T> {
+    Channel(mpsc::Receiver<T>),
+    Subscription(Arc<Queue<T>>),
+}
+
+/// A new bus and its first publisher.
+pub fn status_bus<T>() -> (mpsc::Sender<T>, StatusBus<T>) {
+    let (tx, rx) = mpsc::channel();
+    (tx, StatusBus::from(rx))
+}
+
+impl<T> From<mpsc::Receiver<T>> for StatusBus<T> {
+    fn from(rx: mpsc::Receiver<T>) -> Self {
+        StatusBus { source: Source::Channel(rx) }
+    }
+}
+
+impl<T> StatusBus<T> {
+    /// Block until the next update.
+    pub fn recv(&self) -> Result<T, BusError> {
+        match &self.source {
+            Source::Channel(rx) => rx.recv().map_err(|_| BusError::Disconnected),
+            Source::Subscription(queue) => queue.recv(None),
+        }
+    }
+
+    /// Block until the next update or until `timeout` has passed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, BusError> {
+        match &self.source {
+            Source::Channel(rx) => rx.recv_timeout(timeout).map_err(|e| match e {
+                mpsc::RecvTimeoutError::Timeout => BusError::Timeout,
+                mpsc::RecvTimeoutError::Disconnected => BusError::Disconnected,
+            }),
+            // A timeout too long to represent waits forever
+            Source::Subscription(queue) => queue.recv(Instant::now().checked_add(timeout)),
+        }
+    }
+
+    /// Like `recv_timeout`, with a deadline shared by several receives.
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, BusError> {
+        self.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Updates until every publisher has disconnected.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Updates a subscription has lost to its backpressure policy.
+    pub fn dropped(&self) -> u64 {
+        match &self.source {
+            Source::Channel(_) => 0,
+            Source::Subscription(queue) => queue.lock().dropped,
+        }
+    }
+}
+
+impl<T> Drop for StatusBus<T> {
+    // Unsubscribe, releasing a publisher blocked on this queue
+    fn drop(&mut self) {
+        if let Source::Subscription(queue) = &self.source {
+            queue.lock().unsubscribed = true;
+            queue.room.notify_all();
+        }
+    }
+}
+
+/// Kind of a [`FromController`] message, for subscribers to pick from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Topic {
+    StoplightState,
+    Preemption,
+    TransitPriority,
+    CrosswalkState,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 4] = [Topic::StoplightState, Topic::Preemption, Topic::TransitPriority, Topic::CrosswalkState];
+}
+
+impl FromController {
+    pub fn topic(&self) -> Topic {
+        match self {
+            FromController::Stoplight(FromStoplight::StateUpdate(_)) => Topic::StoplightState,
+            FromController::Stoplight(FromStoplight::Preemption(_)) => Topic::Preemption,
+            FromController::Stoplight(FromStoplight::TransitPriority(_)) => Topic::TransitPriority,
+            FromController::Crosswalk(FromCrosswalk::StateUpdate(_)) => Topic::CrosswalkState,
+        }
+    }
+}
+
+/// What publishing does when a subscriber's queue is full.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backpressure {
+    /// Make room by dropping the oldest queued update.
+    DropOldest(usize),
+    /// Wait up to `timeout` for room, then drop the new update. The only
+    /// policy that can hold up a machine; longer timeouts are cut to
+    /// [`Backpressure::MAX_BLOCK`].
+    Block { capacity: usize, timeout: Duration },
+    /// Keep only the latest update of each topic, so the queue never fills.
+    CoalesceLatest,
+}
+
+impl Backpressure {
+    /// Longest one publish waits for all its blocking subscribers together.
+    pub const MAX_BLOCK: Duration = Duration::from_millis(100);
+}
+
+#[derive(Debug)]
+struct Queue<T> {
+    state: Mutex<QueueState<T>>,
+    ready: Condvar, // Signalled on a new update or on close
+    room: Condvar,  // Signalled when an update is taken or the subscriber leaves
+}
+
+#[derive(Debug)]
+struct QueueState<T> {
+    updates: VecDeque<T>,
+    dropped: u64,
+    closed: bool,       // Every publisher is gone
+    unsubscribed: bool, // The subscription was dropped
+}
+
+impl<T> Queue<T> {
+    fn new() -> Self {
+        let state = QueueState { updates: VecDeque::new(), dropped: 0, closed: false, unsubscribed: false };
+        Queue { state: Mutex::new(state), ready: Condvar::new(), room: Condvar::new() }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState<T>> {
+        self.state.lock().expect("status bus queue poisoned")
+    }
+
+    // Next update, waiting until `deadline` if given
+    fn recv(&self, deadline: Option<Instant>) -> Result<T, BusError> {
+        let mut state = self.lock();
+        loop {
+            if let Some(update) = state.updates.pop_front() {
+                self.room.notify_all();
+                return Ok(update);
+            }
+            if state.closed {
+                return Err(BusError::Disconnected);
+            }
+            state = match deadline {
+                None => self.ready.wait(state).expect("status bus queue poisoned"),
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        return Err(BusError::Timeout);
+                    }
+                    self.ready.wait_timeout(state, timeout).expect("status bus queue poisoned").0
+                }
+            };
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    topics: Vec<Topic>,
+    policy: Backpressure,
+    queue: Arc<Queue<FromController>>,
+}
+
+impl Subscriber {
+    // Queue `update`, blocking no later than `deadline`
+    fn offer(&self, update: FromController, deadline: Instant) {
+        let mut state = self.queue.lock();
+        match self.policy {
+            Backpressure::DropOldest(capacity) => {
+                if state.updates.len() >= capacity.max(1) {
+                    state.updates.pop_front();
+                    state.dropped += 1;
+                }
+            }
+            Backpressure::Block { capacity, timeout } => {
+                let deadline = deadline.min(Instant::now() + timeout);
+                while state.updates.len() >= capacity.max(1) && !state.unsubscribed {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        state.dropped += 1;
+                        return;
+                    }
+                    state = self.queue.room.wait_timeout(state, timeout).expect("status bus queue poisoned").0;
+                }
+            }
+            Backpressure::CoalesceLatest => {
+                let topic = update.topic();
+                if let Some(index) = state.updates.iter().position(|queued| queued.topic() == topic) {
+                    state.updates.remove(index);
+                    state.dropped += 1;
+                }
+            }
+        }
+        if !state.unsubscribed {
+            state.updates.push_back(update);
+            self.queue.ready.notify_one();
+        }
+    }
+}
+
+/// Publishing side of the pub/sub status bus. Subscribers can join at any
+/// time and each get their own queue, so a slow one only affects itself.
+/// Clones publish to the same subscribers, which see `Disconnected` once
+/// every clone has been dropped.
+#[derive(Debug)]
+pub struct Broadcast {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+    publishers: AtomicUsize,
+}
+
+impl Default for Broadcast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Broadcast {
+    pub fn new() -> Self {
+        Broadcast { shared: Arc::new(Shared { subscribers: Mutex::new(Vec::new()), publishers: AtomicUsize::new(1) }) }
+    }
+
+    fn subscribers(&self) -> std::sync::MutexGuard<'_, Vec<Arc<Subscriber>>> {
+        self.shared.subscribers.lock().expect("status bus subscribers poisoned")
+    }
+
+    /// Receive every later update of the given topics.
+    pub fn subscribe(&self, topics: &[Topic], policy: Backpressure) -> StatusBus<FromController> {
+        let policy = match policy {
+            Backpressure::Block { capacity, timeout } => {
+                Backpressure::Block { capacity, timeout: timeout.min(Backpressure::MAX_BLOCK) }
+            }
+            policy => policy,
+        };
+        let queue = Arc::new(Queue::new());
+        let subscriber = Subscriber { topics: topics.to_vec(), policy, queue: queue.clone() };
+        self.subscribers().push(Arc::new(subscriber));
+        StatusBus { source: Source::Subscription(queue) }
+    }
+
+    /// Number of subscriptions not yet dropped.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers().iter().filter(|subscriber| !subscriber.queue.lock().unsubscribed).count()
+    }
+
+    /// Queue `update` for every subscriber of its topic, waiting at most
+    /// [`Backpressure::MAX_BLOCK`] in all for blocking subscribers.
+    pub fn publish(&self, update: impl Into<FromController>) {
+        let update = update.into();
+        let deadline = Instant::now() + Backpressure::MAX_BLOCK;
+        // Offer outside the list lock, so a blocking subscriber does not hold up new ones
+        let (blocking, others): (Vec<_>, Vec<_>) = {
+            let mut subscribers = self.subscribers();
+            subscribers.retain(|subscriber| !subscriber.queue.lock().unsubscribed);
+            let subscribers = subscribers.iter().filter(|subscriber| subscriber.topics.contains(&update.topic()));
+            subscribers.cloned().partition(|subscriber| matches!(subscriber.policy, Backpressure::Block { .. }))
+        };
+        // Blocking subscribers last, so the others are not kept waiting
+        for subscriber in others.into_iter().chain(blocking) {
+            subscriber.offer(update.clone(), deadline);
+        }
+    }
+}
+
+impl Clone for Broadcast {
+    fn clone(&self) -> Self {
+        self.shared.publishers.fetch_add(1, Ordering::SeqCst);
+        Broadcast { shared: self.shared.clone() }
+    }
+}
+
+impl Drop for Broadcast {
+    fn drop(&mut self) {
+        if self.shared.publishers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.subscribers().iter().for_each(|subscriber| subscriber.queue.close());
+        }
+    }
+}
+
+impl Outbox for Broadcast {
+    type Message = FromController;
+    type Error = Infallible;
+
+    fn post(&self, message: FromController) -> Result<(), Infallible> {
+        self.publish(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crosswalk::CrosswalkState;
+    use crate::messages::{FromCrosswalk, FromStoplight};
+    use crate::stoplight::StoplightState;
+    use std::thread;
+
+    #[test]
+    fn test_merged_updates_timeout_and_disconnect() {
+        let (tx, bus) = status_bus::<FromController>();
+        let tx_crosswalk = tx.clone();
+        tx.send(FromStoplight::StateUpdate(StoplightState::Green).into()).unwrap();
+        tx_crosswalk.send(FromCrosswalk::StateUpdate(CrosswalkState::DontWalk).into()).unwrap();
+        assert_eq!(bus.recv(), Ok(FromController::Stoplight(FromStoplight::StateUpdate(StoplightState::Green))));
+        assert_eq!(bus.recv(), Ok(FromController::Crosswalk(FromCrosswalk::StateUpdate(CrosswalkState::DontWalk))));
+        assert_eq!(bus.recv_timeout(Duration::from_millis(10)), Err(BusError::Timeout));
+
+        // An update from another thread wakes the receiver at once
+        let publisher = thread::spawn(move || tx.send(FromStoplight::StateUpdate(StoplightState::Yellow).into()).unwrap());
+        let update = bus.recv_timeout(Duration::from_secs(10));
+        assert_eq!(update, Ok(FromController::Stoplight(FromStoplight::StateUpdate(StoplightState::Yellow))));
+        publisher.join().unwrap();
+
+        // Still connected while the crosswalk's publisher lives
+        assert_eq!(bus.recv_deadline(Instant::now()), Err(BusError::Timeout));
+        drop(tx_crosswalk);
+        assert_eq!(bus.recv_timeout(Duration::from_secs(10)), Err(BusError::Disconnected));
+    }
+
+    fn stoplight(state: StoplightState) -> FromController {
+        FromStoplight::StateUpdate(state).into()
+    }
+
+    fn crosswalk(state: CrosswalkState) -> FromController {
+        FromCrosswalk::StateUpdate(state).into()
+    }
+
+    #[test]
+    fn test_subscribers_pick_topics_and_policies() {
+        let bus = Broadcast::new();
+        let everything = bus.subscribe(&Topic::ALL, Backpressure::DropOldest(2));
+        let stoplight_only = bus.subscribe(&[Topic::StoplightState], Backpressure::CoalesceLatest);
+        let publisher = bus.clone();
+        publisher.publish(FromStoplight::StateUpdate(StoplightState::Green));
+        publisher.publish(FromCrosswalk::StateUpdate(CrosswalkState::BlinkingDontWalk));
+        publisher.publish(FromStoplight::StateUpdate(StoplightState::Yellow));
+        publisher.publish(FromStoplight::StateUpdate(StoplightState::Red));
+
+        let wait = Duration::from_secs(10);
+        assert_eq!(everything.dropped(), 2);
+        assert_eq!(everything.recv_timeout(wait), Ok(stoplight(StoplightState::Yellow)));
+        assert_eq!(everything.recv_timeout(wait), Ok(stoplight(StoplightState::Red)));
+        assert_eq!(stoplight_only.dropped(), 2);
+        assert_eq!(stoplight_only.recv_timeout(wait), Ok(stoplight(StoplightState::Red)));
+        assert_eq!(stoplight_only.recv_timeout(Duration::ZERO), Err(BusError::Timeout));
+
+        // A subscriber joining later sees only what follows
+        let late = bus.subscribe(&[Topic::CrosswalkState], Backpressure::DropOldest(8));
+        assert_eq!(bus.subscriber_count(), 3);
+        drop(stoplight_only);
+        assert_eq!(bus.subscriber_count(), 2);
+        publisher.publish(FromCrosswalk::StateUpdate(CrosswalkState::DontWalk));
+        assert_eq!(late.recv_timeout(wait), Ok(crosswalk(CrosswalkState::DontWalk)));
+
+        // Disconnected once every publisher is gone and the queue is drained
+        drop((bus, publisher));
+        assert_eq!(everything.recv(), Ok(crosswalk(CrosswalkState::DontWalk)));
+        assert_eq!(everything.recv(), Err(BusError::Disconnected));
+        assert_eq!(late.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_blocking_subscriber_cannot_stall_publisher() {
+        let bus = Broadcast::new();
+        let slow = bus.subscribe(&Topic::ALL, Backpressure::Block { capacity: 1, timeout: Duration::from_millis(20) });
+        bus.publish(stoplight(StoplightState::Green));
+        let start = Instant::now();
+        bus.publish(stoplight(StoplightState::Yellow)); // Dropped after the timeout
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(slow.dropped(), 1);
+
+        // Taking an update makes room for a waiting publisher
+        let patient = bus.subscribe(&Topic::ALL, Backpressure::Block { capacity: 1, timeout: Backpressure::MAX_BLOCK });
+        drop(slow);
+        bus.publish(stoplight(StoplightState::Yellow));
+        let publisher = {
+            let bus = bus.clone();
+            thread::spawn(move || bus.publish(stoplight(StoplightState::Red)))
+        };
+        assert_eq!(patient.recv(), Ok(stoplight(StoplightState::Yellow)));
+        publisher.join().unwrap();
+        assert_eq!(patient.recv(), Ok(stoplight(StoplightState::Red)));
+
+        // And so does unsubscribing
+        bus.publish(stoplight(StoplightState::Green));
+        let publisher = {
+            let bus = bus.clone();
+            thread::spawn(move || bus.publish(stoplight(StoplightState::Yellow)))
+        };
+        thread::sleep(Duration::from_millis(20));
+        drop(patient);
+        publisher.join().unwrap();
+        assert_eq!(bus.subscriber_count(), 0);
+    }
+
+    #[test]
+    fn test_publish_waits_at_most_max_block() {
+        let bus = Broadcast::new();
+        let policy = Backpressure::Block { capacity: 1, timeout: Duration::MAX };
+        let (first, second) = (bus.subscribe(&Topic::ALL, policy), bus.subscribe(&Topic::ALL, policy));
+        let coalesced = bus.subscribe(&[Topic::StoplightState], Backpressure::CoalesceLatest);
+        bus.publish(stoplight(StoplightState::Green));
+        assert_eq!(coalesced.recv(), Ok(stoplight(StoplightState::Green)));
+
+        // Both blocking queues are full; one deadline covers the two of them
+        let publisher = {
+            let bus = bus.clone();
+            thread::spawn(move || {
+                let start = Instant::now();
+                bus.publish(stoplight(StoplightState::Yellow));
+                start.elapsed()
+            })
+        };
+        // Offered before the blocking subscribers
+        assert_eq!(coalesced.recv_timeout(Duration::from_secs(10)), Ok(stoplight(StoplightState::Yellow)));
+        let elapsed = publisher.join().unwrap();
+        assert!(elapsed >= Backpressure::MAX_BLOCK && elapsed < 2 * Backpressure::MAX_BLOCK, "{:?}", elapsed);
+        assert_eq!((first.dropped(), second.dropped()), (1, 1));
+    }
+}
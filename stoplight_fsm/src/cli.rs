@@ -0,0 +1,308 @@
+// Command-line parsing for the stoplight_fsm binary
+
+use std::path::PathBuf;
+
+use stoplight_fsm::{ButtonSchedule, Config, ConfigError};
+
+pub const USAGE: &str = "\
+Usage: stoplight_fsm [COMMAND] [OPTIONS]
+
+Commands:
+  run                Run the simulation in real time (default)
+  simulate           Run the simulation as fast as possible
+  validate <CONFIG>  Check a .toml or .json timing plan
+  graph <CONFIG>     Print the state diagrams of a timing plan
+  replay <FILE>      Replay a recording made with --record
+  help               Print this message
+
+Options for run and simulate:
+  --config <PATH>    Timing plan to load (defaults are built in)
+  --ticks <N>        Number of timer ticks to simulate
+  --tick-ms <MS>     Length of one tick in milliseconds
+  --buttons <SPEC>   Button presses: never, every:N, at:T1,T2,.. or random:P
+  --detectors <SPEC> Vehicle detector calls, in the same forms as --buttons
+  --buses <SPEC>     Transit priority requests, in the same forms as --buttons
+  --seed <N>         Seed for random:P presses, calls and requests (default 0)
+  --record <PATH>    Write every message the machines handle to PATH
+
+Options for replay:
+  --config <PATH>    Timing plan the recording was made with
+
+Options for all commands:
+  --format <FMT>     text or json; graph also takes mermaid (default) or dot
+";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Text,
+    Json,
+    Mermaid,
+    Dot,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RunOptions {
+    pub config: Option<PathBuf>,
+    pub ticks: Option<u32>,
+    pub tick_ms: Option<u64>,
+    pub buttons: Option<ButtonSchedule>,
+    pub detectors: Option<ButtonSchedule>,
+    pub buses: Option<ButtonSchedule>,
+    pub seed: Option<u64>,
+    pub record: Option<PathBuf>,
+    pub format: Format,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    Run(RunOptions),
+    Simulate(RunOptions),
+    Validate { config: PathBuf, format: Format },
+    Graph { config: PathBuf, format: Format },
+    Replay { recording: PathBuf, config: Option<PathBuf>, format: Format },
+    Help,
+}
+
+impl RunOptions {
+    // Load the timing plan and apply command-line overrides, then re-validate
+    pub fn resolve(&self) -> Result<Config, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        if let Some(ticks) = self.ticks {
+            config.ticks = ticks;
+        }
+        if let Some(tick_ms) = self.tick_ms {
+            config.tick_ms = tick_ms;
+        }
+        if let Some(buttons) = &self.buttons {
+            config.buttons = buttons.clone();
+        }
+        if let Some(detectors) = &self.detectors {
+            config.detectors = detectors.clone();
+        }
+        if let Some(buses) = &self.buses {
+            config.buses = buses.clone();
+        }
+        for schedule in [&mut config.buttons, &mut config.detectors, &mut config.buses] {
+            if let (Some(seed), ButtonSchedule::Random { seed: s, .. }) = (self.seed, schedule) {
+                *s = seed;
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let command = match args.peek().map(String::as_str) {
+        None => "run".to_string(),
+        Some(arg) if arg.starts_with("--") => "run".to_string(),
+        Some(_) => args.next().unwrap_or_default(),
+    };
+
+    let mut options = RunOptions {
+        config: None,
+        ticks: None,
+        tick_ms: None,
+        buttons: None,
+        detectors: None,
+        buses: None,
+        seed: None,
+        record: None,
+        format: Format::Text,
+    };
+    let mut positional = Vec::new();
+    let mut format = None;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+        // Accept both "--flag value" and "--flag=value"
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        if flag == "--help" {
+            return Ok(Command::Help);
+        }
+        let value = match inline.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(format!("{} needs a value", flag)),
+        };
+        match flag.as_str() {
+            "--config" => options.config = Some(PathBuf::from(value)),
+            "--ticks" => options.ticks = Some(number(&flag, &value)?),
+            "--tick-ms" => options.tick_ms = Some(number(&flag, &value)?),
+            "--buttons" => options.buttons = Some(parse_schedule(&flag, &value)?),
+            "--detectors" => options.detectors = Some(parse_schedule(&flag, &value)?),
+            "--buses" => options.buses = Some(parse_schedule(&flag, &value)?),
+            "--seed" => options.seed = Some(number(&flag, &value)?),
+            "--record" => options.record = Some(PathBuf::from(value)),
+            "--format" => format = Some(parse_format(&value)?),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    let config_arg = |positional: &mut Vec<String>, what: &str| match positional.len() {
+        1 => Ok(PathBuf::from(positional.remove(0))),
+        0 => Err(format!("{} needs a {}", command, what)),
+        _ => Err(format!("unexpected argument {}", positional[1])),
+    };
+
+    match command.as_str() {
+        "run" | "simulate" => {
+            if let Some(arg) = positional.first() {
+                return Err(format!("unexpected argument {}", arg));
+            }
+            options.format = match format {
+                None | Some(Format::Text) => Format::Text,
+                Some(Format::Json) => Format::Json,
+                Some(other) => return Err(format!("{:?} output is only available for graph", other)),
+            };
+            if command == "run" {
+                Ok(Command::Run(options))
+            } else {
+                Ok(Command::Simulate(options))
+            }
+        }
+        "validate" => {
+            let format = match format {
+                None | Some(Format::Text) => Format::Text,
+                Some(Format::Json) => Format::Json,
+                Some(other) => return Err(format!("{:?} output is only available for graph", other)),
+            };
+            Ok(Command::Validate { config: config_arg(&mut positional, "config file")?, format })
+        }
+        "graph" => {
+            let format = match format {
+                None | Some(Format::Text) | Some(Format::Mermaid) => Format::Mermaid,
+                Some(other) => other,
+            };
+            Ok(Command::Graph { config: config_arg(&mut positional, "config file")?, format })
+        }
+        "replay" => {
+            let format = match format {
+                None | Some(Format::Text) => Format::Text,
+                Some(Format::Json) => Format::Json,
+                Some(other) => return Err(format!("{:?} output is only available for graph", other)),
+            };
+            let recording = config_arg(&mut positional, "recording")?;
+            Ok(Command::Replay { recording, config: options.config, format })
+        }
+        "help" => Ok(Command::Help),
+        other => Err(format!("unknown command {}", other)),
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a number, got {:?}", flag, value))
+}
+
+fn parse_format(value: &str) -> Result<Format, String> {
+    match value {
+        "text" => Ok(Format::Text),
+        "json" => Ok(Format::Json),
+        "mermaid" => Ok(Format::Mermaid),
+        "dot" => Ok(Format::Dot),
+        _ => Err(format!("unknown format {:?}", value)),
+    }
+}
+
+fn parse_schedule(flag: &str, spec: &str) -> Result<ButtonSchedule, String> {
+    let (kind, arg) = spec.split_once(':').unwrap_or((spec, ""));
+    match kind {
+        "never" if arg.is_empty() => Ok(ButtonSchedule::Never),
+        "every" => Ok(ButtonSchedule::Every(number(&format!("{} every", flag), arg)?)),
+        "at" => arg
+            .split(',')
+            .map(|tick| number(&format!("{} at", flag), tick.trim()))
+            .collect::<Result<Vec<u32>, String>>()
+            .map(ButtonSchedule::At),
+        "random" => Ok(ButtonSchedule::Random { probability: number(&format!("{} random", flag), arg)?, seed: 0 }),
+        _ => Err(format!("unknown {} schedule {:?}", flag, spec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_no_arguments_runs_defaults() {
+        match parse(args("")).unwrap() {
+            Command::Run(options) => assert_eq!(options.resolve().unwrap(), Config::default()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_simulate_overrides() {
+        let command = parse(args(
+            "simulate --ticks 100 --tick-ms=10 --buttons random:0.5 --detectors random:0.2 --buses every:7 --seed 9 --format json",
+        ))
+        .unwrap();
+        let Command::Simulate(options) = command else { panic!("expected simulate") };
+        assert_eq!(options.format, Format::Json);
+        let config = options.resolve().unwrap();
+        assert_eq!(config.ticks, 100);
+        assert_eq!(config.tick_ms, 10);
+        assert_eq!(config.buttons, ButtonSchedule::Random { probability: 0.5, seed: 9 });
+        assert_eq!(config.detectors, ButtonSchedule::Random { probability: 0.2, seed: 9 });
+        assert_eq!(config.buses, ButtonSchedule::Every(7));
+    }
+
+    #[test]
+    fn test_validate_and_graph_need_config() {
+        assert_eq!(
+            parse(args("validate plan.toml --format json")).unwrap(),
+            Command::Validate { config: PathBuf::from("plan.toml"), format: Format::Json }
+        );
+        assert_eq!(
+            parse(args("graph plan.toml")).unwrap(),
+            Command::Graph { config: PathBuf::from("plan.toml"), format: Format::Mermaid }
+        );
+        assert_eq!(parse(args("graph")).unwrap_err(), "graph needs a config file");
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let Command::Run(options) = parse(args("run --record field.jsonl")).unwrap() else { panic!("expected run") };
+        assert_eq!(options.record, Some(PathBuf::from("field.jsonl")));
+        assert_eq!(
+            parse(args("replay field.jsonl --config plan.toml --format json")).unwrap(),
+            Command::Replay {
+                recording: PathBuf::from("field.jsonl"),
+                config: Some(PathBuf::from("plan.toml")),
+                format: Format::Json
+            }
+        );
+        assert_eq!(parse(args("replay")).unwrap_err(), "replay needs a recording");
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        assert_eq!(parse(args("run --ticks many")).unwrap_err(), "--ticks expects a number, got \"many\"");
+        assert_eq!(parse(args("run --speed 3")).unwrap_err(), "unknown option --speed");
+        assert_eq!(parse(args("run --ticks")).unwrap_err(), "--ticks needs a value");
+        assert_eq!(parse(args("run --format dot")).unwrap_err(), "Dot output is only available for graph");
+        assert_eq!(parse(args("fly")).unwrap_err(), "unknown command fly");
+        assert!(parse(args("run --buttons at:1,x")).is_err());
+    }
+
+    #[test]
+    fn test_button_specs() {
+        assert_eq!(parse_schedule("--buttons", "never"), Ok(ButtonSchedule::Never));
+        assert_eq!(parse_schedule("--buttons", "every:3"), Ok(ButtonSchedule::Every(3)));
+        assert_eq!(parse_schedule("--buttons", "at:1, 4,9"), Ok(ButtonSchedule::At(vec![1, 4, 9])));
+        assert_eq!(parse_schedule("--detectors", "every:x"), Err("--detectors every expects a number, got \"x\"".into()));
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time source for the controller threads.
+///
+/// `now` is the time since the clock was created. Threads only wait through
+/// `sleep`, so a [`VirtualClock`] can run a simulation without real delays.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall clock backed by `Instant` and `thread::sleep`.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Simulated clock that only moves when advanced.
+///
+/// A manual clock blocks `sleep` until another thread calls `advance` past
+/// the deadline. An auto-advancing clock treats `sleep` itself as advancing
+/// time, which lets a single timer thread drive a simulation at full speed.
+pub struct VirtualClock {
+    now: Mutex<Duration>,
+    advanced: Condvar,
+    auto_advance: bool,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock { now: Mutex::new(Duration::ZERO), advanced: Condvar::new(), auto_advance: false }
+    }
+
+    pub fn auto() -> Self {
+        VirtualClock { auto_advance: true, ..Self::new() }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+        self.advanced.notify_all();
+    }
+
+    // Move forward to `time`; a clock never goes backwards
+    pub fn advance_to(&self, time: Duration) {
+        let mut now = self.now.lock().unwrap();
+        if time > *now {
+            *now = time;
+            self.advanced.notify_all();
+        }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if self.auto_advance {
+            self.advance(duration);
+            return;
+        }
+        let now = self.now.lock().unwrap();
+        let deadline = *now + duration;
+        let _guard = self.advanced.wait_while(now, |now| *now < deadline).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_auto_clock_advances_on_sleep() {
+        let clock = VirtualClock::auto();
+        clock.sleep(Duration::from_secs(3600));
+        clock.sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), Duration::from_millis(3_600_005));
+    }
+
+    #[test]
+    fn test_manual_clock_blocks_until_advanced() {
+        let clock = Arc::new(VirtualClock::new());
+        let sleeper = {
+            let clock = Arc::clone(&clock);
+            thread::spawn(move || {
+                clock.sleep(Duration::from_secs(10));
+                clock.now()
+            })
+        };
+        // The sleeper may start before or after any given advance
+        while !sleeper.is_finished() {
+            clock.advance(Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(1));
+        }
+        let woke_at = sleeper.join().unwrap();
+        assert!(woke_at >= Duration::from_secs(10));
+        assert!(woke_at <= clock.now());
+    }
+}
@@ -0,0 +1,942 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coordination::CoordinationConfig;
+use crate::crosswalk::{CrosswalkFsm, CrosswalkGeometry, CrosswalkState, CrosswalkTiming, FlashDisplay};
+use crate::schedule::{PlanScheduler, Schedule, TimingPlan};
+use crate::stoplight::{
+    Actuation, FlashMode, PreemptRequest, RailCrossing, StoplightFsm, StoplightState, StoplightTiming, TransitPriority,
+};
+use crate::threads::TimerOptions;
+
+/// Signal timing plan and simulation settings, loaded from TOML or JSON.
+///
+/// Durations are in timer ticks; `tick_ms` gives the length of one tick.
+/// Missing fields take the built-in defaults, unknown fields are rejected.
+///
+/// ```toml
+/// tick_ms = 1000
+/// ticks = 25
+///
+/// [stoplight]
+/// initial = "Red"
+/// red = 5
+/// green = 4
+/// yellow = 1
+/// red_clearance = 0
+/// red_revert = 0
+/// approach = 1
+///
+/// # Optional: actuated green, `green` is then the minimum
+/// # [stoplight.actuated]
+/// # max_green = 10
+/// # passage = 2
+/// # rest = "Red"
+///
+/// # Optional: transit signal priority for buses
+/// # [stoplight.transit]
+/// # max_extension = 3
+/// # max_early = 2
+/// # lockout = 20
+///
+/// # Optional: coordination with the rest of a corridor; green and red are
+/// # then taken from the split
+/// # [stoplight.coordination]
+/// # cycle_s = 60
+/// # offset_s = 12
+/// # split = { percent = 45 }
+/// # transition = "Shortway"
+///
+/// # Optional: railroad crossing preemption
+/// # [stoplight.rail]
+/// # track_approach = 1
+/// # track_clearance = 5
+/// # dwell_approaches = [2]
+///
+/// [crosswalk]
+/// initial = "DontWalk"
+/// walk = 3
+/// blinking = 2
+/// during_flash = "DontWalk"
+///
+/// # Optional: time walk and blinking from the crossing instead
+/// # [crosswalk.geometry]
+/// # length_ft = 48
+///
+/// [buttons]
+/// every = 5
+///
+/// # Optional: simulated vehicle detector calls, same forms as buttons
+/// # [detectors]
+/// # every = 3
+///
+/// # Optional: simulated bus priority requests, same forms as buttons
+/// # [buses]
+/// # at = [8, 30]
+///
+/// # Optional: simulated emergency preemptions, any number of them
+/// # [[preempt]]
+/// # approach = 2
+/// # priority = 1
+/// # at = 12
+/// # clear = 20
+///
+/// # Optional: simulated trains, any number of them
+/// # [[trains]]
+/// # gate_down = 30
+/// # gate_up = 45
+///
+/// # Optional: named timing plans, overriding [stoplight] durations, and
+/// # the calendar that switches between them
+/// # [plans.am_peak]
+/// # green = 8
+/// # [plans.night]
+/// # flash = "Yellow"
+/// # [schedule]
+/// # start = "2024-01-01 06:00"
+/// # holidays = ["2024-12-25"]
+/// # [[schedule.entries]]
+/// # days = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+/// # at = "06:30"
+/// # plan = "am_peak"
+/// # [[schedule.entries]]
+/// # at = "22:00"
+/// # plan = "night"
+/// ```
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tick_ms: u64,
+    pub ticks: u32, // Number of ticks to simulate
+    pub stoplight: StoplightConfig,
+    pub crosswalk: CrosswalkConfig,
+    pub buttons: ButtonSchedule,
+    pub detectors: ButtonSchedule, // Simulated vehicle detector calls
+    pub buses: ButtonSchedule,     // Simulated transit priority requests
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub preempt: Vec<PreemptRun>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trains: Vec<TrainRun>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub plans: BTreeMap<String, PlanConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>, // Fixed [stoplight] timing when absent
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoplightConfig {
+    pub initial: StoplightState,
+    pub red: u32,
+    pub green: u32,
+    pub yellow: u32,
+    pub red_clearance: u32, // All-red after each Yellow, 0 for none
+    pub red_revert: u32,    // Minimum Red after each Yellow, 0 for none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actuated: Option<Actuation>, // Fixed-time when absent
+    pub approach: u8,                // Approach id for preemption requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rail: Option<RailCrossing>, // Red throughout a railroad preemption when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transit: Option<TransitPriority>, // Transit requests are denied when absent
+    // Common cycle with the rest of the corridor; green and red then come from the split
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordination: Option<CoordinationConfig>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrosswalkConfig {
+    pub initial: CrosswalkState,
+    pub walk: u32,
+    pub blinking: u32,
+    pub during_flash: FlashDisplay, // Dark or DontWalk while the stoplight flashes
+    // When set, walk and blinking are computed from the crossing and stoplight.red
+    // is extended to fit them, rather than checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<CrosswalkGeometry>,
+}
+
+/// Named timing plan. Durations left out are taken from `[stoplight]`; a
+/// plan with `flash` set runs flashing operation instead.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlanConfig {
+    pub red: Option<u32>,
+    pub green: Option<u32>,
+    pub yellow: Option<u32>,
+    pub red_clearance: Option<u32>,
+    pub red_revert: Option<u32>,
+    pub coordination: Option<CoordinationConfig>, // Replaces [stoplight.coordination]
+    pub flash: Option<FlashMode>,
+}
+
+/// Emergency preemption sent by the timer thread: the request on tick `at`,
+/// the clear on tick `clear`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreemptRun {
+    pub approach: u8,
+    #[serde(default)]
+    pub priority: u8,
+    pub at: u32,
+    pub clear: u32,
+}
+
+impl PreemptRun {
+    pub fn request(&self) -> PreemptRequest {
+        PreemptRequest { approach: self.approach, priority: self.priority }
+    }
+}
+
+/// Train sent by the timer thread: gate-down on tick `gate_down`, gate-up
+/// on tick `gate_up`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrainRun {
+    pub gate_down: u32,
+    pub gate_up: u32,
+}
+
+/// When the timer thread simulates crosswalk button presses (or, for
+/// `detectors`, vehicle detector calls).
+///
+/// `every = N` presses on every Nth tick, `at = [..]` on the listed tick
+/// numbers (counting from 0, as printed by the timer thread), and
+/// `random = { probability, seed }` on pseudo-random ticks that are the same
+/// for every run with the same seed.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum ButtonSchedule {
+    Never,
+    Every(u32),
+    At(Vec<u32>),
+    Random { probability: f64, seed: u64 },
+}
+
+impl ButtonSchedule {
+    pub fn pressed_at(&self, tick: u32) -> bool {
+        match self {
+            ButtonSchedule::Never => false,
+            ButtonSchedule::Every(n) => (tick + 1).is_multiple_of(*n),
+            ButtonSchedule::At(ticks) => ticks.contains(&tick),
+            ButtonSchedule::Random { probability, seed } => {
+                // Top 53 bits of the hash as a uniform value in [0, 1)
+                let sample = (splitmix64(seed ^ u64::from(tick)) >> 11) as f64 / (1u64 << 53) as f64;
+                sample < *probability
+            }
+        }
+    }
+}
+
+// SplitMix64 finalizer, enough to spread seeds and tick numbers evenly
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Default for ButtonSchedule {
+    fn default() -> Self {
+        ButtonSchedule::Every(5)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tick_ms: 1000,
+            ticks: 25,
+            stoplight: StoplightConfig::default(),
+            crosswalk: CrosswalkConfig::default(),
+            buttons: ButtonSchedule::default(),
+            detectors: ButtonSchedule::Never,
+            buses: ButtonSchedule::Never,
+            preempt: Vec::new(),
+            trains: Vec::new(),
+            plans: BTreeMap::new(),
+            schedule: None,
+        }
+    }
+}
+
+impl Default for StoplightConfig {
+    fn default() -> Self {
+        let timing = StoplightTiming::default();
+        StoplightConfig {
+            initial: StoplightState::Red,
+            red: timing.red,
+            green: timing.green,
+            yellow: timing.yellow,
+            red_clearance: timing.red_clearance,
+            red_revert: timing.red_revert,
+            actuated: timing.actuation,
+            approach: 1,
+            rail: None,
+            transit: None,
+            coordination: None,
+        }
+    }
+}
+
+impl Default for CrosswalkConfig {
+    fn default() -> Self {
+        let timing = CrosswalkTiming::default();
+        CrosswalkConfig {
+            initial: CrosswalkState::DontWalk,
+            walk: timing.walk,
+            blinking: timing.blinking,
+            during_flash: FlashDisplay::default(),
+            geometry: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    UnknownFormat(String), // File extension is neither .toml nor .json
+    Parse(String),
+    Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path, e),
+            ConfigError::UnknownFormat(path) => write!(f, "{}: expected a .toml or .json file", path),
+            ConfigError::Parse(msg) => write!(f, "parse error: {}", msg),
+            ConfigError::Invalid { field, reason } => write!(f, "invalid {}: {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load and validate a config file, choosing the format by extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Config::from_toml_str(&text),
+            Some("json") => Config::from_json_str(&text),
+            _ => Err(ConfigError::UnknownFormat(name)),
+        }
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(|e| ConfigError::Parse(e.message().to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_json_str(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = serde_json::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, reason: &str| Err(ConfigError::Invalid { field, reason: reason.to_string() });
+
+        if self.tick_ms == 0 {
+            return invalid("tick_ms", "must be greater than 0");
+        }
+        for (field, ticks) in [
+            ("stoplight.red", self.stoplight.red),
+            ("stoplight.green", self.stoplight.green),
+            ("stoplight.yellow", self.stoplight.yellow),
+            ("crosswalk.walk", self.crosswalk.walk),
+            ("crosswalk.blinking", self.crosswalk.blinking),
+        ] {
+            if ticks == 0 {
+                return invalid(field, "must be at least 1 tick");
+            }
+        }
+        // The same bound as a crosswalk interval, which also keeps the sum of
+        // a few intervals well inside u32
+        let max_s = CrosswalkGeometry::MAX_INTERVAL_S;
+        let too_long = |ticks: u32| ticks as f64 * self.tick_ms as f64 / 1000.0 > max_s;
+        let (actuation, rail, transit) =
+            (self.stoplight.actuated.as_ref(), self.stoplight.rail.as_ref(), self.stoplight.transit.as_ref());
+        for (field, ticks) in [
+            ("stoplight.red", Some(self.stoplight.red)),
+            ("stoplight.green", Some(self.stoplight.green)),
+            ("stoplight.yellow", Some(self.stoplight.yellow)),
+            ("stoplight.red_clearance", Some(self.stoplight.red_clearance)),
+            ("stoplight.red_revert", Some(self.stoplight.red_revert)),
+            ("stoplight.actuated.max_green", actuation.map(|actuation| actuation.max_green)),
+            ("stoplight.actuated.passage", actuation.map(|actuation| actuation.passage)),
+            ("stoplight.rail.track_clearance", rail.map(|rail| rail.track_clearance)),
+            ("stoplight.transit.max_extension", transit.map(|transit| transit.max_extension)),
+            ("stoplight.transit.max_early", transit.map(|transit| transit.max_early)),
+            ("stoplight.transit.lockout", transit.map(|transit| transit.lockout)),
+            ("crosswalk.walk", Some(self.crosswalk.walk)),
+            ("crosswalk.blinking", Some(self.crosswalk.blinking)),
+        ] {
+            if ticks.is_some_and(too_long) {
+                return Err(ConfigError::Invalid { field, reason: format!("must be at most {} s", max_s) });
+            }
+        }
+        for (name, plan) in &self.plans {
+            for (field, ticks) in [
+                ("plans.red", plan.red),
+                ("plans.green", plan.green),
+                ("plans.yellow", plan.yellow),
+                ("plans.red_clearance", plan.red_clearance),
+                ("plans.red_revert", plan.red_revert),
+            ] {
+                if ticks.is_some_and(too_long) {
+                    let reason = format!("plan {:?} must be at most {} s", name, max_s);
+                    return Err(ConfigError::Invalid { field, reason });
+                }
+            }
+        }
+        if let Some(actuation) = &self.stoplight.actuated {
+            if actuation.passage == 0 {
+                return invalid("stoplight.actuated.passage", "must be at least 1 tick");
+            }
+            if actuation.max_green < self.stoplight.green {
+                return Err(ConfigError::Invalid {
+                    field: "stoplight.actuated.max_green",
+                    reason: format!(
+                        "{} ticks is shorter than the minimum stoplight.green ({} ticks)",
+                        actuation.max_green, self.stoplight.green
+                    ),
+                });
+            }
+        }
+        if let Some(rail) = &self.stoplight.rail {
+            if rail.track_clearance == 0 {
+                return invalid("stoplight.rail.track_clearance", "must be at least 1 tick");
+            }
+            if rail.dwell_approaches.contains(&rail.track_approach) {
+                return invalid("stoplight.rail.dwell_approaches", "must not include the track approach");
+            }
+        }
+        if let Some(geometry) = &self.crosswalk.geometry {
+            if !(geometry.length_ft.is_finite() && geometry.length_ft > 0.0) {
+                return invalid("crosswalk.geometry.length_ft", "must be greater than 0");
+            }
+            if !(geometry.walking_speed_ft_s.is_finite() && geometry.walking_speed_ft_s > 0.0) {
+                return invalid("crosswalk.geometry.walking_speed_ft_s", "must be greater than 0");
+            }
+            if !(geometry.min_walk_s.is_finite() && geometry.min_walk_s >= 0.0) {
+                return invalid("crosswalk.geometry.min_walk_s", "must not be negative");
+            }
+            let max = CrosswalkGeometry::MAX_INTERVAL_S;
+            if geometry.min_walk_s > max {
+                return Err(ConfigError::Invalid {
+                    field: "crosswalk.geometry.min_walk_s",
+                    reason: format!("must be at most {} s", max),
+                });
+            }
+            if geometry.length_ft / geometry.walking_speed_ft_s > max {
+                return Err(ConfigError::Invalid {
+                    field: "crosswalk.geometry.length_ft",
+                    reason: format!("takes longer than {} s to cross at walking_speed_ft_s", max),
+                });
+            }
+        } else if self.crosswalk.walk > self.stoplight.red {
+            return Err(ConfigError::Invalid {
+                field: "crosswalk.walk",
+                reason: format!("{} ticks is longer than stoplight.red ({} ticks)", self.crosswalk.walk, self.stoplight.red),
+            });
+        }
+        let Some(walk_and_blinking) = self.crosswalk.walk.checked_add(self.crosswalk.blinking) else {
+            return invalid("crosswalk.blinking", "walk + blinking is too large");
+        };
+        if self.crosswalk.geometry.is_none() && walk_and_blinking > self.stoplight.red {
+            return Err(ConfigError::Invalid {
+                field: "crosswalk.blinking",
+                reason: format!(
+                    "walk + blinking ({} ticks) does not fit in stoplight.red ({} ticks)",
+                    walk_and_blinking, self.stoplight.red
+                ),
+            });
+        }
+        // Flashing operation is only entered on command
+        if self.stoplight.initial.is_flashing() {
+            return invalid("stoplight.initial", "must be Red, Green or Yellow");
+        }
+        if self.crosswalk.initial == CrosswalkState::Dark {
+            return invalid("crosswalk.initial", "must not be Dark");
+        }
+        if self.crosswalk.initial != CrosswalkState::DontWalk && self.stoplight.initial != StoplightState::Red {
+            return invalid("crosswalk.initial", "must be DontWalk unless stoplight.initial is Red");
+        }
+        for (fields, schedule) in [
+            (["buttons.every", "buttons.at", "buttons.random.probability"], &self.buttons),
+            (["detectors.every", "detectors.at", "detectors.random.probability"], &self.detectors),
+            (["buses.every", "buses.at", "buses.random.probability"], &self.buses),
+        ] {
+            match schedule {
+                ButtonSchedule::Every(0) => return invalid(fields[0], "must be greater than 0"),
+                ButtonSchedule::At(ticks) if ticks.iter().any(|&t| t >= self.ticks) => {
+                    return invalid(fields[1], "tick numbers must be below ticks");
+                }
+                ButtonSchedule::Random { probability, .. } if !(0.0..=1.0).contains(probability) => {
+                    return invalid(fields[2], "must be between 0 and 1");
+                }
+                _ => {}
+            }
+        }
+        for run in &self.preempt {
+            if run.clear <= run.at {
+                return invalid("preempt.clear", "must come after preempt.at");
+            }
+            if run.at >= self.ticks {
+                return invalid("preempt.at", "tick numbers must be below ticks");
+            }
+        }
+        if let Some(coordination) = &self.stoplight.coordination {
+            self.coordinate(self.fixed_timing(), coordination)
+                .map_err(|reason| ConfigError::Invalid { field: "stoplight.coordination", reason })?;
+        }
+        for (name, plan) in &self.plans {
+            for (field, ticks) in [("plans.red", plan.red), ("plans.green", plan.green), ("plans.yellow", plan.yellow)] {
+                if ticks == Some(0) {
+                    return Err(ConfigError::Invalid { field, reason: format!("plan {:?} must be at least 1 tick", name) });
+                }
+            }
+            if let Some(red) = plan.red.filter(|&red| self.crosswalk.geometry.is_none() && walk_and_blinking > red) {
+                return Err(ConfigError::Invalid {
+                    field: "plans.red",
+                    reason: format!(
+                        "plan {:?}: walk + blinking ({} ticks) does not fit in {} ticks",
+                        name, walk_and_blinking, red
+                    ),
+                });
+            }
+            if let Some(coordination) = &plan.coordination {
+                self.coordinate(self.plan_fixed_timing(plan), coordination).map_err(|reason| ConfigError::Invalid {
+                    field: "plans.coordination",
+                    reason: format!("plan {:?}: {}", name, reason),
+                })?;
+            }
+        }
+        for entry in self.schedule.iter().flat_map(|schedule| &schedule.entries) {
+            if !self.plans.contains_key(&entry.plan) {
+                return Err(ConfigError::Invalid {
+                    field: "schedule.entries.plan",
+                    reason: format!("no plan named {:?}", entry.plan),
+                });
+            }
+        }
+        for train in &self.trains {
+            if train.gate_up <= train.gate_down {
+                return invalid("trains.gate_up", "must come after trains.gate_down");
+            }
+            if train.gate_down >= self.ticks {
+                return invalid("trains.gate_down", "tick numbers must be below ticks");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn tick_period(&self) -> Duration {
+        Duration::from_millis(self.tick_ms)
+    }
+
+    pub fn timer_options(&self) -> TimerOptions {
+        TimerOptions {
+            ticks: self.ticks,
+            tick_period: self.tick_period(),
+            buttons: self.buttons.clone(),
+            detectors: self.detectors.clone(),
+            buses: self.buses.clone(),
+            preempt: self.preempt.clone(),
+            trains: self.trains.clone(),
+            schedule: self.schedule.as_ref().map(|schedule| PlanScheduler::new(schedule.clone(), self.timing_plans())),
+            lockstep: true,
+        }
+    }
+
+    pub fn stoplight_timing(&self) -> StoplightTiming {
+        let fixed = self.fixed_timing();
+        match &self.stoplight.coordination {
+            Some(coordination) => self.coordinate(fixed, coordination).unwrap_or(fixed),
+            None => fixed,
+        }
+    }
+
+    // [stoplight] durations before coordination
+    fn fixed_timing(&self) -> StoplightTiming {
+        // Without geometry, validate has already checked that the crossing fits
+        let crossing = self.crosswalk_timing();
+        StoplightTiming {
+            red: self.stoplight.red.max(crossing.interval()),
+            green: self.stoplight.green,
+            yellow: self.stoplight.yellow,
+            red_clearance: self.stoplight.red_clearance,
+            red_revert: self.stoplight.red_revert,
+            actuation: self.stoplight.actuated,
+            coordination: None,
+        }
+    }
+
+    fn plan_fixed_timing(&self, plan: &PlanConfig) -> StoplightTiming {
+        let base = self.fixed_timing();
+        let crossing = self.crosswalk_timing();
+        StoplightTiming {
+            red: plan.red.unwrap_or(self.stoplight.red).max(crossing.interval()),
+            green: plan.green.unwrap_or(base.green),
+            yellow: plan.yellow.unwrap_or(base.yellow),
+            red_clearance: plan.red_clearance.unwrap_or(base.red_clearance),
+            red_revert: plan.red_revert.unwrap_or(base.red_revert),
+            ..base
+        }
+    }
+
+    // Green and Red from a coordination split, the rest of `timing` kept
+    fn coordinate(&self, timing: StoplightTiming, coordination: &CoordinationConfig) -> Result<StoplightTiming, String> {
+        if !(coordination.cycle_s.is_finite() && coordination.cycle_s > 0.0) {
+            return Err("cycle_s must be greater than 0".to_string());
+        }
+        if coordination.cycle_s > CrosswalkGeometry::MAX_INTERVAL_S {
+            return Err(format!("cycle_s must be at most {} s", CrosswalkGeometry::MAX_INTERVAL_S));
+        }
+        if !(coordination.offset_s.is_finite() && coordination.offset_s >= 0.0) {
+            return Err("offset_s must not be negative".to_string());
+        }
+        if timing.actuation.is_some() {
+            return Err("needs fixed-time operation, without stoplight.actuated".to_string());
+        }
+        let (coordination, green) = coordination
+            .to_ticks(self.tick_period(), timing.yellow, timing.red_clearance)
+            .ok_or("split leaves no green, or no Red, in the cycle")?;
+        let red = coordination.cycle - green - timing.yellow;
+        let crossing = self.crosswalk_timing();
+        if red < crossing.interval() {
+            return Err(format!("Red of {} ticks is shorter than walk + blinking ({} ticks)", red, crossing.interval()));
+        }
+        Ok(StoplightTiming { red, green, coordination: Some(coordination), ..timing })
+    }
+
+    /// The named plans, completed from `[stoplight]` the same way as
+    /// `stoplight_timing`.
+    pub fn timing_plans(&self) -> Vec<TimingPlan> {
+        self.plans
+            .iter()
+            .map(|(name, plan)| {
+                let fixed = self.plan_fixed_timing(plan);
+                let timing = match plan.coordination.as_ref().or(self.stoplight.coordination.as_ref()) {
+                    Some(coordination) => self.coordinate(fixed, coordination).unwrap_or(fixed),
+                    None => fixed,
+                };
+                TimingPlan { name: name.clone(), timing, flash: plan.flash }
+            })
+            .collect()
+    }
+
+    pub fn crosswalk_timing(&self) -> CrosswalkTiming {
+        match &self.crosswalk.geometry {
+            Some(geometry) => geometry.timing(self.tick_period()),
+            None => CrosswalkTiming { walk: self.crosswalk.walk, blinking: self.crosswalk.blinking },
+        }
+    }
+
+    pub fn stoplight_fsm(&self) -> StoplightFsm {
+        let mut fsm =
+            StoplightFsm::with_timing(self.stoplight.initial, self.stoplight_timing()).with_approach(self.stoplight.approach);
+        if let Some(rail) = &self.stoplight.rail {
+            fsm = fsm.with_rail_crossing(rail.clone());
+        }
+        if let Some(transit) = self.stoplight.transit {
+            fsm = fsm.with_transit_priority(transit);
+        }
+        fsm
+    }
+
+    pub fn crosswalk_fsm(&self) -> CrosswalkFsm {
+        CrosswalkFsm::with_timing(self.crosswalk.initial, self.crosswalk_timing()).with_flash_display(self.crosswalk.during_flash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_config() {
+        let config = Config::from_toml_str(
+            r#"
+            tick_ms = 250
+            ticks = 100
+
+            [stoplight]
+            initial = "Green"
+            red = 8
+            yellow = 2
+
+            [buttons]
+            at = [3, 40]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.tick_period(), Duration::from_millis(250));
+        assert_eq!(
+            config.stoplight_timing(),
+            StoplightTiming { red: 8, green: StoplightFsm::GREEN_DURATION, yellow: 2, ..StoplightTiming::default() }
+        );
+        assert_eq!(config.stoplight.initial, StoplightState::Green);
+        assert_eq!(config.crosswalk_timing(), CrosswalkTiming::default());
+        assert!(config.buttons.pressed_at(40));
+        assert!(!config.buttons.pressed_at(4));
+    }
+
+    #[test]
+    fn test_default_config_file_matches_defaults() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config/default.toml");
+        assert_eq!(Config::load(path).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_json_config() {
+        let config =
+            Config::from_json_str(r#"{"ticks": 10, "crosswalk": {"walk": 2, "during_flash": "Dark"}, "buttons": "never"}"#)
+                .unwrap();
+        assert_eq!(config.ticks, 10);
+        assert_eq!(config.crosswalk.walk, 2);
+        assert_eq!(config.crosswalk.during_flash, FlashDisplay::Dark);
+        assert_eq!(config.buttons, ButtonSchedule::Never);
+        assert_eq!(Config::from_json_str("{}").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_invalid_timings_are_rejected() {
+        let err = Config::from_toml_str("[stoplight]\nyellow = 0").unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.yellow: must be at least 1 tick");
+
+        let err = Config::from_toml_str("[crosswalk]\nwalk = 6").unwrap_err();
+        assert_eq!(err.to_string(), "invalid crosswalk.walk: 6 ticks is longer than stoplight.red (5 ticks)");
+
+        let err = Config::from_toml_str("tick_ms = 0").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "tick_ms", .. }));
+
+        let err = Config::from_toml_str("[stoplight]\ninitial = \"FlashingRed\"").unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.initial: must be Red, Green or Yellow");
+
+        let err = Config::from_toml_str("ticks = 10\n[buttons]\nat = [10]").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "buttons.at", .. }));
+
+        let toml = "[stoplight]\nred = 4294967295\n[crosswalk]\nwalk = 4294967295\nblinking = 2";
+        let err = Config::from_toml_str(toml).unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.red: must be at most 3600 s");
+    }
+
+    #[test]
+    fn test_timings_longer_than_an_hour_are_rejected() {
+        // 3601 one-second ticks for each field in turn
+        for (table, key, field) in [
+            ("stoplight", "red", "stoplight.red"),
+            ("stoplight", "green", "stoplight.green"),
+            ("stoplight", "yellow", "stoplight.yellow"),
+            ("stoplight", "red_clearance", "stoplight.red_clearance"),
+            ("stoplight", "red_revert", "stoplight.red_revert"),
+            ("stoplight.actuated", "max_green", "stoplight.actuated.max_green"),
+            ("stoplight.actuated", "passage", "stoplight.actuated.passage"),
+            ("stoplight.rail", "track_clearance", "stoplight.rail.track_clearance"),
+            ("stoplight.transit", "max_extension", "stoplight.transit.max_extension"),
+            ("stoplight.transit", "max_early", "stoplight.transit.max_early"),
+            ("stoplight.transit", "lockout", "stoplight.transit.lockout"),
+            ("crosswalk", "walk", "crosswalk.walk"),
+            ("crosswalk", "blinking", "crosswalk.blinking"),
+            ("plans.peak", "red", "plans.red"),
+            ("plans.peak", "green", "plans.green"),
+            ("plans.peak", "yellow", "plans.yellow"),
+            ("plans.peak", "red_clearance", "plans.red_clearance"),
+            ("plans.peak", "red_revert", "plans.red_revert"),
+        ] {
+            let toml = format!("tick_ms = 1000\n[{}]\n{} = 3601", table, key);
+            let err = Config::from_toml_str(&toml).unwrap_err();
+            assert!(matches!(err, ConfigError::Invalid { field: f, .. } if f == field), "{}: {}", field, err);
+        }
+
+        // An hour is allowed, and the bound scales with tick_ms
+        Config::from_toml_str("tick_ms = 1000\n[stoplight]\nred_clearance = 3600").unwrap();
+        let err = Config::from_toml_str("tick_ms = 100\n[stoplight]\nred_clearance = 36001").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "stoplight.red_clearance", .. }));
+    }
+
+    #[test]
+    fn test_crosswalk_geometry_extends_red() {
+        let config = Config::from_toml_str("tick_ms = 1000\n[crosswalk.geometry]\nlength_ft = 48").unwrap();
+        assert_eq!(config.crosswalk_timing(), CrosswalkTiming { walk: 7, blinking: 14 });
+        assert_eq!(config.stoplight_timing().red, 21);
+
+        // A long enough Red is kept
+        let config = Config::from_toml_str("[stoplight]\nred = 30\n[crosswalk.geometry]\nlength_ft = 48").unwrap();
+        assert_eq!(config.stoplight_timing().red, 30);
+
+        let err = Config::from_toml_str("[crosswalk.geometry]\nwalking_speed_ft_s = 3.0").unwrap_err();
+        assert_eq!(err.to_string(), "invalid crosswalk.geometry.length_ft: must be greater than 0");
+
+        // Too long to cross, whether by length or by speed
+        for geometry in ["length_ft = 1e20", "length_ft = 48\nwalking_speed_ft_s = 1e-300"] {
+            let err = Config::from_toml_str(&format!("[crosswalk.geometry]\n{}", geometry)).unwrap_err();
+            assert_eq!(err.to_string(), "invalid crosswalk.geometry.length_ft: takes longer than 3600 s to cross at walking_speed_ft_s");
+        }
+        let err = Config::from_toml_str("[crosswalk.geometry]\nlength_ft = 48\nmin_walk_s = 1e20").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "crosswalk.geometry.min_walk_s", .. }));
+        // And the timing saturates rather than panics
+        let timing = CrosswalkGeometry::new(1e20).timing(Duration::from_millis(1));
+        assert_eq!(timing.interval(), u32::MAX);
+    }
+
+    #[test]
+    fn test_actuated_stoplight() {
+        let config = Config::from_toml_str(
+            "[stoplight]\ngreen = 3\n[stoplight.actuated]\nmax_green = 12\nrest = \"Green\"\n[detectors]\nevery = 2",
+        )
+        .unwrap();
+        let actuation = Actuation { max_green: 12, passage: 2, rest: crate::stoplight::RestIn::Green };
+        assert_eq!(config.stoplight_timing().actuation, Some(actuation));
+        assert!(config.timer_options().detectors.pressed_at(1));
+
+        let err = Config::from_toml_str("[stoplight.actuated]\nmax_green = 3").unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.actuated.max_green: 3 ticks is shorter than the minimum stoplight.green (4 ticks)");
+        let err = Config::from_toml_str("[detectors]\nevery = 0").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "detectors.every", .. }));
+    }
+
+    #[test]
+    fn test_preempt_runs() {
+        let config = Config::from_toml_str(
+            "ticks = 40\n[stoplight]\napproach = 3\n[[preempt]]\napproach = 3\nat = 4\nclear = 9\n[[preempt]]\napproach = 1\npriority = 2\nat = 20\nclear = 30",
+        )
+        .unwrap();
+        assert_eq!(config.stoplight_fsm().approach(), 3);
+        assert_eq!(config.preempt[1].request(), PreemptRequest { approach: 1, priority: 2 });
+
+        let err = Config::from_toml_str("[[preempt]]\napproach = 1\nat = 5\nclear = 5").unwrap_err();
+        assert_eq!(err.to_string(), "invalid preempt.clear: must come after preempt.at");
+    }
+
+    #[test]
+    fn test_rail_crossing_and_trains() {
+        let config = Config::from_toml_str(
+            "ticks = 60\n[stoplight]\napproach = 2\n[stoplight.rail]\ntrack_approach = 1\ndwell_approaches = [2]\n[[trains]]\ngate_down = 10\ngate_up = 40",
+        )
+        .unwrap();
+        assert_eq!(config.stoplight.rail.as_ref().map(|rail| rail.track_clearance), Some(5));
+        assert_eq!(config.timer_options().trains, vec![TrainRun { gate_down: 10, gate_up: 40 }]);
+
+        let err = Config::from_toml_str("[stoplight.rail]\ntrack_approach = 2\ndwell_approaches = [2, 3]").unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.rail.dwell_approaches: must not include the track approach");
+        let err = Config::from_toml_str("[[trains]]\ngate_down = 5\ngate_up = 3").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "trains.gate_up", .. }));
+    }
+
+    #[test]
+    fn test_plans_and_schedule() {
+        let config = Config::from_toml_str(
+            r#"
+            [stoplight]
+            green = 5
+            [plans.am_peak]
+            green = 9
+            [plans.night]
+            flash = "Yellow"
+            [schedule]
+            start = "2024-07-04T06:00"
+            holidays = ["2024-07-04"]
+            [[schedule.entries]]
+            days = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            at = "06:30"
+            plan = "am_peak"
+            [[schedule.entries]]
+            days = ["Holiday"]
+            at = "00:00"
+            plan = "night"
+            "#,
+        )
+        .unwrap();
+        let plans = config.timing_plans();
+        assert_eq!((plans[0].name.as_str(), plans[0].timing.green, plans[0].timing.red), ("am_peak", 9, 5));
+        assert_eq!(plans[1].flash, Some(FlashMode::Yellow));
+        let scheduler = config.timer_options().schedule.unwrap();
+        assert_eq!(scheduler.plan_at(Duration::from_secs(3600)).map(|plan| plan.name.as_str()), Some("night"));
+
+        let err = Config::from_toml_str("[[schedule.entries]]\nat = \"07:00\"\nplan = \"pm_peak\"").unwrap_err();
+        assert_eq!(err.to_string(), "invalid schedule.entries.plan: no plan named \"pm_peak\"");
+        assert!(matches!(Config::from_toml_str("[schedule]\nstart = \"tomorrow\""), Err(ConfigError::Parse(_))));
+        let err = Config::from_toml_str("[plans.short]\nred = 2").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "plans.red", .. }));
+    }
+
+    #[test]
+    fn test_coordination_splits() {
+        let config = Config::from_toml_str(
+            r#"
+            tick_ms = 500
+            [stoplight]
+            yellow = 2
+            red_clearance = 1
+            [stoplight.coordination]
+            cycle_s = 30
+            offset_s = 5
+            split = { percent = 40 }
+            [plans.evening]
+            coordination = { cycle_s = 40, offset_s = 35, split = { seconds = 12 }, transition = "Dwell" }
+            "#,
+        )
+        .unwrap();
+        let timing = config.stoplight_timing();
+        assert_eq!((timing.green, timing.red), (21, 37)); // 24 tick split in a 60 tick cycle
+        assert_eq!(timing.coordination.map(|c| (c.cycle, c.offset)), Some((60, 10)));
+        let evening = config.timing_plans()[0].timing;
+        assert_eq!((evening.green, evening.red, evening.coordination.map(|c| c.offset)), (21, 57, Some(70)));
+
+        let err = Config::from_toml_str("[stoplight.coordination]\ncycle_s = 10\nsplit = { seconds = 9 }").unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.coordination: Red of 1 ticks is shorter than walk + blinking (5 ticks)");
+        let err = Config::from_toml_str("[stoplight.coordination]\ncycle_s = 0\nsplit = { percent = 50 }").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "stoplight.coordination", .. }));
+        let err = Config::from_toml_str("[stoplight.coordination]\ncycle_s = 1e12\nsplit = { percent = 50 }").unwrap_err();
+        assert_eq!(err.to_string(), "invalid stoplight.coordination: cycle_s must be at most 3600 s");
+    }
+
+    #[test]
+    fn test_transit_priority() {
+        let config =
+            Config::from_toml_str("ticks = 40\n[stoplight.transit]\nlockout = 12\n[buses]\nat = [3, 30]").unwrap();
+        assert_eq!(config.stoplight.transit, Some(TransitPriority { lockout: 12, ..TransitPriority::default() }));
+        assert!(config.timer_options().buses.pressed_at(30));
+        let err = Config::from_toml_str("[buses]\nat = [99]").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "buses.at", .. }));
+    }
+
+    #[test]
+    fn test_parse_errors_are_reported() {
+        assert!(matches!(Config::from_toml_str("[stoplight]\nred = \"five\""), Err(ConfigError::Parse(_))));
+        assert!(matches!(Config::from_toml_str("tick_length = 5"), Err(ConfigError::Parse(_))));
+        assert!(matches!(Config::from_json_str(r#"{"stoplight": {"initial": "Blue"}}"#), Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn test_button_schedule_every() {
+        let schedule = ButtonSchedule::Every(5);
+        let pressed: Vec<u32> = (0..15).filter(|&t| schedule.pressed_at(t)).collect();
+        assert_eq!(pressed, vec![4, 9, 14]);
+    }
+
+    #[test]
+    fn test_button_schedule_random_is_reproducible() {
+        let config = Config::from_toml_str("ticks = 1000\n[buttons.random]\nprobability = 0.25\nseed = 42").unwrap();
+        let pressed: Vec<u32> = (0..1000).filter(|&t| config.buttons.pressed_at(t)).collect();
+        let again: Vec<u32> = (0..1000).filter(|&t| config.buttons.pressed_at(t)).collect();
+        assert_eq!(pressed, again);
+        assert!((200..300).contains(&pressed.len()), "{} presses", pressed.len());
+
+        let other = ButtonSchedule::Random { probability: 0.25, seed: 43 };
+        assert_ne!(pressed, (0..1000).filter(|&t| other.pressed_at(t)).collect::<Vec<u32>>());
+    }
+}
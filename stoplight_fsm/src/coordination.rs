@@ -0,0 +1,178 @@
+// Coordinated operation along a corridor: every controller runs the same
+// cycle length, counted from a shared master reference at tick 0, and starts
+// its green `offset` ticks into the master cycle.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Part of the cycle, in seconds or as a percentage of the cycle length.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    Seconds(f64),
+    Percent(f64),
+}
+
+impl Split {
+    pub fn seconds(self, cycle_s: f64) -> f64 {
+        match self {
+            Split::Seconds(seconds) => seconds,
+            Split::Percent(percent) => cycle_s * percent / 100.0,
+        }
+    }
+}
+
+/// How a controller that is out of step gets back to its offset.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum TransitionMode {
+    /// Shorten or lengthen the coordinated green, whichever is shorter, by at
+    /// most a quarter of it per cycle.
+    #[default]
+    Shortway,
+    /// Hold the coordinated green until the controller is back in step,
+    /// within one cycle.
+    Dwell,
+}
+
+/// Coordination settings as written in a timing plan.
+///
+/// `split` is this head's phase: its green, yellow and all-red. The rest of
+/// the cycle is Red.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoordinationConfig {
+    pub cycle_s: f64,
+    #[serde(default)]
+    pub offset_s: f64,
+    pub split: Split,
+    #[serde(default)]
+    pub transition: TransitionMode,
+}
+
+impl CoordinationConfig {
+    /// Cycle, offset and coordinated green in ticks of `tick_period`, given
+    /// the head's yellow and all-red. None when the split leaves no green
+    /// or no Red.
+    pub fn to_ticks(&self, tick_period: Duration, yellow: u32, red_clearance: u32) -> Option<(Coordination, u32)> {
+        let ticks = |seconds: f64| (seconds / tick_period.as_secs_f64()).round() as u32;
+        let cycle = ticks(self.cycle_s);
+        let split = ticks(self.split.seconds(self.cycle_s));
+        let green = split.checked_sub(yellow.checked_add(red_clearance)?).filter(|&green| green > 0)?;
+        (cycle > split).then(|| {
+            (Coordination { cycle, offset: ticks(self.offset_s) % cycle, transition: self.transition }, green)
+        })
+    }
+}
+
+/// Coordination in ticks, as run by a stoplight.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Coordination {
+    pub cycle: u32,
+    pub offset: u32, // Start of green in the master cycle
+    pub transition: TransitionMode,
+}
+
+impl Coordination {
+    /// Position in this controller's own cycle at master tick `master`, 0
+    /// being the start of green.
+    pub fn position(&self, master: u32) -> u32 {
+        (master % self.cycle + self.cycle - self.offset) % self.cycle
+    }
+
+    /// Length of a coordinated green of `green` ticks starting at cycle
+    /// position `error` (0 when in step), with the Red that follows kept at
+    /// its usual length.
+    pub fn corrected_green(&self, green: u32, error: u32) -> u32 {
+        let early = self.cycle - error; // Ticks until the next in-step start
+        match self.transition {
+            _ if error == 0 => green,
+            TransitionMode::Dwell => green + early,
+            TransitionMode::Shortway => {
+                let step = (green / 4).max(1);
+                if error <= self.cycle / 2 {
+                    green - error.min(step).min(green - 1)
+                } else {
+                    green + early.min(step)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crosswalk::PedestrianCall;
+    use crate::fsm::StateMachine;
+    use crate::stoplight::{PreemptRequest, StoplightEvent, StoplightFsm, StoplightState, StoplightTiming};
+
+    fn coordinated(offset: u32, transition: TransitionMode) -> StoplightFsm {
+        let coordination = Coordination { cycle: 12, offset, transition };
+        let timing =
+            StoplightTiming { red: 7, green: 4, yellow: 1, coordination: Some(coordination), ..StoplightTiming::default() };
+        StoplightFsm::with_timing(StoplightState::Red, timing)
+    }
+
+    // Master ticks at which the head turned Green
+    fn green_starts(fsm: &mut StoplightFsm, ticks: u32) -> Vec<u32> {
+        (1..=ticks)
+            .filter(|_| {
+                let transition = fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Idle);
+                transition.is_some_and(|t| t.to == StoplightState::Green)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_and_offset_in_ticks() {
+        let config = CoordinationConfig {
+            cycle_s: 60.0,
+            offset_s: 75.0,
+            split: Split::Percent(40.0),
+            transition: TransitionMode::Dwell,
+        };
+        let (coordination, green) = config.to_ticks(Duration::from_secs(2), 2, 1).unwrap();
+        assert_eq!((coordination.cycle, coordination.offset, green), (30, 8, 9));
+        let seconds = CoordinationConfig { split: Split::Seconds(6.0), ..config };
+        assert_eq!(seconds.to_ticks(Duration::from_secs(2), 2, 1), None);
+        assert_eq!(config.to_ticks(Duration::from_secs(2), u32::MAX, 1), None);
+    }
+
+    #[test]
+    fn test_shortway_and_dwell_corrections() {
+        let shortway = Coordination { cycle: 60, offset: 0, transition: TransitionMode::Shortway };
+        assert_eq!(shortway.corrected_green(20, 0), 20);
+        assert_eq!(shortway.corrected_green(20, 3), 17); // Late: shorten
+        assert_eq!(shortway.corrected_green(20, 10), 15); // By at most a quarter
+        assert_eq!(shortway.corrected_green(20, 57), 23); // Early: lengthen
+        let dwell = Coordination { transition: TransitionMode::Dwell, ..shortway };
+        assert_eq!(dwell.corrected_green(20, 10), 70);
+    }
+
+    #[test]
+    fn test_corridor_settles_on_offsets() {
+        // Three controllers of a corridor, started together out of step
+        for transition in [TransitionMode::Shortway, TransitionMode::Dwell] {
+            let mut corridor: Vec<_> = [0, 4, 8].map(|offset| coordinated(offset, transition)).into();
+            let settled: Vec<Vec<u32>> = corridor
+                .iter_mut()
+                .map(|fsm| green_starts(fsm, 120).into_iter().filter(|&tick| tick > 72).collect())
+                .collect();
+            assert_eq!(settled, vec![vec![84, 96, 108, 120], vec![76, 88, 100, 112], vec![80, 92, 104, 116]]);
+        }
+    }
+
+    #[test]
+    fn test_back_in_step_after_preemption() {
+        let mut fsm = coordinated(0, TransitionMode::Shortway);
+        green_starts(&mut fsm, 30);
+        fsm.handle_event(StoplightEvent::Preempt(PreemptRequest { approach: 2, priority: 1 }), &PedestrianCall::Idle);
+        green_starts(&mut fsm, 5);
+        fsm.handle_event(StoplightEvent::PreemptClear(2), &PedestrianCall::Idle);
+        // Back on multiples of the cycle, counting the 35 ticks before
+        let starts = green_starts(&mut fsm, 120);
+        assert!(starts.iter().rev().take(3).all(|tick| (tick + 35) % 12 == 0), "{:?}", starts);
+        assert_eq!(fsm.sync_error(), Some(0));
+    }
+}
@@ -0,0 +1,592 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fsm::StateMachine;
+use crate::stoplight::StoplightState;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum CrosswalkState {
+    DontWalk,
+    Walk,
+    BlinkingDontWalk,
+    Dark, // Signal heads off while the stoplight is flashing
+}
+
+/// What the crosswalk shows while the stoplight is flashing.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum FlashDisplay {
+    Dark,
+    #[default]
+    DontWalk,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CrosswalkEvent {
+    TimerTick,
+    ButtonPress,
+    WalkGranted, // The stoplight is holding Red for the pending call
+    Preempt,     // Emergency preemption: clear the crossing, take no calls until PreemptClear
+    PreemptClear,
+    FailSafe,    // Show DontWalk and ignore the timer and button until Resume
+    Resume,
+    // StoplightIsRed and StoplightIsNotRed are removed as per requirement.
+    // This information will be conveyed via ToCrosswalk::StoplightState(StoplightState)
+}
+
+/// The crosswalk's demand for a pedestrian interval, as seen by the stoplight.
+///
+/// The stoplight holds Red from the moment it grants a waiting call until
+/// the crosswalk is back to Idle, so Walk is never cut short.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum PedestrianCall {
+    #[default]
+    Idle,
+    Waiting, // Button pressed, Walk not granted yet
+    Serving, // Walk or BlinkingDontWalk showing
+}
+
+// State durations in TimerTicks
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CrosswalkTiming {
+    pub walk: u32,
+    pub blinking: u32,
+}
+
+impl CrosswalkTiming {
+    // Walk plus BlinkingDontWalk, the Red the crossing needs
+    pub fn interval(&self) -> u32 {
+        self.walk.saturating_add(self.blinking)
+    }
+}
+
+impl Default for CrosswalkTiming {
+    fn default() -> Self {
+        CrosswalkTiming {
+            walk: CrosswalkFsm::WALK_DURATION,
+            blinking: CrosswalkFsm::BLINKING_DURATION,
+        }
+    }
+}
+
+/// Physical crossing, from which the pedestrian interval is timed as in
+/// MUTCD practice: Walk for `min_walk_s`, then BlinkingDontWalk long enough
+/// to cross the full length at `walking_speed_ft_s`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrosswalkGeometry {
+    pub length_ft: f64,
+    pub walking_speed_ft_s: f64,
+    pub min_walk_s: f64,
+}
+
+impl Default for CrosswalkGeometry {
+    fn default() -> Self {
+        CrosswalkGeometry {
+            length_ft: 0.0,
+            walking_speed_ft_s: CrosswalkGeometry::WALKING_SPEED_FT_S,
+            min_walk_s: CrosswalkGeometry::MIN_WALK_S,
+        }
+    }
+}
+
+impl CrosswalkGeometry {
+    pub const WALKING_SPEED_FT_S: f64 = 3.5;
+    pub const MIN_WALK_S: f64 = 7.0;
+    pub const MAX_INTERVAL_S: f64 = 3600.0; // Longest Walk or clearance a config may ask for
+
+    pub fn new(length_ft: f64) -> Self {
+        CrosswalkGeometry { length_ft, ..Self::default() }
+    }
+
+    // Saturates rather than panics on a length or speed validate would reject
+    pub fn clearance(&self) -> Duration {
+        Duration::try_from_secs_f64(self.length_ft / self.walking_speed_ft_s).unwrap_or(Duration::MAX)
+    }
+
+    // Durations rounded up to whole ticks, at least one tick each; the casts saturate
+    pub fn timing(&self, tick_period: Duration) -> CrosswalkTiming {
+        let ticks = |seconds: f64| ((seconds / tick_period.as_secs_f64()).ceil() as u32).max(1);
+        CrosswalkTiming { walk: ticks(self.min_walk_s), blinking: ticks(self.clearance().as_secs_f64()) }
+    }
+}
+
+pub struct CrosswalkFsm {
+    pub(crate) state: CrosswalkState,
+    pub(crate) timer_ticks_in_state: u32,
+    pub(crate) button_pressed_waiting_for_red: bool, // Flag to remember if button was pressed
+    forced: Option<&'static str>, // Why the last transition was forced by the stoplight, if it was
+    fail_safe: bool,
+    preempted: bool,
+    during_flash: FlashDisplay,
+    timing: CrosswalkTiming,
+}
+
+impl Default for CrosswalkFsm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CrosswalkFsm {
+    pub fn new() -> Self {
+        Self::with_timing(CrosswalkState::DontWalk, CrosswalkTiming::default())
+    }
+
+    pub fn with_timing(initial: CrosswalkState, timing: CrosswalkTiming) -> Self {
+        CrosswalkFsm {
+            state: initial,
+            timer_ticks_in_state: 0,
+            button_pressed_waiting_for_red: false,
+            forced: None,
+            fail_safe: false,
+            preempted: false,
+            during_flash: FlashDisplay::default(),
+            timing,
+        }
+    }
+
+    pub fn with_flash_display(mut self, during_flash: FlashDisplay) -> Self {
+        self.during_flash = during_flash;
+        self
+    }
+
+    /// A fresh machine with this one's timing and flash display, in DontWalk.
+    pub fn restarted(&self) -> Self {
+        CrosswalkFsm::with_timing(CrosswalkState::DontWalk, self.timing).with_flash_display(self.during_flash)
+    }
+
+    // Constants for state durations
+    pub const WALK_DURATION: u32 = 3; // How long "Walk" stays on
+    pub const BLINKING_DURATION: u32 = 2; // How long "DontWalk" blinks
+
+    pub fn ticks_in_state(&self) -> u32 {
+        self.timer_ticks_in_state
+    }
+
+    pub fn is_waiting_for_red(&self) -> bool {
+        self.button_pressed_waiting_for_red
+    }
+
+    pub fn timing(&self) -> &CrosswalkTiming {
+        &self.timing
+    }
+
+    pub fn is_fail_safe(&self) -> bool {
+        self.fail_safe
+    }
+
+    pub fn is_preempted(&self) -> bool {
+        self.preempted
+    }
+
+    pub fn pedestrian_call(&self) -> PedestrianCall {
+        match self.state {
+            CrosswalkState::Walk | CrosswalkState::BlinkingDontWalk => PedestrianCall::Serving,
+            _ if self.button_pressed_waiting_for_red => PedestrianCall::Waiting,
+            _ => PedestrianCall::Idle,
+        }
+    }
+}
+
+impl StateMachine for CrosswalkFsm {
+    type State = CrosswalkState;
+    type Event = CrosswalkEvent;
+    type Context = StoplightState;
+
+    fn name(&self) -> &str {
+        "Crosswalk"
+    }
+
+    fn state(&self) -> CrosswalkState {
+        self.state
+    }
+
+    fn next_state(&mut self, event: &CrosswalkEvent, stoplight_state: &StoplightState) -> CrosswalkState {
+        let stoplight_state = *stoplight_state;
+        let mut next_state = self.state;
+        self.forced = None;
+        // Tracked whatever else is going on, so a preemption cancelled by a
+        // flash or fail-safe still ends here
+        match event {
+            CrosswalkEvent::Preempt => {
+                self.preempted = true;
+                self.button_pressed_waiting_for_red = false;
+            }
+            CrosswalkEvent::PreemptClear => self.preempted = false,
+            _ => {}
+        }
+        if self.fail_safe && *event != CrosswalkEvent::Resume {
+            return self.state;
+        }
+        // No pedestrian service while the stoplight flashes
+        if stoplight_state.is_flashing() && *event != CrosswalkEvent::FailSafe {
+            self.button_pressed_waiting_for_red = false;
+            let display = match self.during_flash {
+                FlashDisplay::Dark => CrosswalkState::Dark,
+                FlashDisplay::DontWalk => CrosswalkState::DontWalk,
+            };
+            if self.state != display {
+                self.forced = Some("stoplight is flashing");
+            }
+            return display;
+        }
+        if self.state == CrosswalkState::Dark && *event != CrosswalkEvent::FailSafe {
+            self.forced = Some("stoplight stopped flashing");
+            return CrosswalkState::DontWalk;
+        }
+        match event {
+            CrosswalkEvent::FailSafe => {
+                self.fail_safe = true;
+                self.button_pressed_waiting_for_red = false;
+                next_state = CrosswalkState::DontWalk;
+            }
+            CrosswalkEvent::Resume => self.fail_safe = false,
+            CrosswalkEvent::TimerTick => {
+                self.timer_ticks_in_state += 1;
+                match self.state {
+                    CrosswalkState::Walk => {
+                        if stoplight_state != StoplightState::Red {
+                            next_state = CrosswalkState::DontWalk;
+                            self.forced = Some("stoplight is no longer Red");
+                            self.button_pressed_waiting_for_red = false; // Reset waiting flag
+                        } else if self.timer_ticks_in_state >= self.timing.walk {
+                            next_state = CrosswalkState::BlinkingDontWalk;
+                        }
+                    }
+                    CrosswalkState::BlinkingDontWalk => {
+                        if stoplight_state != StoplightState::Red {
+                            next_state = CrosswalkState::DontWalk;
+                            self.forced = Some("stoplight is no longer Red");
+                            self.button_pressed_waiting_for_red = false; // Reset waiting flag
+                        } else if self.timer_ticks_in_state >= self.timing.blinking {
+                            next_state = CrosswalkState::DontWalk;
+                        }
+                    }
+                    // Walk only starts on WalkGranted
+                    CrosswalkState::DontWalk => {}
+                    CrosswalkState::Dark => {} // Left above, once the stoplight is steady again
+                }
+            }
+            CrosswalkEvent::ButtonPress => {
+                if self.state == CrosswalkState::DontWalk && !self.preempted {
+                    // Even during Red: the stoplight may be about to leave it, so
+                    // the call waits until the stoplight grants it
+                    self.button_pressed_waiting_for_red = true;
+                }
+                // If already Walk or Blinking, button press is ignored or could reset timer (not implemented here)
+            }
+            CrosswalkEvent::Preempt => {
+                // Walk is cut short, but pedestrians already crossing get the full clearance
+                if self.state == CrosswalkState::Walk {
+                    next_state = CrosswalkState::BlinkingDontWalk;
+                    self.forced = Some("preempted");
+                }
+            }
+            CrosswalkEvent::PreemptClear => {}
+            CrosswalkEvent::WalkGranted => {
+                if self.button_pressed_waiting_for_red && stoplight_state == StoplightState::Red {
+                    next_state = CrosswalkState::Walk;
+                    self.button_pressed_waiting_for_red = false; // Reset flag
+                }
+            }
+
+	    /* 250603:DST obsolete events, see CrosswalkFSM::handle_event
+            CrosswalkEvent::StoplightIsRed => {
+                // This event is triggered by the main loop when stoplight turns red
+                if self.state == CrosswalkState::DontWalk && self.button_pressed_waiting_for_red {
+                    next_state = CrosswalkState::Walk;
+                    self.button_pressed_waiting_for_red = false;
+                }
+            }
+            CrosswalkEvent::StoplightIsNotRed => {
+                // If stoplight is no longer red, ensure crosswalk is not in Walk state
+                if self.state == CrosswalkState::Walk || self.state == CrosswalkState::BlinkingDontWalk {
+                    next_state = CrosswalkState::DontWalk;
+                    println!("Stoplight no longer red, forcing Crosswalk to DontWalk.");
+                }
+                 self.button_pressed_waiting_for_red = false; // Cancel any pending walk request
+            }
+	    */
+        }
+        next_state
+    }
+
+    fn enter(&mut self, state: CrosswalkState) {
+        self.state = state;
+        self.timer_ticks_in_state = 0; // Reset timer for new state
+    }
+
+    fn reason(&self) -> Option<&'static str> {
+        if self.forced.is_some() {
+            self.forced
+        } else if self.fail_safe {
+            Some("fail-safe latched")
+        } else {
+            None
+        }
+    }
+
+    fn on_context_change(&mut self, old_stoplight_state: &StoplightState, new_state: &StoplightState) -> Option<CrosswalkEvent> {
+        let old_stoplight_state = *old_stoplight_state;
+        let current_stoplight_state = *new_state;
+        if self.fail_safe {
+            return None;
+        }
+        if old_stoplight_state.is_flashing() != current_stoplight_state.is_flashing() {
+            return Some(CrosswalkEvent::TimerTick);
+        }
+
+        // If the light is no longer red and it was walking/blinking, we should
+        // re-evaluate its state. The CrosswalkFsm's TimerTick event is a good way
+        // to do this, as it forces DontWalk. A waiting call is left to WalkGranted.
+        if old_stoplight_state == StoplightState::Red && current_stoplight_state != StoplightState::Red && (self.state == CrosswalkState::Walk || self.state == CrosswalkState::BlinkingDontWalk)
+        {
+            return Some(CrosswalkEvent::TimerTick);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Button press answered by the stoplight, as crosswalk_thread sees it during Red
+    fn request_walk(fsm: &mut CrosswalkFsm) {
+        fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Red);
+        fsm.handle_event(CrosswalkEvent::WalkGranted, &StoplightState::Red);
+    }
+
+    #[test]
+    fn test_crosswalk_button_press_when_stoplight_red() {
+        let mut crosswalk_fsm = CrosswalkFsm::new();
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+
+        // Simulate button press when stoplight is Red; Walk waits for the grant
+        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Red);
+        assert_eq!(crosswalk_fsm.pedestrian_call(), PedestrianCall::Waiting);
+        crosswalk_fsm.handle_event(CrosswalkEvent::WalkGranted, &StoplightState::Red);
+        assert_eq!(crosswalk_fsm.pedestrian_call(), PedestrianCall::Serving);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk);
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+
+        // Tick through Walk state
+        for _ in 0..CrosswalkFsm::WALK_DURATION {
+            crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        }
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk);
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+
+        // Tick through BlinkingDontWalk state
+        for _ in 0..CrosswalkFsm::BLINKING_DURATION {
+            crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        }
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+        assert_eq!(crosswalk_fsm.pedestrian_call(), PedestrianCall::Idle);
+    }
+
+    #[test]
+    fn test_crosswalk_button_press_when_stoplight_green_then_red() {
+        let mut crosswalk_fsm = CrosswalkFsm::new();
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+
+        // Simulate button press when stoplight is Green
+        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Green);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk); // Should not change yet
+        assert!(crosswalk_fsm.button_pressed_waiting_for_red);
+
+        // Simulate some time passing, stoplight still Green
+        crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Green);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+        assert!(crosswalk_fsm.button_pressed_waiting_for_red);
+
+        // A grant that overtook the stoplight's change to Red is not acted on
+        crosswalk_fsm.handle_event(CrosswalkEvent::WalkGranted, &StoplightState::Green);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+
+        // Stoplight turns Red, then grants the call
+        crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+        crosswalk_fsm.handle_event(CrosswalkEvent::WalkGranted, &StoplightState::Red);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk); // Now it should change
+        assert!(!crosswalk_fsm.button_pressed_waiting_for_red);
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+    }
+
+    #[test]
+    fn test_crosswalk_forced_to_dont_walk_if_stoplight_not_red() {
+        let mut crosswalk_fsm = CrosswalkFsm::new();
+        // Make it Walk
+        request_walk(&mut crosswalk_fsm);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk);
+
+        // Simulate stoplight turning Green. Crosswalk should be forced to DontWalk.
+        // This is handled by passing the new stoplight state to the crosswalk's TimerTick,
+        // the StoplightIsNotRed event no longer exists.
+        crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Green);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+
+        // Test with BlinkingDontWalk state
+        request_walk(&mut crosswalk_fsm); // Go to Walk
+        for _ in 0..CrosswalkFsm::WALK_DURATION { // Go to Blinking
+            crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        }
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk);
+        crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Green);
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+    }
+
+    #[test]
+    fn test_crosswalk_button_press_ignored_if_not_dont_walk() {
+        let mut crosswalk_fsm = CrosswalkFsm::new();
+        request_walk(&mut crosswalk_fsm); // -> Walk
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk);
+        let current_ticks = crosswalk_fsm.timer_ticks_in_state;
+
+        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Red); // Press button again
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk); // State should not change
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, current_ticks); // Ticks should not reset
+
+        for _ in 0..CrosswalkFsm::WALK_DURATION {
+             crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        }
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk); // -> BlinkingDontWalk
+        let current_ticks_blinking = crosswalk_fsm.timer_ticks_in_state;
+
+        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Red); // Press button again
+        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk); // State should not change
+        assert_eq!(crosswalk_fsm.timer_ticks_in_state, current_ticks_blinking); // Ticks should not reset
+    }
+
+    #[test]
+    fn test_geometry_timing() {
+        // 48 ft at 3.5 ft/s is 13.7 s of clearance
+        let geometry = CrosswalkGeometry::new(48.0);
+        assert_eq!(geometry.timing(Duration::from_secs(1)), CrosswalkTiming { walk: 7, blinking: 14 });
+        assert_eq!(geometry.timing(Duration::from_millis(500)), CrosswalkTiming { walk: 14, blinking: 28 });
+
+        let slow = CrosswalkGeometry { walking_speed_ft_s: 3.0, min_walk_s: 4.0, ..geometry };
+        assert_eq!(slow.timing(Duration::from_secs(2)), CrosswalkTiming { walk: 2, blinking: 8 });
+    }
+
+    #[test]
+    fn test_crosswalk_fail_safe_ignores_button_until_resume() {
+        let mut fsm = CrosswalkFsm::new();
+        request_walk(&mut fsm);
+        let transition = fsm.handle_event(CrosswalkEvent::FailSafe, &StoplightState::Red).unwrap();
+        assert_eq!((transition.to, transition.reason), (CrosswalkState::DontWalk, Some("fail-safe latched")));
+
+        assert_eq!(fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Red), None);
+        assert_eq!(fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red), None);
+        assert!(!fsm.is_waiting_for_red());
+
+        fsm.handle_event(CrosswalkEvent::Resume, &StoplightState::Red);
+        request_walk(&mut fsm);
+        assert_eq!(fsm.state, CrosswalkState::Walk);
+    }
+
+    #[test]
+    fn test_crosswalk_during_stoplight_flash() {
+        let mut fsm = CrosswalkFsm::new().with_flash_display(FlashDisplay::Dark);
+        fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::Green);
+        assert_eq!(fsm.on_context_change(&StoplightState::Yellow, &StoplightState::FlashingRed), Some(CrosswalkEvent::TimerTick));
+        let transition = fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::FlashingRed).unwrap();
+        assert_eq!((transition.to, transition.reason), (CrosswalkState::Dark, Some("stoplight is flashing")));
+        assert!(!fsm.is_waiting_for_red());
+
+        // Buttons are not served while flashing
+        assert_eq!(fsm.handle_event(CrosswalkEvent::ButtonPress, &StoplightState::FlashingRed), None);
+        assert!(!fsm.is_waiting_for_red());
+
+        fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        assert_eq!(fsm.state, CrosswalkState::DontWalk);
+
+        // Steady DontWalk display: Walk is cut short like any other change from Red
+        let mut fsm = CrosswalkFsm::new();
+        request_walk(&mut fsm);
+        fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::FlashingYellow);
+        assert_eq!(fsm.state, CrosswalkState::DontWalk);
+    }
+
+    #[test]
+    fn test_crosswalk_cycle_prematurely_ends_if_light_changes() {
+        // Test Walk to DontWalk if light changes from Red
+        let mut fsm_walk_test = CrosswalkFsm::new();
+        // 1. Get to Walk state
+        request_walk(&mut fsm_walk_test);
+        assert_eq!(fsm_walk_test.state, CrosswalkState::Walk, "Test Walk: Should be in Walk state after button press with Red light");
+        assert_eq!(fsm_walk_test.timer_ticks_in_state, 0, "Test Walk: Timer should be 0 after transitioning to Walk");
+
+        // 2. Simulate one TimerTick with StoplightState::Red (assuming WALK_DURATION > 1)
+        // Ensure WALK_DURATION is suitable for this test logic. If WALK_DURATION is 1, this step might transition state.
+        if CrosswalkFsm::WALK_DURATION > 1 {
+            fsm_walk_test.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+            assert_eq!(fsm_walk_test.state, CrosswalkState::Walk, "Test Walk: Should still be in Walk state after 1 tick if duration > 1");
+            assert_eq!(fsm_walk_test.timer_ticks_in_state, 1, "Test Walk: Timer should be 1");
+        } else {
+            // If WALK_DURATION is 1, after one tick it will be BlinkingDontWalk.
+            // This part of the test needs adjustment if WALK_DURATION is 1.
+            // For now, proceeding with assumption WALK_DURATION > 1 based on typical values (e.g., 3).
+            // If it transitions, the next step's premise is wrong.
+            println!("Note: WALK_DURATION is 1, Walk state will transition immediately to BlinkingDontWalk after 1 tick.");
+        }
+
+
+        // 3. Simulate a TimerTick with StoplightState::Green. Should transition to DontWalk.
+        fsm_walk_test.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Green);
+        assert_eq!(fsm_walk_test.state, CrosswalkState::DontWalk, "Test Walk: Should transition to DontWalk if light turns Green");
+        assert_eq!(fsm_walk_test.timer_ticks_in_state, 0, "Test Walk: Timer should reset after forced transition to DontWalk");
+
+        // Test BlinkingDontWalk to DontWalk if light changes from Red
+        let mut fsm_blink_test = CrosswalkFsm::new();
+        // 1. Get to Walk state first
+        request_walk(&mut fsm_blink_test);
+        assert_eq!(fsm_blink_test.state, CrosswalkState::Walk, "Test Blink: Initial transition to Walk failed");
+
+        // 2. Tick through Walk state to reach BlinkingDontWalk
+        for _ in 0..CrosswalkFsm::WALK_DURATION {
+            fsm_blink_test.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        }
+        assert_eq!(fsm_blink_test.state, CrosswalkState::BlinkingDontWalk, "Test Blink: Should be in BlinkingDontWalk after WALK_DURATION ticks");
+        assert_eq!(fsm_blink_test.timer_ticks_in_state, 0, "Test Blink: Timer should reset after transitioning to BlinkingDontWalk");
+
+        // 3. Simulate one TimerTick with StoplightState::Red (assuming BLINKING_DURATION > 1)
+        if CrosswalkFsm::BLINKING_DURATION > 1 {
+            fsm_blink_test.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+            assert_eq!(fsm_blink_test.state, CrosswalkState::BlinkingDontWalk, "Test Blink: Should still be in BlinkingDontWalk after 1 tick if duration > 1");
+            assert_eq!(fsm_blink_test.timer_ticks_in_state, 1, "Test Blink: Timer should be 1 for BlinkingDontWalk");
+        } else {
+            println!("Note: BLINKING_DURATION is 1, BlinkingDontWalk will transition immediately to DontWalk after 1 tick.");
+        }
+
+
+        // 4. Simulate a TimerTick with StoplightState::Green. Should transition to DontWalk.
+        fsm_blink_test.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Green);
+        assert_eq!(fsm_blink_test.state, CrosswalkState::DontWalk, "Test Blink: Should transition to DontWalk if light turns Green during BlinkingDontWalk");
+        assert_eq!(fsm_blink_test.timer_ticks_in_state, 0, "Test Blink: Timer should reset after forced transition to DontWalk from BlinkingDontWalk");
+    }
+
+    #[test]
+    fn test_preemption_clears_through_blinking() {
+        let mut fsm = CrosswalkFsm::new();
+        request_walk(&mut fsm);
+        let transition = fsm.handle_event(CrosswalkEvent::Preempt, &StoplightState::Red).unwrap();
+        assert_eq!((transition.to, transition.reason), (CrosswalkState::BlinkingDontWalk, Some("preempted")));
+        assert_eq!(fsm.pedestrian_call(), PedestrianCall::Serving);
+        for _ in 0..CrosswalkFsm::BLINKING_DURATION {
+            fsm.handle_event(CrosswalkEvent::TimerTick, &StoplightState::Red);
+        }
+        assert_eq!(fsm.state, CrosswalkState::DontWalk);
+
+        // No calls are taken until the preemption is over
+        request_walk(&mut fsm);
+        assert_eq!((fsm.state, fsm.pedestrian_call()), (CrosswalkState::DontWalk, PedestrianCall::Idle));
+        fsm.handle_event(CrosswalkEvent::PreemptClear, &StoplightState::Red);
+        request_walk(&mut fsm);
+        assert_eq!(fsm.state, CrosswalkState::Walk);
+    }
+}
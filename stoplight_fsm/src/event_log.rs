@@ -0,0 +1,248 @@
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::fsm::Transition;
+use crate::messages::Recorded;
+
+/// One state change of one machine.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransitionRecord {
+    pub timestamp_ms: u64, // Clock time of the transition
+    pub tick: u64,         // Timer ticks the machine had handled
+    pub machine: String,
+    pub from: String,
+    pub to: String,
+    pub event: String, // Message that triggered the transition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl TransitionRecord {
+    pub fn timestamp(&self) -> Duration {
+        Duration::from_millis(self.timestamp_ms)
+    }
+}
+
+/// Destination for transition records.
+pub trait TransitionSink: Send + Sync {
+    fn record(&self, record: &TransitionRecord);
+}
+
+/// Human readable lines, one per transition.
+pub struct TextSink<W: Write + Send> {
+    out: Mutex<W>,
+}
+
+impl TextSink<io::Stdout> {
+    pub fn stdout() -> Self {
+        TextSink::new(io::stdout())
+    }
+}
+
+impl<W: Write + Send> TextSink<W> {
+    pub fn new(out: W) -> Self {
+        TextSink { out: Mutex::new(out) }
+    }
+}
+
+impl<W: Write + Send> TransitionSink for TextSink<W> {
+    fn record(&self, r: &TransitionRecord) {
+        let mut line = format!(
+            "[{:>9.3}s] tick {:>4} {} changing from {} to {} on {}",
+            r.timestamp().as_secs_f64(),
+            r.tick,
+            r.machine,
+            r.from,
+            r.to,
+            r.event
+        );
+        if let Some(reason) = &r.reason {
+            line.push_str(&format!(" because {}", reason));
+        }
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{}", line) {
+            eprintln!("Event log: failed to write transition: {}", e);
+        }
+    }
+}
+
+/// One JSON object per line.
+pub struct JsonLinesSink<W: Write + Send> {
+    out: Mutex<W>,
+}
+
+impl JsonLinesSink<io::Stdout> {
+    pub fn stdout() -> Self {
+        JsonLinesSink::new(io::stdout())
+    }
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(out: W) -> Self {
+        JsonLinesSink { out: Mutex::new(out) }
+    }
+}
+
+impl<W: Write + Send> TransitionSink for JsonLinesSink<W> {
+    fn record(&self, record: &TransitionRecord) {
+        let line = serde_json::to_string(record).expect("transition records serialize");
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{}", line) {
+            eprintln!("Event log: failed to write transition: {}", e);
+        }
+    }
+}
+
+/// Keeps every record in memory, for tests and replay comparisons.
+#[derive(Default)]
+pub struct MemorySink {
+    records: Mutex<Vec<TransitionRecord>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<TransitionRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl TransitionSink for MemorySink {
+    fn record(&self, record: &TransitionRecord) {
+        self.records.lock().unwrap().push(record.clone());
+    }
+}
+
+/// Receives every message the machines handle, e.g. [`crate::Recorder`].
+pub trait MessageSink: Send + Sync {
+    fn record(&self, timestamp_ms: u64, tick: u64, message: Recorded);
+}
+
+/// Clock plus the sinks every controller thread reports to, and optionally
+/// a [`MessageSink`] capturing the messages the machines handle.
+#[derive(Clone)]
+pub struct EventLog {
+    clock: Arc<dyn Clock>,
+    sinks: Vec<Arc<dyn TransitionSink>>,
+    recorder: Option<Arc<dyn MessageSink>>,
+    // Keeps recorded messages and transition records in one global order
+    order: Arc<Mutex<()>>,
+}
+
+impl EventLog {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        EventLog { clock, sinks: Vec::new(), recorder: None, order: Arc::new(Mutex::new(())) }
+    }
+
+    pub fn with_sink(mut self, sink: Arc<dyn TransitionSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn with_recorder(mut self, recorder: Arc<dyn MessageSink>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn transition<S: Debug>(&self, machine: &str, tick: u64, transition: &Transition<S>, event: String) {
+        self.step(machine, tick, None, Some(transition), event);
+    }
+
+    // Report one driver step: the message handled (for the recorder) and the
+    // transition it caused, both stamped with the same clock reading.
+    pub fn step<S: Debug>(
+        &self,
+        machine: &str,
+        tick: u64,
+        message: Option<Recorded>,
+        transition: Option<&Transition<S>>,
+        event: String,
+    ) {
+        // Poisoned when a sink panicked, yet it only keeps the order
+        let _order = self.order.lock().unwrap_or_else(PoisonError::into_inner);
+        let timestamp_ms = self.clock.now().as_millis() as u64;
+        if let (Some(recorder), Some(message)) = (&self.recorder, message) {
+            recorder.record(timestamp_ms, tick, message);
+        }
+        let Some(transition) = transition else { return };
+        let record = TransitionRecord {
+            timestamp_ms,
+            tick,
+            machine: machine.to_string(),
+            from: format!("{:?}", transition.from),
+            to: format!("{:?}", transition.to),
+            event,
+            reason: transition.reason.map(String::from),
+        };
+        for sink in &self.sinks {
+            sink.record(&record);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::stoplight::StoplightState;
+
+    /// In-memory writer the test sinks and recorders can share.
+    #[derive(Clone, Default)]
+    pub(crate) struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        pub(crate) fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_event_log_fans_out_to_sinks() {
+        let clock = Arc::new(VirtualClock::new());
+        clock.advance(Duration::from_millis(4500));
+        let text = SharedBuf::default();
+        let json = SharedBuf::default();
+        let memory = Arc::new(MemorySink::new());
+        let log = EventLog::new(clock)
+            .with_sink(Arc::new(TextSink::new(text.clone())))
+            .with_sink(Arc::new(JsonLinesSink::new(json.clone())))
+            .with_sink(memory.clone());
+
+        let transition = Transition { from: StoplightState::Red, to: StoplightState::Green, reason: None };
+        log.transition("Stoplight", 5, &transition, "TimerTick".into());
+        let forced = Transition { from: "Walk", to: "DontWalk", reason: Some("stoplight is no longer Red") };
+        log.transition("Crosswalk", 9, &forced, "TimerTick".into());
+
+        assert_eq!(
+            text.text(),
+            "[    4.500s] tick    5 Stoplight changing from Red to Green on TimerTick\n\
+             [    4.500s] tick    9 Crosswalk changing from \"Walk\" to \"DontWalk\" on TimerTick because stoplight is no longer Red\n"
+        );
+        let lines: Vec<TransitionRecord> =
+            json.text().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines, memory.records());
+        assert_eq!(lines[0].timestamp_ms, 4500);
+        assert_eq!(lines[1].reason.as_deref(), Some("stoplight is no longer Red"));
+    }
+}
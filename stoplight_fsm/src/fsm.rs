@@ -0,0 +1,277 @@
+use std::fmt::{self, Debug};
+use std::sync::mpsc;
+
+/// A change of state reported by a [`StateMachine`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Transition<S> {
+    pub from: S,
+    pub to: S,
+    pub reason: Option<&'static str>, // Set when the transition was forced rather than timed
+}
+
+/// Common shape of the signal state machines.
+///
+/// A machine computes its next state from an event and a read-only context
+/// (for the crosswalk, the current stoplight state). The provided
+/// `handle_event` applies the "compute next state, enter it, report it"
+/// pattern so implementors only describe their transitions.
+pub trait StateMachine {
+    type State: Copy + PartialEq + Debug;
+    type Event: Debug;
+    type Context;
+
+    // Name used when reporting transitions, e.g. "Stoplight"
+    fn name(&self) -> &str;
+
+    fn state(&self) -> Self::State;
+
+    // Compute the state to move to. Bookkeeping such as tick counters or
+    // latched requests may be updated here; return the current state to stay.
+    fn next_state(&mut self, event: &Self::Event, ctx: &Self::Context) -> Self::State;
+
+    // Make `state` current and reset the time spent in it.
+    fn enter(&mut self, state: Self::State);
+
+    // Why the state computed by the last `next_state` call was chosen, if
+    // it needs explaining (e.g. the crosswalk cut short by the stoplight).
+    fn reason(&self) -> Option<&'static str> {
+        None
+    }
+
+    // Transition hook, called after `enter`.
+    fn on_transition(&mut self, _transition: &Transition<Self::State>, _event: &Self::Event, _ctx: &Self::Context) {}
+
+    // Called by the driver when the context changes. Returning an event makes
+    // the driver re-evaluate the machine with it.
+    fn on_context_change(&mut self, _old: &Self::Context, _new: &Self::Context) -> Option<Self::Event> {
+        None
+    }
+
+    fn handle_event(&mut self, event: Self::Event, ctx: &Self::Context) -> Option<Transition<Self::State>> {
+        let from = self.state();
+        let to = self.next_state(&event, ctx);
+        if from == to {
+            return None;
+        }
+        let transition = Transition { from, to, reason: self.reason() };
+        self.enter(to);
+        self.on_transition(&transition, &event, ctx);
+        Some(transition)
+    }
+}
+
+/// What a flush marker does once every input before it has been handled:
+/// wake the side waiting for the flush, or pass the flush on to another
+/// machine.
+pub struct Ack(Box<dyn FnOnce() + Send>);
+
+impl Ack {
+    pub fn new(then: impl FnOnce() + Send + 'static) -> Self {
+        Ack(Box::new(then))
+    }
+
+    pub fn done(self) {
+        (self.0)()
+    }
+}
+
+impl From<mpsc::Sender<()>> for Ack {
+    // The waiting side may have given up
+    fn from(tx: mpsc::Sender<()>) -> Self {
+        Ack::new(move || {
+            let _ = tx.send(());
+        })
+    }
+}
+
+impl Debug for Ack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Ack")
+    }
+}
+
+/// Input understood by [`run_machine`]. Thread message enums convert into it.
+#[derive(Debug)]
+pub enum Input<E, C> {
+    Event(E),
+    Context(C), // Replaces the machine's context
+    Flush(Ack), // Acknowledged once every earlier input has been handled
+    Shutdown,
+}
+
+/// What [`run_machine`] handled before calling `on_step`.
+#[derive(Debug)]
+pub enum Trigger<'a, E, C> {
+    Event(&'a E),
+    Context(&'a C),
+}
+
+/// Why [`run_machine`] returned.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Exit {
+    Shutdown,
+    Disconnected,
+}
+
+/// Common driver loop used by the controller threads.
+///
+/// Receives messages until `Shutdown` or until every sender is dropped.
+/// `on_step` is called after each event or context change with the resulting
+/// transition, if any, so the caller can publish and log state.
+pub fn run_machine<M, T, F>(machine: &mut M, ctx: M::Context, rx: mpsc::Receiver<T>, on_step: F) -> Exit
+where
+    M: StateMachine,
+    M::Event: Copy,
+    T: Into<Input<M::Event, M::Context>>,
+    F: FnMut(&M, Option<Transition<M::State>>, Trigger<'_, M::Event, M::Context>),
+{
+    run_machine_on(machine, ctx, &rx, on_step)
+}
+
+// run_machine on a borrowed receiver, which outlives a panicking machine
+pub(crate) fn run_machine_on<M, T, F>(machine: &mut M, mut ctx: M::Context, rx: &mpsc::Receiver<T>, mut on_step: F) -> Exit
+where
+    M: StateMachine,
+    M::Event: Copy,
+    T: Into<Input<M::Event, M::Context>>,
+    F: FnMut(&M, Option<Transition<M::State>>, Trigger<'_, M::Event, M::Context>),
+{
+    while let Ok(message) = rx.recv() {
+        if let Some(exit) = apply(machine, &mut ctx, message.into(), &mut on_step) {
+            return exit;
+        }
+    }
+    Exit::Disconnected
+}
+
+// One input of a driver loop. Returns the exit once the loop should stop.
+pub(crate) fn apply<M, F>(
+    machine: &mut M,
+    ctx: &mut M::Context,
+    input: Input<M::Event, M::Context>,
+    on_step: &mut F,
+) -> Option<Exit>
+where
+    M: StateMachine,
+    M::Event: Copy,
+    F: FnMut(&M, Option<Transition<M::State>>, Trigger<'_, M::Event, M::Context>),
+{
+    match input {
+        Input::Event(event) => {
+            let transition = machine.handle_event(event, ctx);
+            on_step(machine, transition, Trigger::Event(&event));
+        }
+        Input::Context(new_ctx) => {
+            let reevaluate = machine.on_context_change(ctx, &new_ctx);
+            *ctx = new_ctx;
+            let transition = reevaluate.and_then(|event| machine.handle_event(event, ctx));
+            on_step(machine, transition, Trigger::Context(ctx));
+        }
+        Input::Flush(ack) => ack.done(),
+        Input::Shutdown => return Some(Exit::Shutdown),
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal two-state machine standing in for a user-defined one (a gate arm)
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    enum Gate {
+        Up,
+        Down,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum GateEvent {
+        Toggle,
+        Tick,
+    }
+
+    struct GateFsm {
+        state: Gate,
+        entered: u32,
+    }
+
+    impl StateMachine for GateFsm {
+        type State = Gate;
+        type Event = GateEvent;
+        type Context = bool; // Train present
+
+        fn name(&self) -> &str {
+            "Gate"
+        }
+
+        fn state(&self) -> Gate {
+            self.state
+        }
+
+        fn next_state(&mut self, event: &GateEvent, train_present: &bool) -> Gate {
+            match (event, self.state) {
+                (GateEvent::Toggle, Gate::Up) => Gate::Down,
+                (GateEvent::Toggle, Gate::Down) if !train_present => Gate::Up,
+                (GateEvent::Tick, Gate::Up) if *train_present => Gate::Down,
+                _ => self.state,
+            }
+        }
+
+        fn enter(&mut self, state: Gate) {
+            self.state = state;
+            self.entered += 1;
+        }
+
+        fn on_context_change(&mut self, _old: &bool, new: &bool) -> Option<GateEvent> {
+            if *new {
+                Some(GateEvent::Tick)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_handle_event_reports_transitions() {
+        let mut gate = GateFsm { state: Gate::Up, entered: 0 };
+        assert_eq!(
+            gate.handle_event(GateEvent::Toggle, &false),
+            Some(Transition { from: Gate::Up, to: Gate::Down, reason: None })
+        );
+        // Guarded: cannot raise the gate while a train is present
+        assert_eq!(gate.handle_event(GateEvent::Toggle, &true), None);
+        assert_eq!(gate.state(), Gate::Down);
+        assert_eq!(gate.entered, 1);
+    }
+
+    #[test]
+    fn test_run_machine_drives_custom_machine() {
+        let (tx, rx) = mpsc::channel::<Input<GateEvent, bool>>();
+        tx.send(Input::Context(true)).unwrap(); // Re-evaluates with Tick -> Down
+        tx.send(Input::Event(GateEvent::Toggle)).unwrap(); // Blocked by context
+        tx.send(Input::Context(false)).unwrap();
+        tx.send(Input::Event(GateEvent::Toggle)).unwrap(); // -> Up
+        let (ack_tx, ack_rx) = mpsc::channel();
+        tx.send(Input::Flush(ack_tx.into())).unwrap();
+        tx.send(Input::Shutdown).unwrap();
+
+        let mut gate = GateFsm { state: Gate::Up, entered: 0 };
+        let mut seen = Vec::new();
+        let exit = run_machine(&mut gate, false, rx, |_, transition, _| {
+            if let Some(t) = transition {
+                seen.push(t.to);
+            }
+        });
+        assert_eq!(exit, Exit::Shutdown);
+        assert_eq!(seen, vec![Gate::Down, Gate::Up]);
+        assert_eq!(ack_rx.try_recv(), Ok(()));
+    }
+
+    #[test]
+    fn test_run_machine_exits_on_disconnect() {
+        let (tx, rx) = mpsc::channel::<Input<GateEvent, bool>>();
+        drop(tx);
+        let mut gate = GateFsm { state: Gate::Up, entered: 0 };
+        assert_eq!(run_machine(&mut gate, false, rx, |_, _, _| {}), Exit::Disconnected);
+    }
+}
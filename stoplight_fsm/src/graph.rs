@@ -0,0 +1,133 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::crosswalk::FlashDisplay;
+use crate::stoplight::RestIn;
+
+/// State diagram of one machine, rendered as Mermaid (as used in
+/// finite_state_machine.md) or Graphviz dot.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Diagram {
+    pub name: String,
+    pub initial: String,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub label: String,
+}
+
+impl Diagram {
+    pub fn new(name: &str, initial: impl Into<String>) -> Self {
+        Diagram { name: name.to_string(), initial: initial.into(), edges: Vec::new() }
+    }
+
+    pub fn edge(mut self, from: impl Into<String>, to: impl Into<String>, label: impl Into<String>) -> Self {
+        self.edges.push(Edge { from: from.into(), to: to.into(), label: label.into() });
+        self
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "%% {}", self.name);
+        let _ = writeln!(out, "graph TD");
+        let _ = writeln!(out, "    [*] --> {};", self.initial);
+        for edge in &self.edges {
+            if edge.label.is_empty() {
+                let _ = writeln!(out, "    {} --> {};", edge.from, edge.to);
+            } else {
+                let _ = writeln!(out, "    {} -- \"{}\" --> {};", edge.from, edge.label, edge.to);
+            }
+        }
+        out
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph {} {{", self.name);
+        let _ = writeln!(out, "    start [shape=point];");
+        let _ = writeln!(out, "    start -> {};", self.initial);
+        for edge in &self.edges {
+            let _ = writeln!(out, "    {} -> {} [label=\"{}\"];", edge.from, edge.to, edge.label);
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+}
+
+/// Diagrams of the stoplight and crosswalk machines for a timing plan.
+pub fn diagrams(config: &Config) -> Vec<Diagram> {
+    let (timing, crossing) = (config.stoplight_timing(), config.crosswalk_timing());
+    let mut red = match (timing.red_clearance, timing.red_revert) {
+        (0, 0) => format!("{} ticks", timing.red),
+        (clearance, revert) => format!("{} ticks, all-red {}, revert {}", timing.red, clearance, revert),
+    };
+    let green = match timing.actuation {
+        None => format!("{} ticks", timing.green),
+        Some(actuation) => {
+            if actuation.rest == RestIn::Red {
+                red.push_str(", on vehicle call");
+            }
+            format!("min {}, max {}, gap {} ticks", timing.green, actuation.max_green, actuation.passage)
+        }
+    };
+    let stoplight = Diagram::new("Stoplight", format!("{:?}", config.stoplight.initial))
+        .edge("Red", "Green", red)
+        .edge("Green", "Yellow", green)
+        .edge("Yellow", "Red", format!("{} ticks", config.stoplight.yellow))
+        .edge("Red", "Red", "held while pedestrians cross")
+        .edge("Red", "FlashingYellow", "Flash(Yellow)")
+        .edge("FlashingYellow", "Yellow", "ExitFlash")
+        .edge("Red", "FlashingRed", "Flash(Red), from any state")
+        .edge("FlashingRed", "Red", "ExitFlash");
+
+    let mut crosswalk = Diagram::new("Crosswalk", format!("{:?}", config.crosswalk.initial))
+        .edge("DontWalk", "Walk", "button, then WalkGranted")
+        .edge("Walk", "BlinkingDontWalk", format!("{} ticks", crossing.walk))
+        .edge("BlinkingDontWalk", "DontWalk", format!("{} ticks", crossing.blinking))
+        .edge("Walk", "DontWalk", "stoplight not Red")
+        .edge("BlinkingDontWalk", "DontWalk", "stoplight not Red");
+    if config.crosswalk.during_flash == FlashDisplay::Dark {
+        crosswalk = crosswalk.edge("DontWalk", "Dark", "stoplight flashing").edge("Dark", "DontWalk", "stoplight steady");
+    }
+
+    vec![stoplight, crosswalk]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stoplight::Actuation;
+
+    #[test]
+    fn test_render_formats() {
+        let diagram = Diagram::new("Turnstile", "Locked").edge("Locked", "Unlocked", "coin").edge("Unlocked", "Locked", "");
+        assert_eq!(
+            diagram.to_mermaid(),
+            "%% Turnstile\ngraph TD\n    [*] --> Locked;\n    Locked -- \"coin\" --> Unlocked;\n    Unlocked --> Locked;\n"
+        );
+        assert_eq!(
+            diagram.to_dot(),
+            "digraph Turnstile {\n    start [shape=point];\n    start -> Locked;\n    Locked -> Unlocked [label=\"coin\"];\n    Unlocked -> Locked [label=\"\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_diagrams_use_config_durations() {
+        let mut config = Config::default();
+        config.stoplight.green = 9;
+        let diagrams = diagrams(&config);
+        assert_eq!(diagrams[0].edges[1].label, "9 ticks");
+        assert_eq!(diagrams[1].initial, "DontWalk");
+
+        config.stoplight.actuated = Some(Actuation { max_green: 20, ..Actuation::default() });
+        let diagrams = super::diagrams(&config);
+        assert_eq!(diagrams[0].edges[0].label, "5 ticks, on vehicle call");
+        assert_eq!(diagrams[0].edges[1].label, "min 9, max 20, gap 2 ticks");
+    }
+}
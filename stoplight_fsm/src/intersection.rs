@@ -0,0 +1,472 @@
+use std::fmt;
+
+use crate::crosswalk::{CrosswalkState, CrosswalkTiming};
+use crate::fsm::StateMachine;
+use crate::stoplight::{StoplightFsm, StoplightState};
+
+/// NEMA phase numbers by ring. Phases 1, 2, 5 and 6 lie on one side of the
+/// barrier and 3, 4, 7 and 8 on the other; conventionally 2 and 6 are the
+/// major-street throughs, 4 and 8 the minor-street throughs and the odd
+/// phases their protected left turns.
+pub const RINGS: [[u8; 4]; 2] = [[1, 2, 3, 4], [5, 6, 7, 8]];
+
+fn ring_of(phase: u8) -> usize {
+    usize::from((phase - 1) / 4)
+}
+
+fn side_of(phase: u8) -> usize {
+    usize::from((phase - 1) % 4 / 2)
+}
+
+fn index(phase: u8) -> usize {
+    usize::from(phase - 1)
+}
+
+// Two phases may show green together only from different rings on the same side of the barrier
+pub fn phases_conflict(a: u8, b: u8) -> bool {
+    a != b && (ring_of(a) == ring_of(b) || side_of(a) != side_of(b))
+}
+
+/// One vehicle phase and the pedestrian crossing served alongside it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Phase {
+    pub number: u8, // 1-8
+    pub name: String,
+    pub green: u32,
+    pub yellow: u32,
+    pub red_clearance: u32, // All-red after the Yellow before a conflicting phase may start
+    pub crossing: Option<CrosswalkTiming>, // Walk and clearance run at the start of the green
+}
+
+impl Phase {
+    pub fn new(number: u8, name: &str) -> Self {
+        Phase {
+            number,
+            name: name.to_string(),
+            green: StoplightFsm::GREEN_DURATION,
+            yellow: StoplightFsm::YELLOW_DURATION,
+            red_clearance: 0,
+            crossing: None,
+        }
+    }
+
+    pub fn green(mut self, ticks: u32) -> Self {
+        self.green = ticks;
+        self
+    }
+
+    pub fn yellow(mut self, ticks: u32) -> Self {
+        self.yellow = ticks;
+        self
+    }
+
+    pub fn red_clearance(mut self, ticks: u32) -> Self {
+        self.red_clearance = ticks;
+        self
+    }
+
+    pub fn crossing(mut self, timing: CrosswalkTiming) -> Self {
+        self.crossing = Some(timing);
+        self
+    }
+}
+
+/// Problem found while building an [`Intersection`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum IntersectionError {
+    NoPhases,
+    UnknownPhase(u8), // Outside 1-8
+    DuplicatePhase(u8),
+    ZeroInterval { phase: u8, interval: &'static str },
+    // Walk plus clearance must end before the phase's Yellow
+    CrossingTooLong { phase: u8, crossing: u32, green: u32 },
+}
+
+impl fmt::Display for IntersectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntersectionError::NoPhases => write!(f, "no phases configured"),
+            IntersectionError::UnknownPhase(phase) => write!(f, "phase {} is not a NEMA phase (1-8)", phase),
+            IntersectionError::DuplicatePhase(phase) => write!(f, "phase {} is configured twice", phase),
+            IntersectionError::ZeroInterval { phase, interval } => {
+                write!(f, "phase {} {} must be at least 1 tick", phase, interval)
+            }
+            IntersectionError::CrossingTooLong { phase, crossing, green } => write!(
+                f,
+                "phase {} crossing needs {} ticks but the green is only {} ticks",
+                phase, crossing, green
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntersectionError {}
+
+/// The phase a ring is timing, with its head and crossing indications.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RingState {
+    pub phase: u8,
+    pub head: StoplightState, // Red while waiting for conflicting phases to clear
+    pub crossing: Option<CrosswalkState>,
+}
+
+/// Both rings of an [`Intersection`]; `None` for a ring waiting at the barrier.
+#[derive(PartialEq, Clone, Copy)]
+pub struct IntersectionState {
+    pub rings: [Option<RingState>; 2],
+}
+
+// Compact form for the event log, e.g. "2 Green Walk | 6 Yellow"
+impl fmt::Debug for IntersectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, ring) in self.rings.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            match ring {
+                Some(RingState { phase, head, crossing: Some(crossing) }) => {
+                    write!(f, "{} {:?} {:?}", phase, head, crossing)?
+                }
+                Some(RingState { phase, head, crossing: None }) => write!(f, "{} {:?}", phase, head)?,
+                None => write!(f, "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IntersectionEvent {
+    TimerTick,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RingTimer {
+    phase: Option<u8>,
+    ticks: u32, // In the phase's current interval
+}
+
+/// Fixed-time controller for several signal heads and crossings, sequenced
+/// through a dual-ring, single-barrier NEMA structure.
+///
+/// Each ring times its phases in order. A ring's next phase turns green as
+/// soon as the previous one has finished its Yellow and red clearance,
+/// provided no conflicting head is still Green, Yellow or clearing. Both rings must finish their
+/// side of the barrier before either crosses it. Unconfigured phases are
+/// skipped and their heads stay Red.
+pub struct Intersection {
+    phases: [Option<Phase>; 8],
+    heads: [StoplightState; 8],
+    clearance: [u32; 8], // All-red ticks left after each phase's Yellow
+    crossings: [CrosswalkState; 8],
+    crossing_ticks: [u32; 8],
+    rings: [RingTimer; 2],
+    side: usize, // Side of the barrier being served
+}
+
+impl Intersection {
+    pub fn new(phases: Vec<Phase>) -> Result<Self, IntersectionError> {
+        if phases.is_empty() {
+            return Err(IntersectionError::NoPhases);
+        }
+        let mut slots: [Option<Phase>; 8] = Default::default();
+        for phase in phases {
+            let number = phase.number;
+            if !(1..=8).contains(&number) {
+                return Err(IntersectionError::UnknownPhase(number));
+            }
+            for (interval, ticks) in [("green", phase.green), ("yellow", phase.yellow)] {
+                if ticks == 0 {
+                    return Err(IntersectionError::ZeroInterval { phase: number, interval });
+                }
+            }
+            if let Some(crossing) = phase.crossing {
+                if crossing.walk == 0 || crossing.blinking == 0 {
+                    return Err(IntersectionError::ZeroInterval { phase: number, interval: "crossing" });
+                }
+                if crossing.interval() > phase.green {
+                    let crossing = crossing.interval();
+                    return Err(IntersectionError::CrossingTooLong { phase: number, crossing, green: phase.green });
+                }
+            }
+            if slots[index(number)].replace(phase).is_some() {
+                return Err(IntersectionError::DuplicatePhase(number));
+            }
+        }
+
+        let mut intersection = Intersection {
+            phases: slots,
+            heads: [StoplightState::Red; 8],
+            clearance: [0; 8],
+            crossings: [CrosswalkState::DontWalk; 8],
+            crossing_ticks: [0; 8],
+            rings: Default::default(),
+            side: 1,
+        };
+        intersection.cross_barrier();
+        intersection.start_waiting_phases();
+        Ok(intersection)
+    }
+
+    pub fn phase(&self, number: u8) -> Option<&Phase> {
+        if !(1..=8).contains(&number) {
+            return None;
+        }
+        self.phases[index(number)].as_ref()
+    }
+
+    pub fn head(&self, phase: u8) -> StoplightState {
+        self.heads[index(phase)]
+    }
+
+    // None for phases without a crossing
+    pub fn crossing(&self, phase: u8) -> Option<CrosswalkState> {
+        self.phase(phase)?.crossing.map(|_| self.crossings[index(phase)])
+    }
+
+    /// First pair of conflicting phases whose heads are both Green or
+    /// Yellow, if any. Always `None` unless the controller is broken.
+    pub fn conflict(&self) -> Option<(u8, u8)> {
+        let active: Vec<u8> = (1..=8).filter(|&p| self.head(p) != StoplightState::Red).collect();
+        active
+            .iter()
+            .flat_map(|&a| active.iter().map(move |&b| (a, b)))
+            .find(|&(a, b)| a < b && phases_conflict(a, b))
+    }
+
+    fn configured(&self, ring: usize, side: usize) -> impl Iterator<Item = u8> + '_ {
+        RINGS[ring][side * 2..side * 2 + 2].iter().copied().filter(move |&p| self.phases[index(p)].is_some())
+    }
+
+    // Serve the other side of the barrier, or this side again if the other has no phases
+    fn cross_barrier(&mut self) {
+        for _ in 0..2 {
+            self.side = 1 - self.side;
+            for ring in 0..2 {
+                let first = self.configured(ring, self.side).next();
+                self.rings[ring] = RingTimer { phase: first, ticks: 0 };
+            }
+            if self.rings.iter().any(|r| r.phase.is_some()) {
+                return;
+            }
+        }
+    }
+
+    // Turn waiting phases green once every conflicting head is Red
+    fn start_waiting_phases(&mut self) {
+        for ring in 0..2 {
+            let Some(phase) = self.rings[ring].phase else { continue };
+            if self.head(phase) != StoplightState::Red {
+                continue;
+            }
+            let busy = |other: u8| self.head(other) != StoplightState::Red || self.clearance[index(other)] > 0;
+            if (1..=8).any(|other| phases_conflict(phase, other) && busy(other)) {
+                continue;
+            }
+            let i = index(phase);
+            self.heads[i] = StoplightState::Green;
+            self.rings[ring].ticks = 0;
+            if self.phases[i].as_ref().is_some_and(|p| p.crossing.is_some()) {
+                self.crossings[i] = CrosswalkState::Walk;
+                self.crossing_ticks[i] = 0;
+            }
+        }
+    }
+
+    // Walk, then clearance, both within the green
+    fn time_crossing(&mut self, i: usize) {
+        let Some(timing) = self.phases[i].as_ref().and_then(|p| p.crossing) else { return };
+        self.crossing_ticks[i] += 1;
+        let next = match self.crossings[i] {
+            CrosswalkState::Walk if self.crossing_ticks[i] >= timing.walk => CrosswalkState::BlinkingDontWalk,
+            CrosswalkState::BlinkingDontWalk if self.crossing_ticks[i] >= timing.blinking => CrosswalkState::DontWalk,
+            state => state,
+        };
+        if next != self.crossings[i] {
+            self.crossings[i] = next;
+            self.crossing_ticks[i] = 0;
+        }
+    }
+
+    fn tick(&mut self) {
+        for ticks in self.clearance.iter_mut() {
+            *ticks = ticks.saturating_sub(1);
+        }
+        for ring in 0..2 {
+            let Some(phase) = self.rings[ring].phase else { continue };
+            let i = index(phase);
+            if self.heads[i] == StoplightState::Red {
+                continue; // Still waiting for a conflicting phase
+            }
+            self.time_crossing(i);
+            self.rings[ring].ticks += 1;
+            let ticks = self.rings[ring].ticks;
+            let timing = self.phases[i].as_ref().expect("ring phases are configured");
+            match self.heads[i] {
+                StoplightState::Green if ticks >= timing.green => {
+                    self.heads[i] = StoplightState::Yellow;
+                    self.rings[ring].ticks = 0;
+                }
+                StoplightState::Yellow if ticks >= timing.yellow => {
+                    self.heads[i] = StoplightState::Red;
+                    self.clearance[i] = timing.red_clearance;
+                    let next = self.configured(ring, side_of(phase)).find(|&p| p > phase);
+                    self.rings[ring] = RingTimer { phase: next, ticks: 0 };
+                }
+                _ => {}
+            }
+        }
+        if self.rings.iter().all(|r| r.phase.is_none()) {
+            self.cross_barrier();
+        }
+        self.start_waiting_phases();
+    }
+}
+
+impl StateMachine for Intersection {
+    type State = IntersectionState;
+    type Event = IntersectionEvent;
+    type Context = ();
+
+    fn name(&self) -> &str {
+        "Intersection"
+    }
+
+    fn state(&self) -> IntersectionState {
+        let ring = |timer: &RingTimer| {
+            timer.phase.map(|phase| RingState { phase, head: self.head(phase), crossing: self.crossing(phase) })
+        };
+        IntersectionState { rings: [ring(&self.rings[0]), ring(&self.rings[1])] }
+    }
+
+    fn next_state(&mut self, event: &IntersectionEvent, _ctx: &()) -> IntersectionState {
+        match event {
+            IntersectionEvent::TimerTick => self.tick(),
+        }
+        self.state()
+    }
+
+    // Heads and ring timers were already advanced by next_state
+    fn enter(&mut self, _state: IntersectionState) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eight_phase() -> Intersection {
+        let crossing = CrosswalkTiming { walk: 2, blinking: 2 };
+        Intersection::new(vec![
+            Phase::new(1, "Northbound left").green(2),
+            Phase::new(2, "Southbound through").green(6).yellow(2).crossing(crossing),
+            Phase::new(3, "Eastbound left").green(2),
+            Phase::new(4, "Westbound through").green(5).crossing(crossing),
+            Phase::new(5, "Southbound left").green(3),
+            Phase::new(6, "Northbound through").green(4).crossing(crossing),
+            Phase::new(7, "Westbound left").green(2),
+            Phase::new(8, "Eastbound through").green(5).crossing(crossing),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_phase_conflicts() {
+        assert!(!phases_conflict(2, 6));
+        assert!(!phases_conflict(1, 6));
+        assert!(phases_conflict(1, 2)); // Same ring
+        assert!(phases_conflict(2, 8)); // Across the barrier
+        assert!(!phases_conflict(4, 7));
+    }
+
+    #[test]
+    fn test_rings_sequence_through_barrier() {
+        let mut intersection = eight_phase();
+        let mut greens: Vec<u8> = (1..=8).filter(|&p| intersection.head(p) == StoplightState::Green).collect();
+        for _ in 0..60 {
+            let before: Vec<StoplightState> = (1..=8).map(|p| intersection.head(p)).collect();
+            intersection.handle_event(IntersectionEvent::TimerTick, &());
+            greens.extend((1..=8).filter(|&p| {
+                intersection.head(p) == StoplightState::Green && before[index(p)] != StoplightState::Green
+            }));
+
+            assert_eq!(intersection.conflict(), None);
+            for phase in 1..=8 {
+                // A crossing only shows Walk or clearance during its own green
+                if matches!(intersection.crossing(phase), Some(CrosswalkState::Walk | CrosswalkState::BlinkingDontWalk)) {
+                    assert_eq!(intersection.head(phase), StoplightState::Green);
+                }
+            }
+        }
+        // Left turns lead, and both rings cross the barrier together
+        assert_eq!(greens[..8], [1, 5, 2, 6, 3, 7, 4, 8]);
+        assert_eq!(greens[8..16], greens[..8]);
+    }
+
+    #[test]
+    fn test_barrier_waits_for_slower_ring() {
+        let mut intersection =
+            Intersection::new(vec![Phase::new(2, "Major").green(8), Phase::new(6, "Major").green(2), Phase::new(4, "Minor")])
+                .unwrap();
+        let mut ticks = 0;
+        while intersection.head(4) != StoplightState::Green {
+            intersection.handle_event(IntersectionEvent::TimerTick, &());
+            ticks += 1;
+            if intersection.head(6) == StoplightState::Red {
+                // Phase 6 is done but 4 must wait for phase 2 to clear
+                assert!(intersection.head(4) == StoplightState::Red || intersection.head(2) == StoplightState::Red);
+            }
+        }
+        // Green and Yellow of phase 2, then phase 4 starts on the tick phase 2 goes Red
+        assert_eq!(ticks, 8 + StoplightFsm::YELLOW_DURATION);
+        assert_eq!(format!("{:?}", intersection.state()), "4 Green | -");
+    }
+
+    #[test]
+    fn test_red_clearance_between_conflicting_phases() {
+        let mut intersection = Intersection::new(vec![
+            Phase::new(2, "Major").green(3).red_clearance(2),
+            Phase::new(6, "Major").green(3),
+            Phase::new(4, "Minor").green(3).red_clearance(1),
+        ])
+        .unwrap();
+        let mut all_red = 0;
+        let mut greens = Vec::new();
+        for _ in 0..20 {
+            intersection.handle_event(IntersectionEvent::TimerTick, &());
+            let heads: Vec<StoplightState> = [2, 4, 6].iter().map(|&p| intersection.head(p)).collect();
+            if heads.iter().all(|&h| h == StoplightState::Red) {
+                all_red += 1;
+            } else {
+                greens.push(heads);
+            }
+        }
+        // Two ticks of all-red after 2 and 6 (2 clears longer), then one after 4: ticks 4-5, 10 and 15-16
+        assert_eq!(intersection.conflict(), None);
+        assert_eq!(all_red, 5);
+        assert_eq!(greens.len(), 15);
+    }
+
+    #[test]
+    fn test_state_reports_crossing() {
+        let mut intersection = eight_phase();
+        for _ in 0..3 {
+            intersection.handle_event(IntersectionEvent::TimerTick, &());
+        }
+        // Phase 1 finished its green and yellow and 2 took over; phase 5 had a longer green
+        assert_eq!(format!("{:?}", intersection.state()), "2 Green Walk | 5 Yellow");
+        let transition = intersection.handle_event(IntersectionEvent::TimerTick, &()).unwrap();
+        assert_eq!(format!("{:?}", transition.to), "2 Green Walk | 6 Green Walk");
+    }
+
+    #[test]
+    fn test_invalid_phases_are_rejected() {
+        assert_eq!(Intersection::new(vec![]).err(), Some(IntersectionError::NoPhases));
+        assert_eq!(Intersection::new(vec![Phase::new(9, "Ramp")]).err(), Some(IntersectionError::UnknownPhase(9)));
+        assert_eq!(
+            Intersection::new(vec![Phase::new(2, "A"), Phase::new(2, "B")]).err(),
+            Some(IntersectionError::DuplicatePhase(2))
+        );
+        let err = Intersection::new(vec![Phase::new(4, "Minor").green(3).crossing(CrosswalkTiming::default())]).err().unwrap();
+        assert_eq!(err.to_string(), "phase 4 crossing needs 5 ticks but the green is only 3 ticks");
+    }
+}
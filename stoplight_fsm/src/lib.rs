@@ -0,0 +1,53 @@
+//! Stop light and synchronized crosswalk state machines.
+//!
+//! Both machines implement the generic `fsm::StateMachine` trait. They can be
+//! driven directly through `handle_event`, or run on their own threads with `timer_thread`, `stoplight_thread` and
+//! `crosswalk_thread` exchanging the message enums in `messages`. With the `async` feature, `async_driver` runs them as
+//! tokio tasks instead.
+
+#[cfg(feature = "async")]
+pub mod async_driver;
+pub mod bus;
+pub mod clock;
+pub mod config;
+pub mod coordination;
+pub mod crosswalk;
+pub mod event_log;
+pub mod fsm;
+pub mod graph;
+pub mod intersection;
+pub mod messages;
+pub mod monitor;
+pub mod replay;
+pub mod schedule;
+pub mod stoplight;
+pub mod supervisor;
+pub mod table;
+pub mod threads;
+
+#[cfg(feature = "async")]
+pub use async_driver::{
+    crosswalk_task, monitor_task, run_machine_async, spawn_intersection, stoplight_task, task_channel, timer_task,
+    IntersectionTasks, TaskReceiver, TaskSender, TokioClock,
+};
+pub use bus::{status_bus, Backpressure, Broadcast, BusError, StatusBus, Topic};
+pub use clock::{Clock, SystemClock, VirtualClock};
+pub use coordination::{Coordination, CoordinationConfig, Split, TransitionMode};
+pub use config::{ButtonSchedule, Config, ConfigError, PlanConfig, PreemptRun, TrainRun};
+pub use crosswalk::{CrosswalkEvent, CrosswalkFsm, CrosswalkGeometry, CrosswalkState, CrosswalkTiming, FlashDisplay, PedestrianCall};
+pub use event_log::{EventLog, JsonLinesSink, MemorySink, MessageSink, TextSink, TransitionRecord, TransitionSink};
+pub use fsm::{run_machine, Ack, Exit, Input, StateMachine, Transition, Trigger};
+pub use graph::{Diagram, Edge};
+pub use intersection::{Intersection, IntersectionError, IntersectionEvent, IntersectionState, Phase, RingState};
+pub use messages::{FromController, FromCrosswalk, FromMonitor, FromStoplight, Recorded, ToCrosswalk, ToMonitor, ToStoplight};
+pub use monitor::{monitor_thread, ResetError, SafetyMonitor, Violation};
+pub use replay::{replay, replay_thread, RecordedMessage, Recorder, ReplayError};
+pub use schedule::{Date, DateTime, DayType, PlanScheduler, Schedule, ScheduleEntry, TimeOfDay, TimingPlan};
+pub use stoplight::{
+    Actuation, FlashMode, PreemptOutcome, PreemptPhase, PreemptRequest, PreemptionCycle, RailCrossing, RailPhase, RedInterval,
+    RestIn, StoplightEvent, StoplightFsm, StoplightState, StoplightTiming, TransitDecision, TransitDenial, TransitOutcome,
+    TransitPriority,
+};
+pub use supervisor::{FinalReport, ResetHandle, StopHandle, Supervisor};
+pub use table::{Guard, Row, TableError, TableMachine, TransitionTable};
+pub use threads::{crosswalk_thread, stoplight_thread, timer_thread, Outbox, TimerOptions};
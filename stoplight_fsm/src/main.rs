@@ -1,679 +1,178 @@
-use std::thread;
-use std::time::Duration;
-use std::sync::mpsc;
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum StoplightState {
-    Red,
-    Green,
-    Yellow,
-}
-
-#[derive(Debug, PartialEq)]
-enum StoplightEvent {
-    TimerTick,
-}
-
-// Messages for inter-thread communication
-enum ToStoplight {
-    TimerTick,
-    Shutdown,
-}
-
-enum ToCrosswalk {
-    TimerTick,
-    ButtonPress,
-    StoplightState(StoplightState), // Carries the current state of the stoplight
-    Shutdown,
-}
+mod cli;
 
-enum FromStoplight {
-    StateUpdate(StoplightState), // Stoplight informs others (e.g., main loop, crosswalk) about its state
-}
-
-enum FromCrosswalk {
-    StateUpdate(CrosswalkState), // Crosswalk informs others about its state
-}
-
-struct StoplightFsm {
-    state: StoplightState,
-    timer_ticks_in_state: u32, // Counter for how long we've been in the current state
-}
-
-impl StoplightFsm {
-    fn new() -> Self {
-        StoplightFsm {
-            state: StoplightState::Red, // Initial state
-            timer_ticks_in_state: 0,
-        }
-    }
+use std::env;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::thread;
 
-    // Constants for state durations (in TimerTicks)
-    const RED_DURATION: u32 = 5;
-    const GREEN_DURATION: u32 = 4;
-    const YELLOW_DURATION: u32 = 1;
+use serde_json::json;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::flag;
+use signal_hook::iterator::Signals;
+use stoplight_fsm::graph;
+use stoplight_fsm::{Clock, SystemClock, VirtualClock};
+use stoplight_fsm::{Config, FromMonitor, Supervisor};
+use stoplight_fsm::{EventLog, JsonLinesSink, RecordedMessage, Recorder, TextSink, TransitionSink};
 
-    fn handle_event(&mut self, event: StoplightEvent) {
-        match event {
-            StoplightEvent::TimerTick => {
-                self.timer_ticks_in_state += 1;
-                let mut next_state = self.state; // Default to current state
+use cli::{Command, Format, RunOptions};
 
-                match self.state {
-                    StoplightState::Red => {
-                        if self.timer_ticks_in_state >= Self::RED_DURATION {
-                            next_state = StoplightState::Green;
-                        }
-                    }
-                    StoplightState::Green => {
-                        if self.timer_ticks_in_state >= Self::GREEN_DURATION {
-                            next_state = StoplightState::Yellow;
-                        }
-                    }
-                    StoplightState::Yellow => {
-                        if self.timer_ticks_in_state >= Self::YELLOW_DURATION {
-                            next_state = StoplightState::Red;
-                        }
-                    }
-                }
+fn main() {
+    let command = cli::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("error: {}\n\n{}", e, cli::USAGE);
+        process::exit(2);
+    });
 
-                if self.state != next_state {
-                    println!("Stoplight changing from {:?} to {:?}", self.state, next_state);
-                    self.state = next_state;
-                    self.timer_ticks_in_state = 0; // Reset timer for new state
-                }
-            }
-        }
+    match command {
+        Command::Run(options) => run_simulation(&options, false),
+        Command::Simulate(options) => run_simulation(&options, true),
+        Command::Validate { config, format } => validate(&config, format),
+        Command::Graph { config, format } => print_graph(&config, format),
+        Command::Replay { recording, config, format } => replay(&recording, config.as_deref(), format),
+        Command::Help => print!("{}", cli::USAGE),
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum CrosswalkState {
-    DontWalk,
-    Walk,
-    BlinkingDontWalk,
-}
-
-#[derive(Debug, PartialEq)]
-enum CrosswalkEvent {
-    TimerTick,
-    ButtonPress,
-    // StoplightIsRed and StoplightIsNotRed are removed as per requirement.
-    // This information will be conveyed via ToCrosswalk::StoplightState(StoplightState)
-}
-
-struct CrosswalkFsm {
-    state: CrosswalkState,
-    timer_ticks_in_state: u32,
-    button_pressed_waiting_for_red: bool, // Flag to remember if button was pressed
+fn load_or_exit(options: &RunOptions) -> Config {
+    options.resolve().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    })
 }
 
-impl CrosswalkFsm {
-    fn new() -> Self {
-        CrosswalkFsm {
-            state: CrosswalkState::DontWalk,
-            timer_ticks_in_state: 0,
-            button_pressed_waiting_for_red: false,
+fn validate(path: &Path, format: Format) {
+    let result = Config::load(path);
+    match (format, &result) {
+        (Format::Json, Ok(_)) => println!("{}", json!({ "config": path, "valid": true })),
+        (Format::Json, Err(e)) => println!("{}", json!({ "config": path, "valid": false, "error": e.to_string() })),
+        (_, Ok(config)) => {
+            let (stoplight, crosswalk) = (config.stoplight_timing(), config.crosswalk_timing());
+            println!(
+                "{}: OK (red {}, green {}, yellow {}, walk {}, blinking {} ticks of {} ms)",
+                path.display(),
+                stoplight.red,
+                stoplight.green,
+                stoplight.yellow,
+                crosswalk.walk,
+                crosswalk.blinking,
+                config.tick_ms
+            )
         }
+        (_, Err(e)) => println!("{}: {}", path.display(), e),
     }
-
-    // Constants for state durations
-    const WALK_DURATION: u32 = 3; // How long "Walk" stays on
-    const BLINKING_DURATION: u32 = 2; // How long "DontWalk" blinks
-
-    fn handle_event(&mut self, event: CrosswalkEvent, stoplight_state: StoplightState) {
-        let mut next_state = self.state;
-        let mut forced_by_stoplight = false; // Flag to indicate a forced transition
-        match event {
-            CrosswalkEvent::TimerTick => {
-                self.timer_ticks_in_state += 1;
-                match self.state {
-                    CrosswalkState::Walk => {
-                        if stoplight_state != StoplightState::Red {
-                            next_state = CrosswalkState::DontWalk;
-                            forced_by_stoplight = true;
-                            self.button_pressed_waiting_for_red = false; // Reset waiting flag
-                        } else if self.timer_ticks_in_state >= Self::WALK_DURATION {
-                            next_state = CrosswalkState::BlinkingDontWalk;
-                        }
-                    }
-                    CrosswalkState::BlinkingDontWalk => {
-                        if stoplight_state != StoplightState::Red {
-                            next_state = CrosswalkState::DontWalk;
-                            forced_by_stoplight = true;
-                            self.button_pressed_waiting_for_red = false; // Reset waiting flag
-                        } else if self.timer_ticks_in_state >= Self::BLINKING_DURATION {
-                            next_state = CrosswalkState::DontWalk;
-                        }
-                    }
-                    CrosswalkState::DontWalk => {
-                        // If button was pressed and light is now Red, transition to Walk
-                        if self.button_pressed_waiting_for_red && stoplight_state == StoplightState::Red {
-                            next_state = CrosswalkState::Walk;
-                            self.button_pressed_waiting_for_red = false; // Reset flag
-                        }
-                    }
-                }
-            }
-            CrosswalkEvent::ButtonPress => {
-                println!("Crosswalk button pressed.");
-                if self.state == CrosswalkState::DontWalk {
-                    if stoplight_state == StoplightState::Red {
-                        // If light is already red, transition immediately
-                        next_state = CrosswalkState::Walk;
-                    } else {
-                        // Otherwise, set flag and wait for StoplightIsRed event (or for timer tick when light is red)
-                        self.button_pressed_waiting_for_red = true;
-                        println!("Crosswalk waiting for stoplight to be Red.");
-                    }
-                }
-                // If already Walk or Blinking, button press is ignored or could reset timer (not implemented here)
-            }
-
-	    /* 250603:DST obsolete events, see CrosswalkFSM::handle_event
-            CrosswalkEvent::StoplightIsRed => {
-                // This event is triggered by the main loop when stoplight turns red
-                if self.state == CrosswalkState::DontWalk && self.button_pressed_waiting_for_red {
-                    next_state = CrosswalkState::Walk;
-                    self.button_pressed_waiting_for_red = false;
-                }
-            }
-            CrosswalkEvent::StoplightIsNotRed => {
-                // If stoplight is no longer red, ensure crosswalk is not in Walk state
-                if self.state == CrosswalkState::Walk || self.state == CrosswalkState::BlinkingDontWalk {
-                    next_state = CrosswalkState::DontWalk;
-                    println!("Stoplight no longer red, forcing Crosswalk to DontWalk.");
-                }
-                 self.button_pressed_waiting_for_red = false; // Cancel any pending walk request
-            }
-	    */
-        }
-
-        if self.state != next_state {
-            if forced_by_stoplight {
-                println!("Crosswalk changing from {:?} to {:?} because stoplight is no longer Red.", self.state, next_state);
-            } else {
-                println!("Crosswalk changing from {:?} to {:?}", self.state, next_state);
-            }
-            self.state = next_state;
-            self.timer_ticks_in_state = 0; // Reset timer for new state
-        } else if event == CrosswalkEvent::TimerTick && self.state == CrosswalkState::DontWalk && self.button_pressed_waiting_for_red && stoplight_state == StoplightState::Red {
-            // Special case: TimerTick while waiting for red, and light is now red
-            // This transition is already covered by the main `if self.state != next_state` block if next_state was set correctly.
-            // However, the original logic had this as an `else if`, implying it would only trigger if the first `if` was false.
-            // Let's ensure next_state is set in DontWalk for this condition for consistency.
-            // The original `DontWalk` handler already sets `next_state = CrosswalkState::Walk;`
-            // and `self.button_pressed_waiting_for_red = false;` if the conditions are met.
-            // The `if self.state != next_state` will then catch this.
-            // So this specific `else if` might be redundant if DontWalk correctly sets next_state.
-            // Let's re-verify DontWalk:
-            // CrosswalkState::DontWalk => {
-            //    if self.button_pressed_waiting_for_red && stoplight_state == StoplightState::Red {
-            //        next_state = CrosswalkState::Walk; // This is good
-            //        self.button_pressed_waiting_for_red = false;
-            //    }
-            // }
-            // The original `else if` was likely to print a message for this specific sub-case of DontWalk.
-            // Given the new `forced_by_stoplight` print, we can simplify.
-            // The key is that `next_state` must be correctly set within the match block.
-            // This `else if` as written would now be problematic if `next_state` was ALREADY `Walk` from the inner block.
-            // Let's remove it as the main `if self.state != next_state` should handle all transitions.
-            // The special case print for "TimerTick while waiting for red" is now part of the generic transition print.
-        }
+    if result.is_err() {
+        process::exit(1);
     }
 }
 
-// Timer thread function
-fn timer_thread(
-    tx_stoplight: mpsc::Sender<ToStoplight>,
-    tx_crosswalk: mpsc::Sender<ToCrosswalk>,
-    simulation_ticks: u32,
-) {
-    for tick in 0..simulation_ticks {
-        let mut button_press_simulated = false;
-        // Send TimerTick to Stoplight FSM
-        if let Err(e) = tx_stoplight.send(ToStoplight::TimerTick) {
-            eprintln!("Timer thread: failed to send TimerTick to stoplight: {}", e);
-            break; // Exit loop if channel is closed
-        }
-
-        // Send TimerTick to Crosswalk FSM
-        if let Err(e) = tx_crosswalk.send(ToCrosswalk::TimerTick) {
-            eprintln!("Timer thread: failed to send TimerTick to crosswalk: {}", e);
-            break; // Exit loop if channel is closed
-        }
-
-        // Simulate a button press every 5 ticks
-        if (tick + 1) % 5 == 0 {
-            if let Err(e) = tx_crosswalk.send(ToCrosswalk::ButtonPress) {
-                eprintln!("Timer thread: failed to send ButtonPress to crosswalk: {}", e);
-                break; // Exit loop if channel is closed
-            }
-            button_press_simulated = true;
-        }
-
-        if button_press_simulated {
-            println!("Timer thread: Tick {} - Sent TimerTicks, Simulated ButtonPress", tick);
-        } else {
-            println!("Timer thread: Tick {} - Sent TimerTicks", tick);
-        }
-
-        thread::sleep(Duration::from_millis(1000)); // Sleep for 1 second per tick
-    }
-
-    println!("Timer thread: Simulation finished. Sending shutdown signals.");
-    // Send Shutdown signals
-    if let Err(e) = tx_stoplight.send(ToStoplight::Shutdown) {
-        eprintln!("Timer thread: failed to send Shutdown to stoplight: {}", e);
-    }
-    if let Err(e) = tx_crosswalk.send(ToCrosswalk::Shutdown) {
-        eprintln!("Timer thread: failed to send Shutdown to crosswalk: {}", e);
+fn print_graph(path: &Path, format: Format) {
+    let config = Config::load(path).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+    let diagrams = graph::diagrams(&config);
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&diagrams).expect("diagrams serialize")),
+        Format::Dot => diagrams.iter().for_each(|d| print!("{}", d.to_dot())),
+        Format::Text | Format::Mermaid => diagrams.iter().for_each(|d| print!("{}", d.to_mermaid())),
     }
-    println!("Timer thread: Exiting.");
 }
 
-// Stoplight thread function
-fn stoplight_thread(
-    rx: mpsc::Receiver<ToStoplight>,
-    tx_main: Option<mpsc::Sender<FromStoplight>>,
-    tx_crosswalk: Option<mpsc::Sender<ToCrosswalk>>,
-) {
-    let mut fsm = StoplightFsm::new();
-    println!("Stoplight thread started. Initial state: {:?}", fsm.state);
-
-    // Send initial state to main (if channel provided)
-    if let Some(ref sender) = tx_main {
-        if let Err(e) = sender.send(FromStoplight::StateUpdate(fsm.state)) {
-            eprintln!("Stoplight thread: failed to send initial state to main: {}", e);
-        }
-    }
-
-    // Send initial state to crosswalk (if channel provided)
-    if let Some(ref sender) = tx_crosswalk {
-        if let Err(e) = sender.send(ToCrosswalk::StoplightState(fsm.state)) {
-            eprintln!("Stoplight thread: failed to send initial state to crosswalk: {}", e);
-        }
+fn stdout_sink(format: Format) -> Arc<dyn TransitionSink> {
+    match format {
+        Format::Json => Arc::new(JsonLinesSink::stdout()),
+        _ => Arc::new(TextSink::stdout()),
     }
-
-    while let Ok(message) = rx.recv() {
-        match message {
-            ToStoplight::TimerTick => {
-                let old_state = fsm.state;
-                fsm.handle_event(StoplightEvent::TimerTick);
-                // The println inside handle_event already announces the change,
-                // but we can add a specific one for the thread context if needed.
-                // println!("Stoplight thread: Processed TimerTick. Current state: {:?}", fsm.state);
-
-                // If state changed, send update to main
-                if let Some(ref sender) = tx_main {
-                    if old_state != fsm.state { // Send only if state changed
-                        if let Err(e) = sender.send(FromStoplight::StateUpdate(fsm.state)) {
-                            eprintln!("Stoplight thread: failed to send state update to main: {}", e);
-                        }
-                    }
-                }
-                // Always send current state to crosswalk thread
-                if let Some(ref sender) = tx_crosswalk {
-                    if let Err(e) = sender.send(ToCrosswalk::StoplightState(fsm.state)) {
-                        eprintln!("Stoplight thread: failed to send state to crosswalk: {}", e);
-                        // If crosswalk channel is broken, we might not need to shut down stoplight,
-                        // but it's a sign something is wrong.
-                    }
-                }
-            }
-            ToStoplight::Shutdown => {
-                println!("Stoplight thread shutting down.");
-                break;
-            }
-        }
-    }
-    println!("Stoplight thread terminated.");
 }
 
-// Crosswalk thread function
-fn crosswalk_thread(
-    rx: mpsc::Receiver<ToCrosswalk>,
-    tx_main: Option<mpsc::Sender<FromCrosswalk>>,
-) {
-    let mut fsm = CrosswalkFsm::new();
-    // Default to Red, will be updated by the first message from stoplight_thread
-    let mut current_stoplight_state = StoplightState::Red;
-    println!("Crosswalk thread started. Initial state: {:?}, assuming Stoplight is {:?}", fsm.state, current_stoplight_state);
-
-    // Send initial state to main (if channel provided)
-    if let Some(ref sender) = tx_main {
-        if let Err(e) = sender.send(FromCrosswalk::StateUpdate(fsm.state)) {
-            eprintln!("Crosswalk thread: failed to send initial state to main: {}", e);
-        }
-    }
-
-    while let Ok(message) = rx.recv() {
-        let old_fsm_state = fsm.state;
-        match message {
-            ToCrosswalk::TimerTick => {
-                // println!("Crosswalk thread: Received TimerTick. Current stoplight state: {:?}", current_stoplight_state);
-                fsm.handle_event(CrosswalkEvent::TimerTick, current_stoplight_state);
-            }
-            ToCrosswalk::ButtonPress => {
-                // println!("Crosswalk thread: Received ButtonPress. Current stoplight state: {:?}", current_stoplight_state);
-                // The CrosswalkFsm::handle_event for ButtonPress already prints "Crosswalk button pressed."
-                fsm.handle_event(CrosswalkEvent::ButtonPress, current_stoplight_state);
-            }
-            ToCrosswalk::StoplightState(new_state) => {
-                println!("Crosswalk thread: Received StoplightState: {:?}", new_state);
-                let old_stoplight_state = current_stoplight_state;
-                current_stoplight_state = new_state;
-
-                // If the crosswalk was waiting for a red light, and the light is now red,
-                // or if the light is no longer red and it was walking/blinking.
-                // We should re-evaluate its state.
-                // The CrosswalkFsm's TimerTick event is a good way to do this,
-                // as it checks button_pressed_waiting_for_red and current stoplight state.
-                // Also, handle cases where stoplight changes from Red to something else, potentially forcing DontWalk.
-                if (fsm.button_pressed_waiting_for_red && current_stoplight_state == StoplightState::Red) ||
-                   (old_stoplight_state == StoplightState::Red && current_stoplight_state != StoplightState::Red && (fsm.state == CrosswalkState::Walk || fsm.state == CrosswalkState::BlinkingDontWalk))
-                {
-                    // This print helps understand the re-evaluation trigger
-                    println!("Crosswalk thread: Re-evaluating state due to StoplightState change from {:?} to {:?} while button_pressed_waiting_for_red is {} or state was {:?}.", old_stoplight_state, current_stoplight_state, fsm.button_pressed_waiting_for_red, fsm.state);
-                    fsm.handle_event(CrosswalkEvent::TimerTick, current_stoplight_state);
-                }
-            }
-            ToCrosswalk::Shutdown => {
-                println!("Crosswalk thread shutting down.");
-                break;
-            }
-        }
-
-        // If FSM state changed, send update to main
-        if old_fsm_state != fsm.state {
-            if let Some(ref sender) = tx_main {
-                if let Err(e) = sender.send(FromCrosswalk::StateUpdate(fsm.state)) {
-                    eprintln!("Crosswalk thread: failed to send state update to main: {}", e);
-                }
-            }
-        }
-    }
-    println!("Crosswalk thread terminated.");
-}
-
-fn main() {
-    const SIMULATION_TICKS: u32 = 25;
-
-    // Create channels
-    let (tx_to_stoplight, rx_from_timer_for_stoplight) = mpsc::channel::<ToStoplight>();
-    let (tx_to_crosswalk_combined, rx_for_crosswalk_combined) = mpsc::channel::<ToCrosswalk>();
-    let (tx_from_stoplight_to_main, rx_from_stoplight_for_main) = mpsc::channel::<FromStoplight>();
-    let (tx_from_crosswalk_to_main, rx_from_crosswalk_for_main) = mpsc::channel::<FromCrosswalk>();
-
-    // Clone sender for crosswalk as it's used by timer and stoplight threads
-    let tx_to_crosswalk_for_timer = tx_to_crosswalk_combined.clone();
-    // The last sender tx_to_crosswalk_combined can be moved directly to the stoplight thread
-    let tx_to_crosswalk_for_stoplight = tx_to_crosswalk_combined;
-
-    println!("--- Starting simulation with {} ticks ---", SIMULATION_TICKS);
-
-    // Spawn Timer Thread
-    let timer_handle = thread::spawn(move || {
-        timer_thread(tx_to_stoplight, tx_to_crosswalk_for_timer, SIMULATION_TICKS);
+fn replay(path: &Path, config: Option<&Path>, format: Format) {
+    let config = config.map_or_else(|| Ok(Config::default()), Config::load).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
     });
-
-    // Spawn Stoplight Thread
-    let stoplight_handle = thread::spawn(move || {
-        stoplight_thread(
-            rx_from_timer_for_stoplight,
-            Some(tx_from_stoplight_to_main),
-            Some(tx_to_crosswalk_for_stoplight),
-        );
+    let recording = RecordedMessage::load(path).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", path.display(), e);
+        process::exit(1);
     });
-
-    // Spawn Crosswalk Thread
-    let crosswalk_handle = thread::spawn(move || {
-        crosswalk_thread(rx_for_crosswalk_combined, Some(tx_from_crosswalk_to_main));
+    stoplight_fsm::replay(recording, config.stoplight_fsm(), config.crosswalk_fsm(), stdout_sink(format));
+}
+
+// Run the controller under a supervisor and print its monitor's reports.
+// `fast` runs the timer on a virtual clock instead of the wall clock.
+fn run_simulation(options: &RunOptions, fast: bool) {
+    let config = load_or_exit(options);
+    let format = options.format;
+    let clock: Arc<dyn Clock> = if fast { Arc::new(VirtualClock::auto()) } else { Arc::new(SystemClock::new()) };
+    let mut log = EventLog::new(clock.clone()).with_sink(stdout_sink(format));
+    if let Some(path) = &options.record {
+        let recorder = Recorder::create(path).unwrap_or_else(|e| {
+            eprintln!("error: cannot create {}: {}", path.display(), e);
+            process::exit(1);
+        });
+        log = log.with_recorder(Arc::new(recorder));
+    }
+    let text = format == Format::Text;
+
+    if text {
+        println!("--- Starting simulation with {} ticks ---", config.ticks);
+    }
+
+    let supervisor =
+        Supervisor::start(config.stoplight_fsm(), config.crosswalk_fsm(), config.timer_options(), clock, log);
+    // Ctrl-C or SIGTERM stops the controller in its safe state; a second one exits at once
+    let stop = supervisor.stop_handle().flag();
+    for signal in [SIGINT, SIGTERM] {
+        let registered = flag::register_conditional_shutdown(signal, 1, stop.clone())
+            .and_then(|_| flag::register(signal, stop.clone()));
+        if let Err(e) = registered {
+            eprintln!("warning: cannot handle signal {}: {}", signal, e);
+        }
+    }
+    // SIGHUP resets the fail-safe latched by a safety violation, once all-red with DontWalk
+    let reset = supervisor.reset_handle();
+    match Signals::new([SIGHUP]) {
+        Ok(mut signals) => {
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    reset.reset(); // The result is reported by the monitor
+                }
+            });
+        }
+        Err(e) => eprintln!("warning: cannot handle signal {}: {}", SIGHUP, e),
+    }
+
+    // Main Monitoring Loop, transitions are reported by the event log. Returns
+    // once every controller thread has ended.
+    let report = supervisor.wait(|report| match report {
+        FromMonitor::Stoplight(_) | FromMonitor::Crosswalk(_) => {}
+        FromMonitor::Preemption(cycle) if text => println!("Preemption: {}", cycle),
+        FromMonitor::Preemption(cycle) => println!("{}", json!({ "preemption": cycle })),
+        FromMonitor::TransitPriority(decision) if text => println!("Transit priority: {}", decision),
+        FromMonitor::TransitPriority(decision) => println!("{}", json!({ "transit_priority": decision })),
+        FromMonitor::Violation(violation) => {
+            eprintln!("SAFETY VIOLATION: {}; all-red fail-safe latched until reset", violation)
+        }
+        FromMonitor::Reset(result) => eprintln!("Monitor reset: {:?}", result),
     });
 
-    // Main Monitoring Loop
-    println!("Main thread listening for updates...");
-    let mut stoplight_updates_active = true;
-    let mut crosswalk_updates_active = true;
-
-    loop {
-        if !stoplight_updates_active && !crosswalk_updates_active {
-            println!("Main: Both FSM update channels disconnected. Exiting monitoring loop.");
-            break;
-        }
-
-        // Check for messages from Stoplight FSM
-        if stoplight_updates_active {
-            match rx_from_stoplight_for_main.try_recv() {
-                Ok(FromStoplight::StateUpdate(state)) => {
-                    println!("Main received: Stoplight is now {:?}", state);
-                }
-                Err(mpsc::TryRecvError::Empty) => {
-                    // No message currently available
-                }
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    println!("Main: Stoplight FSM channel disconnected.");
-                    stoplight_updates_active = false;
-                }
-            }
-        }
-
-        // Check for messages from Crosswalk FSM
-        if crosswalk_updates_active {
-            match rx_from_crosswalk_for_main.try_recv() {
-                Ok(FromCrosswalk::StateUpdate(state)) => {
-                    println!("Main received: Crosswalk is now {:?}", state);
-                }
-                Err(mpsc::TryRecvError::Empty) => {
-                    // No message currently available
-                }
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    println!("Main: Crosswalk FSM channel disconnected.");
-                    crosswalk_updates_active = false;
-                }
-            }
-        }
-
-        // Avoid busy-waiting if both channels are still active but empty
-        if stoplight_updates_active || crosswalk_updates_active {
-            thread::sleep(Duration::from_millis(50)); // Short sleep to yield CPU
-        }
-    }
-
-    // Join Threads
-    println!("Main: Waiting for threads to join...");
-    timer_handle.join().expect("Timer thread panicked");
-    println!("Main: Timer thread joined.");
-    stoplight_handle.join().expect("Stoplight thread panicked");
-    println!("Main: Stoplight thread joined.");
-    crosswalk_handle.join().expect("Crosswalk thread panicked");
-    println!("Main: Crosswalk thread joined.");
-
-    println!("--- Simulation finished ---");
-    println!("All threads joined. Main thread exiting.");
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*; // Import everything from the outer scope (main.rs)
-
-    #[test]
-    fn test_stoplight_cycle() {
-        let mut fsm = StoplightFsm::new();
-        assert_eq!(fsm.state, StoplightState::Red);
-
-        // Tick through Red state
-        for _ in 0..StoplightFsm::RED_DURATION {
-            fsm.handle_event(StoplightEvent::TimerTick);
-        }
-        assert_eq!(fsm.state, StoplightState::Green);
-        assert_eq!(fsm.timer_ticks_in_state, 0); // Timer should reset
-
-        // Tick through Green state
-        for _ in 0..StoplightFsm::GREEN_DURATION {
-            fsm.handle_event(StoplightEvent::TimerTick);
+    if text {
+        println!("--- Simulation {} ---", if report.stopped_early { "stopped" } else { "finished" });
+        if let (Some(stoplight), Some(crosswalk)) = (report.stoplight, report.crosswalk) {
+            println!("Final state: Stoplight {:?}, Crosswalk {:?}", stoplight, crosswalk);
         }
-        assert_eq!(fsm.state, StoplightState::Yellow);
-        assert_eq!(fsm.timer_ticks_in_state, 0);
-
-        // Tick through Yellow state
-        for _ in 0..StoplightFsm::YELLOW_DURATION {
-            fsm.handle_event(StoplightEvent::TimerTick);
+        if let Some(violation) = report.latched {
+            println!("Fail-safe latched: {}", violation);
         }
-        assert_eq!(fsm.state, StoplightState::Red);
-        assert_eq!(fsm.timer_ticks_in_state, 0);
-    }
-
-    #[test]
-    fn test_crosswalk_button_press_when_stoplight_red() {
-        let mut crosswalk_fsm = CrosswalkFsm::new();
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
-
-        // Simulate button press when stoplight is Red
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk);
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
-
-        // Tick through Walk state
-        for _ in 0..CrosswalkFsm::WALK_DURATION {
-            crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-        }
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk);
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
-
-        // Tick through BlinkingDontWalk state
-        for _ in 0..CrosswalkFsm::BLINKING_DURATION {
-            crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-        }
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
-    }
-
-    #[test]
-    fn test_crosswalk_button_press_when_stoplight_green_then_red() {
-        let mut crosswalk_fsm = CrosswalkFsm::new();
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
-
-        // Simulate button press when stoplight is Green
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Green);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk); // Should not change yet
-        assert!(crosswalk_fsm.button_pressed_waiting_for_red);
-
-        // Simulate some time passing, stoplight still Green
-        crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, StoplightState::Green);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
-        assert!(crosswalk_fsm.button_pressed_waiting_for_red);
-
-        // Simulate stoplight turning Red (via TimerTick to crosswalk while stoplight is Red)
-        crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk); // Now it should change
-        assert!(!crosswalk_fsm.button_pressed_waiting_for_red);
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
-    }
-
-    #[test]
-    fn test_crosswalk_forced_to_dont_walk_if_stoplight_not_red() {
-        let mut crosswalk_fsm = CrosswalkFsm::new();
-        // Make it Walk
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk);
-
-        // Simulate stoplight turning Green. Crosswalk should be forced to DontWalk.
-        // In our current main loop, this is implicitly handled by passing the new stoplight state
-        // to the crosswalk's TimerTick.
-        // We can also use the (currently unused by main loop) StoplightIsNotRed event for a more direct test.
-        crosswalk_fsm.handle_event(CrosswalkEvent::StoplightIsNotRed, StoplightState::Green);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
-
-        // Test with BlinkingDontWalk state
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red); // Go to Walk
-        for _ in 0..CrosswalkFsm::WALK_DURATION { // Go to Blinking
-            crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
+        for (name, restarts) in &report.restarts {
+            println!("{} restarted after a panic: {} times", name, restarts);
         }
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk);
-        crosswalk_fsm.handle_event(CrosswalkEvent::StoplightIsNotRed, StoplightState::Green);
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::DontWalk);
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, 0);
+    } else {
+        println!("{}", json!({ "final": report }));
     }
-
-    #[test]
-    fn test_crosswalk_button_press_ignored_if_not_dont_walk() {
-        let mut crosswalk_fsm = CrosswalkFsm::new();
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red); // -> Walk
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk);
-        let current_ticks = crosswalk_fsm.timer_ticks_in_state;
-
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red); // Press button again
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::Walk); // State should not change
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, current_ticks); // Ticks should not reset
-
-        for _ in 0..CrosswalkFsm::WALK_DURATION {
-             crosswalk_fsm.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-        }
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk); // -> BlinkingDontWalk
-        let current_ticks_blinking = crosswalk_fsm.timer_ticks_in_state;
-
-        crosswalk_fsm.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red); // Press button again
-        assert_eq!(crosswalk_fsm.state, CrosswalkState::BlinkingDontWalk); // State should not change
-        assert_eq!(crosswalk_fsm.timer_ticks_in_state, current_ticks_blinking); // Ticks should not reset
+    for name in &report.panicked {
+        eprintln!("error: {} thread panicked", name);
     }
-
-    #[test]
-    fn test_crosswalk_cycle_prematurely_ends_if_light_changes() {
-        // Test Walk to DontWalk if light changes from Red
-        let mut fsm_walk_test = CrosswalkFsm::new();
-        // 1. Get to Walk state
-        fsm_walk_test.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red);
-        assert_eq!(fsm_walk_test.state, CrosswalkState::Walk, "Test Walk: Should be in Walk state after button press with Red light");
-        assert_eq!(fsm_walk_test.timer_ticks_in_state, 0, "Test Walk: Timer should be 0 after transitioning to Walk");
-
-        // 2. Simulate one TimerTick with StoplightState::Red (assuming WALK_DURATION > 1)
-        // Ensure WALK_DURATION is suitable for this test logic. If WALK_DURATION is 1, this step might transition state.
-        if CrosswalkFsm::WALK_DURATION > 1 {
-            fsm_walk_test.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-            assert_eq!(fsm_walk_test.state, CrosswalkState::Walk, "Test Walk: Should still be in Walk state after 1 tick if duration > 1");
-            assert_eq!(fsm_walk_test.timer_ticks_in_state, 1, "Test Walk: Timer should be 1");
-        } else {
-            // If WALK_DURATION is 1, after one tick it will be BlinkingDontWalk.
-            // This part of the test needs adjustment if WALK_DURATION is 1.
-            // For now, proceeding with assumption WALK_DURATION > 1 based on typical values (e.g., 3).
-            // If it transitions, the next step's premise is wrong.
-            println!("Note: WALK_DURATION is 1, Walk state will transition immediately to BlinkingDontWalk after 1 tick.");
-        }
-
-
-        // 3. Simulate a TimerTick with StoplightState::Green. Should transition to DontWalk.
-        fsm_walk_test.handle_event(CrosswalkEvent::TimerTick, StoplightState::Green);
-        assert_eq!(fsm_walk_test.state, CrosswalkState::DontWalk, "Test Walk: Should transition to DontWalk if light turns Green");
-        assert_eq!(fsm_walk_test.timer_ticks_in_state, 0, "Test Walk: Timer should reset after forced transition to DontWalk");
-
-        // Test BlinkingDontWalk to DontWalk if light changes from Red
-        let mut fsm_blink_test = CrosswalkFsm::new();
-        // 1. Get to Walk state first
-        fsm_blink_test.handle_event(CrosswalkEvent::ButtonPress, StoplightState::Red);
-        assert_eq!(fsm_blink_test.state, CrosswalkState::Walk, "Test Blink: Initial transition to Walk failed");
-
-        // 2. Tick through Walk state to reach BlinkingDontWalk
-        for _ in 0..CrosswalkFsm::WALK_DURATION {
-            fsm_blink_test.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-        }
-        assert_eq!(fsm_blink_test.state, CrosswalkState::BlinkingDontWalk, "Test Blink: Should be in BlinkingDontWalk after WALK_DURATION ticks");
-        assert_eq!(fsm_blink_test.timer_ticks_in_state, 0, "Test Blink: Timer should reset after transitioning to BlinkingDontWalk");
-
-        // 3. Simulate one TimerTick with StoplightState::Red (assuming BLINKING_DURATION > 1)
-        if CrosswalkFsm::BLINKING_DURATION > 1 {
-            fsm_blink_test.handle_event(CrosswalkEvent::TimerTick, StoplightState::Red);
-            assert_eq!(fsm_blink_test.state, CrosswalkState::BlinkingDontWalk, "Test Blink: Should still be in BlinkingDontWalk after 1 tick if duration > 1");
-            assert_eq!(fsm_blink_test.timer_ticks_in_state, 1, "Test Blink: Timer should be 1 for BlinkingDontWalk");
-        } else {
-            println!("Note: BLINKING_DURATION is 1, BlinkingDontWalk will transition immediately to DontWalk after 1 tick.");
-        }
-
-
-        // 4. Simulate a TimerTick with StoplightState::Green. Should transition to DontWalk.
-        fsm_blink_test.handle_event(CrosswalkEvent::TimerTick, StoplightState::Green);
-        assert_eq!(fsm_blink_test.state, CrosswalkState::DontWalk, "Test Blink: Should transition to DontWalk if light turns Green during BlinkingDontWalk");
-        assert_eq!(fsm_blink_test.timer_ticks_in_state, 0, "Test Blink: Timer should reset after forced transition to DontWalk from BlinkingDontWalk");
+    if !report.panicked.is_empty() {
+        process::exit(1);
     }
 }
@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crosswalk::{CrosswalkEvent, CrosswalkState, PedestrianCall};
+use crate::fsm::{Ack, Input, Trigger};
+use crate::monitor::{ResetError, Violation};
+use crate::stoplight::{
+    FlashMode, PreemptRequest, PreemptionCycle, StoplightEvent, StoplightState, StoplightTiming, TransitDecision,
+};
+
+/// A message as handled by one of the machines, as the recorder captures it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "to", content = "message")]
+pub enum Recorded {
+    Stoplight(ToStoplight),
+    Crosswalk(ToCrosswalk),
+}
+
+// Messages for inter-thread communication. Flush is local to the process
+// and never serialized (see replay).
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ToStoplight {
+    TimerTick,
+    FailSafe, // Sent by the safety monitor
+    Resume,
+    Hold, // All-red hold by the supervisor while the crosswalk restarts, see StoplightEvent::Hold
+    Release,
+    Flash(FlashMode), // Commanded flashing operation, see StoplightEvent::Flash
+    ExitFlash,
+    DetectorCall,               // Vehicle detector actuation
+    Preempt(PreemptRequest),    // Emergency vehicle approaching
+    PreemptClear(u8),           // Emergency vehicle on this approach has passed
+    GateDown,                   // Railroad crossing gates lowering
+    GateUp,
+    TransitRequest,             // Bus priority request
+    ChangeTiming(StoplightTiming), // Sent by the plan scheduler
+    Pedestrian(PedestrianCall), // Sent by the crosswalk whenever its call changes
+    #[serde(skip)]
+    Flush(Ack), // Acknowledged after all earlier messages are handled
+    Shutdown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ToCrosswalk {
+    TimerTick,
+    ButtonPress,
+    StoplightState(StoplightState), // Carries the current state of the stoplight
+    WalkGranted,                    // Sent by the stoplight when it starts holding Red for a call
+    Preempt,                        // Sent by the stoplight for the whole preemption cycle, emergency or rail
+    PreemptClear,
+    FailSafe,
+    Resume,
+    #[serde(skip)]
+    Flush(Ack),
+    Shutdown,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FromStoplight {
+    StateUpdate(StoplightState), // Stoplight informs others (e.g., main loop, crosswalk) about its state
+    Preemption(PreemptionCycle),      // Sent as each preemption cycle ends
+    TransitPriority(TransitDecision), // Sent for every transit request, granted or not
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FromCrosswalk {
+    StateUpdate(CrosswalkState), // Crosswalk informs others about its state
+}
+
+// Everything the machines publish on the status bus, see bus::StatusBus
+#[derive(Debug, PartialEq, Clone)]
+pub enum FromController {
+    Stoplight(FromStoplight),
+    Crosswalk(FromCrosswalk),
+}
+
+// Status updates watched by the safety monitor. Both machines send to the
+// same channel so the monitor sees their updates in causal order.
+pub enum ToMonitor {
+    Stoplight(StoplightState),
+    Crosswalk(CrosswalkState),
+    Preemption(PreemptionCycle), // Relayed to main as is
+    TransitPriority(TransitDecision),
+    Reset,                       // Operator request to leave fail-safe
+    Shutdown,
+}
+
+// Updates relayed by the monitor, plus what it did about them
+#[derive(Debug, PartialEq)]
+pub enum FromMonitor {
+    Stoplight(StoplightState),
+    Crosswalk(CrosswalkState),
+    Preemption(PreemptionCycle),
+    TransitPriority(TransitDecision),
+    Violation(Violation),
+    Reset(Result<(), ResetError>),
+}
+
+impl From<FromStoplight> for ToMonitor {
+    fn from(message: FromStoplight) -> Self {
+        match message {
+            FromStoplight::StateUpdate(state) => ToMonitor::Stoplight(state),
+            FromStoplight::Preemption(cycle) => ToMonitor::Preemption(cycle),
+            FromStoplight::TransitPriority(decision) => ToMonitor::TransitPriority(decision),
+        }
+    }
+}
+
+impl From<FromCrosswalk> for ToMonitor {
+    fn from(message: FromCrosswalk) -> Self {
+        match message {
+            FromCrosswalk::StateUpdate(state) => ToMonitor::Crosswalk(state),
+        }
+    }
+}
+
+impl From<FromStoplight> for FromController {
+    fn from(message: FromStoplight) -> Self {
+        FromController::Stoplight(message)
+    }
+}
+
+impl From<FromCrosswalk> for FromController {
+    fn from(message: FromCrosswalk) -> Self {
+        FromController::Crosswalk(message)
+    }
+}
+
+impl From<FromController> for ToMonitor {
+    fn from(message: FromController) -> Self {
+        match message {
+            FromController::Stoplight(message) => message.into(),
+            FromController::Crosswalk(message) => message.into(),
+        }
+    }
+}
+
+// Mapping of thread messages onto the generic driver input, see fsm::run_machine
+impl From<ToStoplight> for Input<StoplightEvent, PedestrianCall> {
+    fn from(message: ToStoplight) -> Self {
+        match message {
+            ToStoplight::TimerTick => Input::Event(StoplightEvent::TimerTick),
+            ToStoplight::FailSafe => Input::Event(StoplightEvent::FailSafe),
+            ToStoplight::Resume => Input::Event(StoplightEvent::Resume),
+            ToStoplight::Hold => Input::Event(StoplightEvent::Hold),
+            ToStoplight::Release => Input::Event(StoplightEvent::Release),
+            ToStoplight::Flash(mode) => Input::Event(StoplightEvent::Flash(mode)),
+            ToStoplight::ExitFlash => Input::Event(StoplightEvent::ExitFlash),
+            ToStoplight::DetectorCall => Input::Event(StoplightEvent::DetectorCall),
+            ToStoplight::Preempt(request) => Input::Event(StoplightEvent::Preempt(request)),
+            ToStoplight::PreemptClear(approach) => Input::Event(StoplightEvent::PreemptClear(approach)),
+            ToStoplight::GateDown => Input::Event(StoplightEvent::GateDown),
+            ToStoplight::GateUp => Input::Event(StoplightEvent::GateUp),
+            ToStoplight::TransitRequest => Input::Event(StoplightEvent::TransitRequest),
+            ToStoplight::ChangeTiming(timing) => Input::Event(StoplightEvent::ChangeTiming(timing)),
+            ToStoplight::Pedestrian(call) => Input::Context(call),
+            ToStoplight::Flush(ack) => Input::Flush(ack),
+            ToStoplight::Shutdown => Input::Shutdown,
+        }
+    }
+}
+
+impl From<ToCrosswalk> for Input<CrosswalkEvent, StoplightState> {
+    fn from(message: ToCrosswalk) -> Self {
+        match message {
+            ToCrosswalk::TimerTick => Input::Event(CrosswalkEvent::TimerTick),
+            ToCrosswalk::ButtonPress => Input::Event(CrosswalkEvent::ButtonPress),
+            ToCrosswalk::StoplightState(state) => Input::Context(state),
+            ToCrosswalk::WalkGranted => Input::Event(CrosswalkEvent::WalkGranted),
+            ToCrosswalk::Preempt => Input::Event(CrosswalkEvent::Preempt),
+            ToCrosswalk::PreemptClear => Input::Event(CrosswalkEvent::PreemptClear),
+            ToCrosswalk::FailSafe => Input::Event(CrosswalkEvent::FailSafe),
+            ToCrosswalk::Resume => Input::Event(CrosswalkEvent::Resume),
+            ToCrosswalk::Flush(ack) => Input::Flush(ack),
+            ToCrosswalk::Shutdown => Input::Shutdown,
+        }
+    }
+}
+
+// Message behind a driver step, used when recording what a machine handled
+impl ToStoplight {
+    pub fn from_trigger(trigger: &Trigger<'_, StoplightEvent, PedestrianCall>) -> ToStoplight {
+        match trigger {
+            Trigger::Event(StoplightEvent::TimerTick) => ToStoplight::TimerTick,
+            Trigger::Event(StoplightEvent::FailSafe) => ToStoplight::FailSafe,
+            Trigger::Event(StoplightEvent::Resume) => ToStoplight::Resume,
+            Trigger::Event(StoplightEvent::Hold) => ToStoplight::Hold,
+            Trigger::Event(StoplightEvent::Release) => ToStoplight::Release,
+            Trigger::Event(StoplightEvent::Flash(mode)) => ToStoplight::Flash(*mode),
+            Trigger::Event(StoplightEvent::ExitFlash) => ToStoplight::ExitFlash,
+            Trigger::Event(StoplightEvent::DetectorCall) => ToStoplight::DetectorCall,
+            Trigger::Event(StoplightEvent::Preempt(request)) => ToStoplight::Preempt(*request),
+            Trigger::Event(StoplightEvent::PreemptClear(approach)) => ToStoplight::PreemptClear(*approach),
+            Trigger::Event(StoplightEvent::GateDown) => ToStoplight::GateDown,
+            Trigger::Event(StoplightEvent::GateUp) => ToStoplight::GateUp,
+            Trigger::Event(StoplightEvent::TransitRequest) => ToStoplight::TransitRequest,
+            Trigger::Event(StoplightEvent::ChangeTiming(timing)) => ToStoplight::ChangeTiming(*timing),
+            Trigger::Context(call) => ToStoplight::Pedestrian(**call),
+        }
+    }
+}
+
+impl ToCrosswalk {
+    pub fn from_trigger(trigger: &Trigger<'_, CrosswalkEvent, StoplightState>) -> ToCrosswalk {
+        match trigger {
+            Trigger::Event(CrosswalkEvent::TimerTick) => ToCrosswalk::TimerTick,
+            Trigger::Event(CrosswalkEvent::ButtonPress) => ToCrosswalk::ButtonPress,
+            Trigger::Event(CrosswalkEvent::WalkGranted) => ToCrosswalk::WalkGranted,
+            Trigger::Event(CrosswalkEvent::Preempt) => ToCrosswalk::Preempt,
+            Trigger::Event(CrosswalkEvent::PreemptClear) => ToCrosswalk::PreemptClear,
+            Trigger::Event(CrosswalkEvent::FailSafe) => ToCrosswalk::FailSafe,
+            Trigger::Event(CrosswalkEvent::Resume) => ToCrosswalk::Resume,
+            Trigger::Context(state) => ToCrosswalk::StoplightState(**state),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+use std::sync::{mpsc, Mutex, PoisonError};
+
+use serde::Serialize;
+
+use crate::bus::StatusBus;
+use crate::crosswalk::CrosswalkState;
+use crate::messages::{FromMonitor, ToCrosswalk, ToMonitor, ToStoplight};
+use crate::stoplight::StoplightState;
+use crate::threads::Outbox;
+
+/// Indications that must never be shown together: pedestrians invited to
+/// cross while traffic may move.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Violation {
+    pub stoplight: StoplightState,
+    pub crosswalk: CrosswalkState,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "crosswalk {:?} while stoplight {:?}", self.crosswalk, self.stoplight)
+    }
+}
+
+// The core safety rule: Walk and BlinkingDontWalk only with Red
+pub fn conflicts(stoplight: StoplightState, crosswalk: CrosswalkState) -> bool {
+    stoplight != StoplightState::Red && matches!(crosswalk, CrosswalkState::Walk | CrosswalkState::BlinkingDontWalk)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResetError {
+    NotLatched,
+    // The machines have not reached all-red and DontWalk yet
+    NotSafe { stoplight: Option<StoplightState>, crosswalk: Option<CrosswalkState> },
+}
+
+impl fmt::Display for ResetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResetError::NotLatched => write!(f, "monitor is not in fail-safe"),
+            ResetError::NotSafe { stoplight, crosswalk } => {
+                write!(f, "signals not yet safe (stoplight {:?}, crosswalk {:?})", stoplight, crosswalk)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResetError {}
+
+/// Watches the latest stoplight and crosswalk indications.
+///
+/// The first conflicting pair latches the monitor. It stays latched, and
+/// reports nothing new, until `reset` is called with both machines showing
+/// all-red and DontWalk.
+#[derive(Debug, Default)]
+pub struct SafetyMonitor {
+    stoplight: Option<StoplightState>,
+    crosswalk: Option<CrosswalkState>,
+    latched: Option<Violation>,
+}
+
+impl SafetyMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns the violation if this update caused the monitor to latch
+    pub fn observe_stoplight(&mut self, state: StoplightState) -> Option<Violation> {
+        self.stoplight = Some(state);
+        self.check()
+    }
+
+    pub fn observe_crosswalk(&mut self, state: CrosswalkState) -> Option<Violation> {
+        self.crosswalk = Some(state);
+        self.check()
+    }
+
+    pub fn latched(&self) -> Option<Violation> {
+        self.latched
+    }
+
+    pub fn reset(&mut self) -> Result<(), ResetError> {
+        if self.latched.is_none() {
+            return Err(ResetError::NotLatched);
+        }
+        if self.stoplight != Some(StoplightState::Red) || self.crosswalk != Some(CrosswalkState::DontWalk) {
+            return Err(ResetError::NotSafe { stoplight: self.stoplight, crosswalk: self.crosswalk });
+        }
+        self.latched = None;
+        Ok(())
+    }
+
+    fn check(&mut self) -> Option<Violation> {
+        if self.latched.is_some() {
+            return None;
+        }
+        let (stoplight, crosswalk) = (self.stoplight?, self.crosswalk?);
+        if !conflicts(stoplight, crosswalk) {
+            return None;
+        }
+        let violation = Violation { stoplight, crosswalk };
+        self.latched = Some(violation);
+        Some(violation)
+    }
+}
+
+// Safety monitor thread function. Commands both machines into fail-safe on a
+// violation and out of it on an accepted reset; everything it sees or does is
+// relayed to `tx_main`.
+pub fn monitor_thread<T: Into<ToMonitor>>(
+    monitor: SafetyMonitor,
+    rx: StatusBus<T>,
+    tx_stoplight: mpsc::Sender<ToStoplight>,
+    tx_crosswalk: mpsc::Sender<ToCrosswalk>,
+    tx_main: Option<mpsc::Sender<FromMonitor>>,
+) -> SafetyMonitor {
+    let monitor = Mutex::new(monitor);
+    run_monitor(&monitor, rx, tx_stoplight, tx_crosswalk, tx_main);
+    monitor.into_inner().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The body of monitor_thread, on a monitor the supervisor also consults
+pub(crate) fn run_monitor<T: Into<ToMonitor>>(
+    monitor: &Mutex<SafetyMonitor>,
+    rx: StatusBus<T>,
+    tx_stoplight: mpsc::Sender<ToStoplight>,
+    tx_crosswalk: mpsc::Sender<ToCrosswalk>,
+    tx_main: Option<mpsc::Sender<FromMonitor>>,
+) {
+    let report = |message: FromMonitor| {
+        if let Some(ref sender) = tx_main {
+            if let Err(e) = sender.send(message) {
+                eprintln!("Monitor thread: failed to send report to main: {}", e);
+            }
+        }
+    };
+
+    while let Ok(message) = rx.recv() {
+        // A panic elsewhere while holding the lock leaves the observed states
+        // and the latch as they were, which is what the monitor keeps acting on
+        let mut monitor = monitor.lock().unwrap_or_else(PoisonError::into_inner);
+        if !dispatch(&mut monitor, message.into(), &tx_stoplight, &tx_crosswalk, report) {
+            break;
+        }
+    }
+}
+
+// Handle one status update for monitor_thread or the async driver. False on Shutdown.
+pub(crate) fn dispatch<S, C>(
+    monitor: &mut SafetyMonitor,
+    message: ToMonitor,
+    tx_stoplight: &S,
+    tx_crosswalk: &C,
+    report: impl Fn(FromMonitor),
+) -> bool
+where
+    S: Outbox<Message = ToStoplight>,
+    C: Outbox<Message = ToCrosswalk>,
+{
+    let violation = match message {
+        // While latched, a machine restarted after a panic has lost its
+        // fail-safe; latch it again
+        ToMonitor::Stoplight(state) => {
+            report(FromMonitor::Stoplight(state));
+            let violation = monitor.observe_stoplight(state);
+            if violation.is_none() && monitor.latched().is_some() && state != StoplightState::Red {
+                let _ = tx_stoplight.post(ToStoplight::FailSafe);
+            }
+            violation
+        }
+        ToMonitor::Crosswalk(state) => {
+            report(FromMonitor::Crosswalk(state));
+            let violation = monitor.observe_crosswalk(state);
+            let serving = matches!(state, CrosswalkState::Walk | CrosswalkState::BlinkingDontWalk);
+            if violation.is_none() && monitor.latched().is_some() && serving {
+                let _ = tx_crosswalk.post(ToCrosswalk::FailSafe);
+            }
+            violation
+        }
+        ToMonitor::Preemption(cycle) => {
+            report(FromMonitor::Preemption(cycle));
+            None
+        }
+        ToMonitor::TransitPriority(decision) => {
+            report(FromMonitor::TransitPriority(decision));
+            None
+        }
+        ToMonitor::Reset => {
+            let result = monitor.reset();
+            if result.is_ok() {
+                // Stoplight first, so the crosswalk never resumes ahead of it
+                let _ = tx_stoplight.post(ToStoplight::Resume);
+                let _ = tx_crosswalk.post(ToCrosswalk::Resume);
+            }
+            report(FromMonitor::Reset(result));
+            None
+        }
+        ToMonitor::Shutdown => return false,
+    };
+    if let Some(violation) = violation {
+        // Either machine may already have stopped; the latch stands regardless
+        let _ = tx_stoplight.post(ToStoplight::FailSafe);
+        let _ = tx_crosswalk.post(ToCrosswalk::FailSafe);
+        report(FromMonitor::Violation(violation));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crosswalk::CrosswalkFsm;
+    use crate::event_log::EventLog;
+    use crate::clock::VirtualClock;
+    use crate::stoplight::{StoplightFsm, StoplightTiming};
+    use crate::threads::{crosswalk_thread, stoplight_thread};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_monitor_latches_until_reset() {
+        let mut monitor = SafetyMonitor::new();
+        assert_eq!(monitor.observe_stoplight(StoplightState::Red), None);
+        assert_eq!(monitor.observe_crosswalk(CrosswalkState::Walk), None);
+        assert_eq!(monitor.reset(), Err(ResetError::NotLatched));
+
+        let violation = Violation { stoplight: StoplightState::Green, crosswalk: CrosswalkState::Walk };
+        assert_eq!(monitor.observe_stoplight(StoplightState::Green), Some(violation));
+        // Reported once, even as further conflicting updates arrive
+        assert_eq!(monitor.observe_crosswalk(CrosswalkState::BlinkingDontWalk), None);
+        assert_eq!(monitor.latched(), Some(violation));
+
+        monitor.observe_crosswalk(CrosswalkState::DontWalk);
+        assert!(matches!(monitor.reset(), Err(ResetError::NotSafe { .. })));
+        // A dark crosswalk is no conflict, but reset needs steady DontWalk
+        monitor.observe_crosswalk(CrosswalkState::Dark);
+        monitor.observe_stoplight(StoplightState::Red);
+        assert!(matches!(monitor.reset(), Err(ResetError::NotSafe { .. })));
+        monitor.observe_crosswalk(CrosswalkState::DontWalk);
+        assert_eq!(monitor.reset(), Ok(()));
+        assert_eq!(monitor.latched(), None);
+    }
+
+    #[test]
+    fn test_latched_monitor_fails_restarted_machines_safe() {
+        let (tx_stoplight, rx_stoplight) = mpsc::channel();
+        let (tx_crosswalk, rx_crosswalk) = mpsc::channel();
+        let mut monitor = SafetyMonitor::new();
+        let mut send = |message| dispatch(&mut monitor, message, &tx_stoplight, &tx_crosswalk, |_| {});
+        send(ToMonitor::Crosswalk(CrosswalkState::Walk));
+        send(ToMonitor::Stoplight(StoplightState::Green));
+        send(ToMonitor::Stoplight(StoplightState::Red));
+        send(ToMonitor::Crosswalk(CrosswalkState::DontWalk));
+        assert_eq!(rx_stoplight.try_iter().count(), 1);
+        assert_eq!(rx_crosswalk.try_iter().count(), 1);
+
+        // A restarted stoplight turning Green and crosswalk showing Walk
+        send(ToMonitor::Stoplight(StoplightState::Green));
+        send(ToMonitor::Crosswalk(CrosswalkState::Walk));
+        assert!(matches!(rx_stoplight.try_recv(), Ok(ToStoplight::FailSafe)));
+        assert!(matches!(rx_crosswalk.try_recv(), Ok(ToCrosswalk::FailSafe)));
+    }
+
+    #[test]
+    fn test_monitor_keeps_running_when_poisoned() {
+        let monitor = Mutex::new(SafetyMonitor::new());
+        thread::scope(|scope| {
+            let poisoner = scope.spawn(|| {
+                let _guard = monitor.lock().unwrap();
+                panic!("poisoning the monitor");
+            });
+            assert!(poisoner.join().is_err());
+        });
+        assert!(monitor.is_poisoned());
+
+        let (tx_stoplight, rx_stoplight) = mpsc::channel();
+        let (tx_crosswalk, _rx_crosswalk) = mpsc::channel();
+        let (tx_monitor, rx_monitor) = mpsc::channel::<ToMonitor>();
+        tx_monitor.send(ToMonitor::Crosswalk(CrosswalkState::Walk)).unwrap();
+        tx_monitor.send(ToMonitor::Stoplight(StoplightState::Green)).unwrap();
+        tx_monitor.send(ToMonitor::Shutdown).unwrap();
+        run_monitor(&monitor, StatusBus::from(rx_monitor), tx_stoplight, tx_crosswalk, None);
+        assert!(matches!(rx_stoplight.try_recv(), Ok(ToStoplight::FailSafe)));
+        assert!(monitor.into_inner().unwrap_or_else(PoisonError::into_inner).latched().is_some());
+    }
+
+    #[test]
+    fn test_monitor_thread_commands_fail_safe() {
+        let log = EventLog::new(Arc::new(VirtualClock::new()));
+        let (tx_stoplight, rx_stoplight) = mpsc::channel();
+        let (tx_crosswalk, rx_crosswalk) = mpsc::channel();
+        let (tx_monitor, rx_monitor) = mpsc::channel::<ToMonitor>();
+        let (tx_main, rx_main) = mpsc::channel();
+
+        // A stoplight about to turn Green while the crosswalk is showing Walk
+        let timing = StoplightTiming { red: 1, ..StoplightTiming::default() };
+        let stoplight = StoplightFsm::with_timing(StoplightState::Red, timing);
+        let crosswalk = CrosswalkFsm::with_timing(CrosswalkState::Walk, Default::default());
+        let handles = [
+            {
+                let (tx_monitor, log) = (tx_monitor.clone(), log.clone());
+                thread::spawn(move || stoplight_thread(stoplight, rx_stoplight, Some(tx_monitor), None, log))
+            },
+            {
+                let tx_monitor = tx_monitor.clone();
+                thread::spawn(move || crosswalk_thread(crosswalk, rx_crosswalk, Some(tx_monitor), None, log))
+            },
+        ];
+        let monitor = {
+            let (tx_stoplight, tx_crosswalk) = (tx_stoplight.clone(), tx_crosswalk.clone());
+            let rx_monitor = StatusBus::from(rx_monitor);
+            thread::spawn(move || monitor_thread(SafetyMonitor::new(), rx_monitor, tx_stoplight, tx_crosswalk, Some(tx_main)))
+        };
+
+        let next_violation = || rx_main.iter().find_map(|m| if let FromMonitor::Violation(v) = m { Some(v) } else { None });
+        tx_stoplight.send(ToStoplight::TimerTick).unwrap();
+        assert_eq!(next_violation(), Some(Violation { stoplight: StoplightState::Green, crosswalk: CrosswalkState::Walk }));
+
+        // Wait for both machines to report all-red and DontWalk, then reset
+        let mut states = (None, None);
+        while states != (Some(StoplightState::Red), Some(CrosswalkState::DontWalk)) {
+            match rx_main.recv().unwrap() {
+                FromMonitor::Stoplight(s) => states.0 = Some(s),
+                FromMonitor::Crosswalk(s) => states.1 = Some(s),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        tx_monitor.send(ToMonitor::Reset).unwrap();
+        assert_eq!(rx_main.recv().unwrap(), FromMonitor::Reset(Ok(())));
+
+        tx_stoplight.send(ToStoplight::Shutdown).unwrap();
+        tx_crosswalk.send(ToCrosswalk::Shutdown).unwrap();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        tx_monitor.send(ToMonitor::Shutdown).unwrap();
+        assert_eq!(monitor.join().unwrap().latched(), None);
+    }
+}
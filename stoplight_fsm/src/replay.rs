@@ -0,0 +1,233 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::VirtualClock;
+use crate::crosswalk::CrosswalkFsm;
+use crate::event_log::{EventLog, MessageSink, TransitionSink};
+use crate::messages::{FromCrosswalk, FromStoplight, Recorded, ToCrosswalk, ToStoplight};
+use crate::stoplight::StoplightFsm;
+use crate::threads::{crosswalk_thread, flush, stoplight_thread};
+
+/// One line of a recording, e.g.
+/// `{"timestamp_ms":2000,"tick":3,"to":"Crosswalk","message":"ButtonPress"}`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub timestamp_ms: u64, // Clock time the message was handled
+    pub tick: u64,         // Timer ticks the receiving machine had handled, including this one
+    #[serde(flatten)]
+    pub message: Recorded,
+}
+
+/// Writes every handled message as JSON lines, for [`replay`].
+///
+/// Each line is flushed as it is written so a recording survives a crash of
+/// the controller it was captured from.
+pub struct Recorder {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Recorder {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Recorder { out: Mutex::new(Box::new(out)) }
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Recorder::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl MessageSink for Recorder {
+    fn record(&self, timestamp_ms: u64, tick: u64, message: Recorded) {
+        let message = RecordedMessage { timestamp_ms, tick, message };
+        let line = serde_json::to_string(&message).expect("recorded messages serialize");
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{}", line).and_then(|_| out.flush()) {
+            eprintln!("Recorder: failed to write message: {}", e);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(String, io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(path, e) => write!(f, "cannot read {}: {}", path, e),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl RecordedMessage {
+    /// Load a recording written by [`Recorder`]. Blank lines are ignored.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<RecordedMessage>, ReplayError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| ReplayError::Io(path.display().to_string(), e))?;
+        RecordedMessage::read(BufReader::new(file)).map_err(|e| match e {
+            ReplayError::Io(_, e) => ReplayError::Io(path.display().to_string(), e),
+            other => other,
+        })
+    }
+
+    pub fn read(input: impl BufRead) -> Result<Vec<RecordedMessage>, ReplayError> {
+        let mut messages = Vec::new();
+        for (index, line) in input.lines().enumerate() {
+            let line = line.map_err(|e| ReplayError::Io(String::new(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = serde_json::from_str(&line)
+                .map_err(|e| ReplayError::Parse { line: index + 1, message: e.to_string() })?;
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
+
+/// Stands in for `timer_thread`, feeding a recording back to the machines.
+///
+/// The clock is moved to each message's timestamp and every message is
+/// flushed before the next, so transitions are reported with the recorded
+/// times and in the recorded order. The machines must not message each
+/// other: their state updates, calls and grants are part of the recording.
+pub fn replay_thread(
+    tx_stoplight: mpsc::Sender<ToStoplight>,
+    tx_crosswalk: mpsc::Sender<ToCrosswalk>,
+    recording: Vec<RecordedMessage>,
+    clock: Arc<VirtualClock>,
+) {
+    for recorded in recording {
+        clock.advance_to(Duration::from_millis(recorded.timestamp_ms));
+        let delivered = match recorded.message {
+            Recorded::Stoplight(message) => {
+                tx_stoplight.send(message).is_ok() && flush(&tx_stoplight, ToStoplight::Flush)
+            }
+            Recorded::Crosswalk(message) => {
+                tx_crosswalk.send(message).is_ok() && flush(&tx_crosswalk, ToCrosswalk::Flush)
+            }
+        };
+        if !delivered {
+            eprintln!("Replay thread: machine stopped before message at {} ms", recorded.timestamp_ms);
+            break;
+        }
+    }
+
+    if let Err(e) = tx_stoplight.send(ToStoplight::Shutdown) {
+        eprintln!("Replay thread: failed to send Shutdown to stoplight: {}", e);
+    }
+    if let Err(e) = tx_crosswalk.send(ToCrosswalk::Shutdown) {
+        eprintln!("Replay thread: failed to send Shutdown to crosswalk: {}", e);
+    }
+}
+
+/// Replay a recording through fresh machines on a virtual clock and report
+/// the transitions to `sink`. The machines must be built from the timing plan
+/// the recording was captured with.
+pub fn replay(
+    recording: Vec<RecordedMessage>,
+    stoplight: StoplightFsm,
+    crosswalk: CrosswalkFsm,
+    sink: Arc<dyn TransitionSink>,
+) {
+    let clock = Arc::new(VirtualClock::new());
+    let log = EventLog::new(clock.clone()).with_sink(sink);
+    let (tx_stoplight, rx_stoplight) = mpsc::channel();
+    let (tx_crosswalk, rx_crosswalk) = mpsc::channel();
+
+    let replayer = thread::spawn(move || replay_thread(tx_stoplight, tx_crosswalk, recording, clock));
+    let stoplight = {
+        let log = log.clone();
+        let tx_main = None::<mpsc::Sender<FromStoplight>>;
+        thread::spawn(move || stoplight_thread(stoplight, rx_stoplight, tx_main, None, log))
+    };
+    let tx_main = None::<mpsc::Sender<FromCrosswalk>>;
+    let crosswalk = thread::spawn(move || crosswalk_thread(crosswalk, rx_crosswalk, tx_main, None, log));
+
+    for handle in [replayer, stoplight, crosswalk] {
+        handle.join().expect("Replay thread panicked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::config::{ButtonSchedule, Config};
+    use crate::event_log::tests::SharedBuf;
+    use crate::event_log::MemorySink;
+    use crate::stoplight::StoplightState;
+    use crate::threads::timer_thread;
+
+    #[test]
+    fn test_recorded_message_format() {
+        let message = RecordedMessage {
+            timestamp_ms: 4000,
+            tick: 4,
+            message: Recorded::Crosswalk(ToCrosswalk::StoplightState(StoplightState::Green)),
+        };
+        let line = serde_json::to_string(&message).unwrap();
+        assert_eq!(line, r#"{"timestamp_ms":4000,"tick":4,"to":"Crosswalk","message":{"StoplightState":"Green"}}"#);
+
+        let input = format!("{}\n\n{}\n", line, r#"{"timestamp_ms":0,"tick":1,"to":"Stoplight","message":"TimerTick"}"#);
+        let messages = RecordedMessage::read(input.as_bytes()).unwrap();
+        assert!(matches!(messages[1].message, Recorded::Stoplight(ToStoplight::TimerTick)));
+
+        let err = RecordedMessage::read(r#"{"timestamp_ms":0,"tick":1,"to":"Main"}"#.as_bytes()).unwrap_err();
+        assert!(matches!(err, ReplayError::Parse { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_replay_reproduces_transition_trace() {
+        let config = Config {
+            ticks: 500,
+            buttons: ButtonSchedule::Random { probability: 0.3, seed: 11 },
+            ..Config::default()
+        };
+
+        // Capture a run, as a field controller would with --record
+        let clock = Arc::new(VirtualClock::auto());
+        let recorded = Arc::new(MemorySink::new());
+        let buf = SharedBuf::default();
+        let log = EventLog::new(clock.clone())
+            .with_sink(recorded.clone())
+            .with_recorder(Arc::new(Recorder::new(buf.clone())));
+        let (tx_stoplight, rx_stoplight) = mpsc::channel();
+        let (tx_crosswalk, rx_crosswalk) = mpsc::channel();
+        let options = config.timer_options();
+        let timer = {
+            let (tx_stoplight, tx_crosswalk, clock) = (tx_stoplight.clone(), tx_crosswalk.clone(), clock.clone());
+            thread::spawn(move || timer_thread(tx_stoplight, tx_crosswalk, options, clock as Arc<dyn Clock>))
+        };
+        let stoplight = {
+            let (fsm, log) = (config.stoplight_fsm(), log.clone());
+            let tx_main = None::<mpsc::Sender<FromStoplight>>;
+            thread::spawn(move || stoplight_thread(fsm, rx_stoplight, tx_main, Some(tx_crosswalk), log))
+        };
+        let fsm = config.crosswalk_fsm();
+        let tx_main = None::<mpsc::Sender<FromCrosswalk>>;
+        let crosswalk = thread::spawn(move || crosswalk_thread(fsm, rx_crosswalk, tx_main, Some(tx_stoplight), log));
+        for handle in [timer, stoplight, crosswalk] {
+            handle.join().unwrap();
+        }
+
+        let recording = RecordedMessage::read(buf.text().as_bytes()).unwrap();
+        let replayed = Arc::new(MemorySink::new());
+        replay(recording, config.stoplight_fsm(), config.crosswalk_fsm(), replayed.clone());
+
+        assert!(recorded.records().iter().any(|r| r.to == "Walk"));
+        assert_eq!(replayed.records(), recorded.records());
+    }
+}
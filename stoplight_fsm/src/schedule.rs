@@ -0,0 +1,391 @@
+// Time-of-day and day-of-week selection of timing plans. Dates are local
+// Gregorian dates; there is no notion of time zones or daylight saving.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::stoplight::{FlashMode, StoplightTiming};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        let date = Date { year, month, day };
+        ((1..=12).contains(&month) && day >= 1 && Date::from_days(date.days()) == date).then_some(date)
+    }
+
+    // Days since 1970-01-01 (H. Hinnant's days_from_civil)
+    fn days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = i64::from((self.month + 9) % 12);
+        let day_of_year = (153 * month + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    fn from_days(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+        let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date { year: year as i32, month, day }
+    }
+
+    pub fn add_days(self, days: i64) -> Date {
+        Date::from_days(self.days() + days)
+    }
+
+    pub fn weekday(self) -> DayType {
+        // 1970-01-01 was a Thursday
+        const WEEK: [DayType; 7] =
+            [DayType::Thu, DayType::Fri, DayType::Sat, DayType::Sun, DayType::Mon, DayType::Tue, DayType::Wed];
+        WEEK[self.days().rem_euclid(7) as usize]
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+        let date = match parts[..] {
+            [year, month, day] => match (year.parse(), month.parse(), day.parse()) {
+                (Ok(year), Ok(month), Ok(day)) => Date::new(year, month, day),
+                _ => None,
+            },
+            _ => None,
+        };
+        date.ok_or_else(|| format!("expected a date as YYYY-MM-DD, got {:?}", s))
+    }
+}
+
+impl TryFrom<String> for Date {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Date> for String {
+    fn from(date: Date) -> Self {
+        date.to_string()
+    }
+}
+
+/// Minutes since midnight, written "HH:MM".
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u32);
+
+impl TimeOfDay {
+    pub fn new(hour: u32, minute: u32) -> Option<TimeOfDay> {
+        (hour < 24 && minute < 60).then_some(TimeOfDay(hour * 60 + minute))
+    }
+
+    pub fn minutes(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .split_once(':')
+            .and_then(|(hour, minute)| TimeOfDay::new(hour.parse().ok()?, minute.parse().ok()?))
+            .ok_or_else(|| format!("expected a time as HH:MM, got {:?}", s))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+/// Local date and time, written "YYYY-MM-DD HH:MM" (or with a `T`).
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DateTime {
+    pub date: Date,
+    pub time: TimeOfDay,
+}
+
+impl DateTime {
+    /// This time plus `elapsed`, to the minute.
+    pub fn after(self, elapsed: Duration) -> DateTime {
+        let minutes = u64::from(self.time.minutes()) + elapsed.as_secs() / 60;
+        let days = minutes / u64::from(MINUTES_PER_DAY);
+        DateTime {
+            date: self.date.add_days(days as i64),
+            time: TimeOfDay((minutes % u64::from(MINUTES_PER_DAY)) as u32),
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.date, self.time)
+    }
+}
+
+impl FromStr for DateTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (date, time) = s
+            .trim()
+            .split_once([' ', 'T'])
+            .ok_or_else(|| format!("expected a date and time as YYYY-MM-DD HH:MM, got {:?}", s))?;
+        Ok(DateTime { date: date.parse()?, time: time.parse()? })
+    }
+}
+
+impl TryFrom<String> for DateTime {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DateTime> for String {
+    fn from(time: DateTime) -> Self {
+        time.to_string()
+    }
+}
+
+/// Kind of day a schedule entry applies to. Holiday dates are `Holiday`
+/// rather than their weekday.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum DayType {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+    Holiday,
+}
+
+/// Switch to `plan` at `at` on the listed days, or on every day if none
+/// are listed.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<DayType>,
+    pub at: TimeOfDay,
+    pub plan: String,
+}
+
+impl ScheduleEntry {
+    fn applies_on(&self, day: DayType) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+/// Calendar of plan changes. `start` is the local time at tick 0.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Schedule {
+    pub start: DateTime,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub holidays: Vec<Date>,
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            start: DateTime { date: Date { year: 2024, month: 1, day: 1 }, time: TimeOfDay(0) },
+            holidays: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Schedule {
+    pub fn day_type(&self, date: Date) -> DayType {
+        if self.holidays.contains(&date) {
+            DayType::Holiday
+        } else {
+            date.weekday()
+        }
+    }
+
+    /// Name of the plan in effect at `time`: the latest entry at or before
+    /// it, looking back over the previous week if today has none yet.
+    pub fn plan_at(&self, time: DateTime) -> Option<&str> {
+        (0..=7).find_map(|days_back| {
+            let date = time.date.add_days(-days_back);
+            let day = self.day_type(date);
+            self.entries
+                .iter()
+                .filter(|entry| entry.applies_on(day) && (days_back > 0 || entry.at <= time.time))
+                .max_by_key(|entry| entry.at)
+                .map(|entry| entry.plan.as_str())
+        })
+    }
+}
+
+/// Stoplight timing selected by a schedule. A plan with `flash` set runs
+/// flashing operation instead.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TimingPlan {
+    pub name: String,
+    pub timing: StoplightTiming,
+    pub flash: Option<FlashMode>,
+}
+
+/// Tracks the plan in effect as the clock advances.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PlanScheduler {
+    schedule: Schedule,
+    plans: BTreeMap<String, TimingPlan>,
+    active: Option<String>,
+}
+
+impl PlanScheduler {
+    pub fn new(schedule: Schedule, plans: impl IntoIterator<Item = TimingPlan>) -> Self {
+        let plans = plans.into_iter().map(|plan| (plan.name.clone(), plan)).collect();
+        PlanScheduler { schedule, plans, active: None }
+    }
+
+    pub fn plan_at(&self, elapsed: Duration) -> Option<&TimingPlan> {
+        let name = self.schedule.plan_at(self.schedule.start.after(elapsed))?;
+        self.plans.get(name)
+    }
+
+    /// The plan in effect `elapsed` after the start, if it differs from the
+    /// one returned last time.
+    pub fn poll(&mut self, elapsed: Duration) -> Option<&TimingPlan> {
+        let name = self.plan_at(elapsed)?.name.clone();
+        if self.active.as_ref() == Some(&name) {
+            return None;
+        }
+        let plan = self.plans.get(&name);
+        self.active = Some(name);
+        plan
+    }
+
+    pub fn active(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    fn entry(days: &[DayType], at: &str, plan: &str) -> ScheduleEntry {
+        ScheduleEntry { days: days.to_vec(), at: at.parse().unwrap(), plan: plan.to_string() }
+    }
+
+    #[test]
+    fn test_calendar_arithmetic() {
+        assert_eq!(date("2024-02-28").add_days(1), date("2024-02-29"));
+        assert_eq!(date("2023-12-31").add_days(1), date("2024-01-01"));
+        assert_eq!(date("2026-10-18").weekday(), DayType::Sun);
+        assert_eq!(date("1969-12-31").weekday(), DayType::Wed);
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+
+        let start: DateTime = "2024-03-01T23:30".parse().unwrap();
+        assert_eq!(start.after(HOUR).to_string(), "2024-03-02 00:30");
+    }
+
+    #[test]
+    fn test_plan_by_time_weekday_and_holiday() {
+        use DayType::*;
+        let weekdays = [Mon, Tue, Wed, Thu, Fri];
+        let schedule = Schedule {
+            start: "2024-12-23 00:00".parse().unwrap(), // Monday
+            holidays: vec![date("2024-12-25")],
+            entries: vec![
+                entry(&weekdays, "06:30", "am_peak"),
+                entry(&weekdays, "09:30", "midday"),
+                entry(&weekdays, "16:00", "pm_peak"),
+                entry(&[], "22:00", "night"),
+                entry(&[Sat, Sun, Holiday], "08:00", "weekend"),
+            ],
+        };
+        let at = |s: &str| schedule.plan_at(s.parse().unwrap());
+        assert_eq!(at("2024-12-23 00:00"), Some("night")); // Carried over from Sunday
+        assert_eq!(at("2024-12-23 06:29"), Some("night"));
+        assert_eq!(at("2024-12-23 06:30"), Some("am_peak"));
+        assert_eq!(at("2024-12-24 17:00"), Some("pm_peak"));
+        assert_eq!(at("2024-12-25 07:00"), Some("night"));
+        assert_eq!(at("2024-12-25 10:00"), Some("weekend")); // Christmas, a Wednesday
+        assert_eq!(at("2024-12-28 10:00"), Some("weekend"));
+        assert_eq!(Schedule::default().plan_at(schedule.start), None);
+    }
+
+    #[test]
+    fn test_scheduler_reports_changes_only() {
+        let plan = |name: &str, green| TimingPlan {
+            name: name.to_string(),
+            timing: StoplightTiming { green, ..StoplightTiming::default() },
+            flash: None,
+        };
+        let schedule = Schedule {
+            start: "2024-01-01 06:00".parse().unwrap(),
+            holidays: Vec::new(),
+            entries: vec![entry(&[], "07:00", "peak"), entry(&[], "19:00", "off_peak")],
+        };
+        let mut scheduler = PlanScheduler::new(schedule, [plan("peak", 8), plan("off_peak", 4)]);
+        assert_eq!(scheduler.poll(Duration::ZERO).map(|p| p.timing.green), Some(4));
+        assert_eq!(scheduler.poll(HOUR / 2), None);
+        assert_eq!(scheduler.poll(HOUR).map(|p| p.timing.green), Some(8));
+        assert_eq!(scheduler.active(), Some("peak"));
+        assert_eq!(scheduler.poll(HOUR * 2), None);
+    }
+}
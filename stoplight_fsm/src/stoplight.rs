@@ -0,0 +1,1727 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coordination::{Coordination, TransitionMode};
+use crate::crosswalk::PedestrianCall;
+use crate::fsm::StateMachine;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum StoplightState {
+    Red,
+    Green,
+    Yellow,
+    FlashingRed,
+    FlashingYellow,
+}
+
+impl StoplightState {
+    pub fn is_flashing(self) -> bool {
+        matches!(self, StoplightState::FlashingRed | StoplightState::FlashingYellow)
+    }
+}
+
+/// Flashing operation. `Red` is fault flash and starts at once; `Yellow`
+/// (night flash on the major approach) starts at the next Red.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum FlashMode {
+    Red,
+    Yellow,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StoplightEvent {
+    TimerTick,
+    FailSafe, // Go all-red at once and ignore the timer until Resume
+    Resume,   // Leave fail-safe, restarting the Red interval
+    // Go all-red at once and ignore the timer until Release, keeping preemption
+    // and rail state; both are served again from their entry once released
+    Hold,
+    Release,
+    Flash(FlashMode),
+    // Back to normal cycling: flashing yellow through a full Yellow, flashing red through a full Red
+    ExitFlash,
+    DetectorCall, // Vehicle actuation from a loop, video or radar detector
+    Preempt(PreemptRequest),
+    PreemptClear(u8), // The emergency vehicle on this approach has passed
+    GateDown,         // Railroad crossing gates lowering for a train, overrides emergency preemption
+    GateUp,           // Train gone, gates up
+    TransitRequest,   // Bus asking for priority on this head's approach
+    ChangeTiming(StoplightTiming), // New timing plan, applied at the next Red at rest
+}
+
+/// Emergency vehicle preemption request. A higher `priority` from another
+/// approach takes over an active preemption; anything else is ignored.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct PreemptRequest {
+    pub approach: u8,
+    pub priority: u8,
+}
+
+/// Steps of a preemption cycle.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PreemptPhase {
+    Entry,    // Safe exit from the current state: full Yellow, all-red, pedestrians clear
+    Dwell,    // Green for the preempted approach, Red for any other
+    Recovery, // Back through Yellow and the all-red before normal timing resumes
+}
+
+/// How a preemption cycle ended.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum PreemptOutcome {
+    Completed,
+    Superseded, // A higher priority request took over
+    Cancelled,  // Fail-safe or fault flash
+}
+
+/// Record of one preemption cycle, with the ticks spent in each phase.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct PreemptionCycle {
+    pub request: PreemptRequest,
+    pub entry_ticks: u32,
+    pub dwell_ticks: u32,
+    pub recovery_ticks: u32,
+    pub outcome: PreemptOutcome,
+}
+
+impl fmt::Display for PreemptionCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "approach {} priority {} {:?}: entry {}, dwell {}, recovery {} ticks",
+            self.request.approach,
+            self.request.priority,
+            self.outcome,
+            self.entry_ticks,
+            self.dwell_ticks,
+            self.recovery_ticks
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Preemption {
+    phase: PreemptPhase,
+    target: StoplightState, // Green when this head's approach is preempted, Red otherwise
+    cycle: PreemptionCycle,
+}
+
+/// Railroad crossing near the intersection. On gate-down the approach
+/// crossing the tracks gets `track_clearance` ticks of green to move queued
+/// vehicles off them; then, while the train is present, only the approaches
+/// in `dwell_approaches` may run green.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RailCrossing {
+    pub track_approach: u8,
+    pub track_clearance: u32,
+    pub dwell_approaches: Vec<u8>, // Never the track approach
+}
+
+impl Default for RailCrossing {
+    fn default() -> Self {
+        RailCrossing { track_approach: 1, track_clearance: 5, dwell_approaches: Vec::new() }
+    }
+}
+
+/// Steps of a railroad preemption.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum RailPhase {
+    Entry,          // Safe exit from the current state, as for emergency preemption
+    TrackClearance, // Green for the track approach, Red for any other
+    Dwell,          // Train present: green only for the dwell approaches
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RailPreemption {
+    phase: RailPhase,
+    ticks: u32, // Ticks in the current phase
+}
+
+// What a head does during a railroad preemption
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum RailRole {
+    Track,
+    Dwell,
+    Hold, // Red throughout
+}
+
+/// Timed sub-states of Red, in order. Red entered after Yellow (or cut
+/// short from Green) starts in `Clearance`; any other Red starts in `Rest`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RedInterval {
+    Clearance, // All-red: no conflicting head may start its green yet
+    Revert,    // Red-revert minimum: this head may not go back to Green yet
+    Rest,
+}
+
+/// Where an actuated stoplight waits when nobody is asking for a change.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum RestIn {
+    #[default]
+    Red, // Green only on a vehicle call
+    Green, // Green until the crosswalk calls
+}
+
+/// Demand-responsive green. `StoplightTiming::green` becomes the minimum
+/// green; each detector call extends it by `passage` ticks, up to
+/// `max_green` counted from the first conflicting demand.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Actuation {
+    pub max_green: u32,
+    pub passage: u32, // Gap in ticks after the last call that ends green
+    pub rest: RestIn,
+}
+
+impl Default for Actuation {
+    fn default() -> Self {
+        Actuation { max_green: 10, passage: 2, rest: RestIn::Red }
+    }
+}
+
+/// Transit signal priority. A bus request in Green extends it by
+/// `max_extension` ticks; in Red it ends Red up to `max_early` ticks early,
+/// though never before the all-red, the red revert or a pedestrian interval
+/// is over. After a grant, requests are denied for `lockout` ticks.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransitPriority {
+    pub max_extension: u32,
+    pub max_early: u32,
+    pub lockout: u32,
+}
+
+impl Default for TransitPriority {
+    fn default() -> Self {
+        TransitPriority { max_extension: 3, max_early: 2, lockout: 20 }
+    }
+}
+
+/// Why a transit priority request was turned down.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum TransitDenial {
+    NotConfigured,
+    Lockout,            // Too soon after the last grant
+    PedestrianInterval, // Red is held for the crosswalk
+    Preempted,          // Emergency or railroad preemption in control
+    Unavailable,        // Yellow, flashing or fail-safe
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum TransitOutcome {
+    Extended(u32),   // Green lengthened by this many ticks
+    EarlyGreen(u32), // Red shortened by up to this many ticks
+    Denied(TransitDenial),
+}
+
+/// Record of one transit priority request and what was done about it.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct TransitDecision {
+    pub state: StoplightState, // When the request arrived
+    pub outcome: TransitOutcome,
+}
+
+impl fmt::Display for TransitDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.outcome {
+            TransitOutcome::Extended(ticks) => write!(f, "{:?}: extended by {} ticks", self.state, ticks),
+            TransitOutcome::EarlyGreen(ticks) => write!(f, "{:?}: early green, up to {} ticks", self.state, ticks),
+            TransitOutcome::Denied(reason) => write!(f, "{:?}: denied, {:?}", self.state, reason),
+        }
+    }
+}
+
+// State durations in TimerTicks
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct StoplightTiming {
+    pub red: u32,
+    pub green: u32, // Minimum green when actuated
+    pub yellow: u32,
+    pub red_clearance: u32, // All-red ticks at the start of Red, 0 for none
+    pub red_revert: u32,    // Minimum ticks in Red after Yellow, counted from its start
+    pub actuation: Option<Actuation>, // None for fixed-time operation
+    // Common cycle with other controllers; green and red then make up the cycle
+    pub coordination: Option<Coordination>,
+}
+
+impl Default for StoplightTiming {
+    fn default() -> Self {
+        StoplightTiming {
+            red: StoplightFsm::RED_DURATION,
+            green: StoplightFsm::GREEN_DURATION,
+            yellow: StoplightFsm::YELLOW_DURATION,
+            red_clearance: 0,
+            red_revert: 0,
+            actuation: None,
+            coordination: None,
+        }
+    }
+}
+
+pub struct StoplightFsm {
+    pub(crate) state: StoplightState,
+    pub(crate) timer_ticks_in_state: u32, // Counter for how long we've been in the current state
+    red_interval: RedInterval,
+    fail_safe: bool,
+    held: bool,                       // All-red hold, see StoplightEvent::Hold
+    flash_request: Option<FlashMode>, // Flash waiting for the head to reach Red
+    pedestrian_call: PedestrianCall,  // Latest call reported by the crosswalk
+    vehicle_call: bool,               // Detector call waiting for the next Green
+    ticks_since_call: u32,            // Gap timer while actuated Green
+    max_timer: u32,                   // Green ticks with conflicting demand
+    cause: Option<&'static str>,      // Why the last event changed state, when not plain timing
+    approach: u8,                     // Approach this head controls, for preemption
+    preemption: Option<Preemption>,
+    preemption_cycles: Vec<PreemptionCycle>, // Every finished preemption, oldest first
+    rail_crossing: Option<RailCrossing>,     // Without one, a head holds Red while the gates are down
+    rail: Option<RailPreemption>,
+    deferred_preempt: Option<PreemptRequest>, // Emergency request waiting for the train to pass
+    transit: Option<TransitPriority>,
+    green_extension: u32,                    // Transit priority ticks added to this Green
+    red_truncation: u32,                     // Transit priority ticks taken off this Red
+    ticks_since_grant: Option<u32>,          // For the transit priority lockout
+    transit_decisions: Vec<TransitDecision>, // Every transit request, oldest first
+    pending_timing: Option<StoplightTiming>,  // Timing plan waiting for a safe point
+    cycle_clock: u32,                        // Ticks since the master reference, for coordination
+    coordinated_green: Option<u32>,          // This Green's length after transition correction
+    sync_error: Option<u32>,                 // Cycle position at the last coordinated green start
+    timing: StoplightTiming,
+}
+
+impl Default for StoplightFsm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoplightFsm {
+    pub fn new() -> Self {
+        Self::with_timing(StoplightState::Red, StoplightTiming::default()) // Initial state
+    }
+
+    pub fn with_timing(initial: StoplightState, timing: StoplightTiming) -> Self {
+        StoplightFsm {
+            state: initial,
+            timer_ticks_in_state: 0,
+            red_interval: RedInterval::Rest,
+            fail_safe: false,
+            held: false,
+            flash_request: None,
+            pedestrian_call: PedestrianCall::Idle,
+            vehicle_call: false,
+            ticks_since_call: 0,
+            max_timer: 0,
+            cause: None,
+            approach: 1,
+            preemption: None,
+            preemption_cycles: Vec::new(),
+            rail_crossing: None,
+            rail: None,
+            deferred_preempt: None,
+            transit: None,
+            green_extension: 0,
+            red_truncation: 0,
+            ticks_since_grant: None,
+            transit_decisions: Vec::new(),
+            pending_timing: None,
+            cycle_clock: 0,
+            coordinated_green: None,
+            sync_error: None,
+            timing,
+        }
+    }
+
+    // Constants for state durations (in TimerTicks)
+    pub const RED_DURATION: u32 = 5;
+    pub const GREEN_DURATION: u32 = 4;
+    pub const YELLOW_DURATION: u32 = 1;
+
+    pub fn ticks_in_state(&self) -> u32 {
+        self.timer_ticks_in_state
+    }
+
+    pub fn timing(&self) -> &StoplightTiming {
+        &self.timing
+    }
+
+    pub fn pending_timing(&self) -> Option<&StoplightTiming> {
+        self.pending_timing.as_ref()
+    }
+
+    /// How far from its offset the last coordinated green started, in ticks
+    /// into the cycle; 0 when in step.
+    pub fn sync_error(&self) -> Option<u32> {
+        self.sync_error
+    }
+
+    pub fn is_fail_safe(&self) -> bool {
+        self.fail_safe
+    }
+
+    pub fn flash_requested(&self) -> Option<FlashMode> {
+        self.flash_request
+    }
+
+    pub fn has_vehicle_call(&self) -> bool {
+        self.vehicle_call
+    }
+
+    pub fn with_approach(mut self, approach: u8) -> Self {
+        self.approach = approach;
+        self
+    }
+
+    pub fn approach(&self) -> u8 {
+        self.approach
+    }
+
+    pub fn preemption(&self) -> Option<(PreemptRequest, PreemptPhase)> {
+        self.preemption.map(|p| (p.cycle.request, p.phase))
+    }
+
+    pub fn preemption_cycles(&self) -> &[PreemptionCycle] {
+        &self.preemption_cycles
+    }
+
+    pub fn with_rail_crossing(mut self, rail_crossing: RailCrossing) -> Self {
+        self.rail_crossing = Some(rail_crossing);
+        self
+    }
+
+    pub fn rail_preemption(&self) -> Option<RailPhase> {
+        self.rail.map(|rail| rail.phase)
+    }
+
+    pub fn with_transit_priority(mut self, transit: TransitPriority) -> Self {
+        self.transit = Some(transit);
+        self
+    }
+
+    pub fn transit_decisions(&self) -> &[TransitDecision] {
+        &self.transit_decisions
+    }
+
+    /// This machine without its logged cycles and decisions, which
+    /// `restarted` does not carry over: cheap enough to take after every step.
+    pub(crate) fn checkpoint(&self) -> Self {
+        StoplightFsm {
+            preemption_cycles: Vec::new(),
+            transit_decisions: Vec::new(),
+            rail_crossing: self.rail_crossing.clone(),
+            ..*self
+        }
+    }
+
+    /// A fresh machine with this one's configuration, in Red unless it was
+    /// flashing. For restarting a controller whose machine panicked: a latch,
+    /// a hold, a flash request, the pedestrian call and the gates being down
+    /// carry over, a Red entered from Green or Yellow or not yet at rest
+    /// starts over with its all-red, and a preemption not yet cleared is
+    /// served again from its entry. The logged cycles and decisions are not
+    /// carried over.
+    pub fn restarted(&self) -> Self {
+        let timing = self.pending_timing.unwrap_or(self.timing);
+        let initial = if self.state.is_flashing() { self.state } else { StoplightState::Red };
+        let mut fsm = StoplightFsm::with_timing(initial, timing).with_approach(self.approach);
+        fsm.rail_crossing = self.rail_crossing.clone();
+        fsm.transit = self.transit;
+        fsm.fail_safe = self.fail_safe;
+        fsm.held = self.held;
+        fsm.flash_request = self.flash_request;
+        fsm.pedestrian_call = self.pedestrian_call;
+        // Traffic may still be clearing from a Green the crash cut short
+        let at_rest = self.state == StoplightState::Red && self.red_interval == RedInterval::Rest;
+        if !at_rest && !initial.is_flashing() {
+            fsm.red_interval = fsm.red_interval_after(0);
+        }
+        let preemption = self.preemption.filter(|preemption| preemption.phase != PreemptPhase::Recovery);
+        fsm.preemption = preemption.map(|preemption| {
+            let cycle = PreemptionCycle { entry_ticks: 0, dwell_ticks: 0, recovery_ticks: 0, ..preemption.cycle };
+            Preemption { phase: PreemptPhase::Entry, cycle, ..preemption }
+        });
+        // The track is cleared again
+        fsm.rail = self.rail.map(|_| RailPreemption { phase: RailPhase::Entry, ticks: 0 });
+        fsm.deferred_preempt = self.deferred_preempt;
+        fsm
+    }
+
+    /// Whether any preemption, emergency or railroad, is in control of the head.
+    pub fn is_preempted(&self) -> bool {
+        self.preemption.is_some() || self.rail.is_some()
+    }
+
+    /// Whether Red is held for the crosswalk. A waiting call is granted once
+    /// the all-red is over, and Red then lasts until the crosswalk is back to
+    /// DontWalk, however long the pedestrian interval is.
+    pub fn pedestrian_hold(&self) -> bool {
+        self.state == StoplightState::Red
+            && !self.fail_safe
+            && match self.pedestrian_call {
+                PedestrianCall::Idle => false,
+                PedestrianCall::Waiting => self.red_interval != RedInterval::Clearance,
+                PedestrianCall::Serving => true,
+            }
+    }
+
+    // Sub-state of Red, None in any other state
+    pub fn red_interval(&self) -> Option<RedInterval> {
+        (self.state == StoplightState::Red).then_some(self.red_interval)
+    }
+
+    // Green ends by timing alone, or for an actuated head when the gap or
+    // maximum runs out while something else is waiting
+    fn green_done(&mut self) -> bool {
+        let min_green = self.coordinated_green.unwrap_or(self.timing.green) + self.green_extension;
+        let Some(actuation) = self.timing.actuation else {
+            return self.timer_ticks_in_state >= min_green;
+        };
+        self.ticks_since_call += 1;
+        let demand = actuation.rest == RestIn::Red || self.pedestrian_call != PedestrianCall::Idle;
+        if !demand {
+            return false; // Rest in Green
+        }
+        self.max_timer += 1;
+        if self.max_timer >= actuation.max_green + self.green_extension {
+            self.cause = Some("max-out");
+        } else if self.timer_ticks_in_state >= min_green && self.ticks_since_call >= actuation.passage {
+            self.cause = Some("gap-out");
+        }
+        self.cause.is_some()
+    }
+
+    fn decide_transit(&mut self) -> TransitOutcome {
+        let Some(transit) = self.transit else {
+            return TransitOutcome::Denied(TransitDenial::NotConfigured);
+        };
+        if self.is_preempted() {
+            return TransitOutcome::Denied(TransitDenial::Preempted);
+        }
+        if self.fail_safe || !matches!(self.state, StoplightState::Red | StoplightState::Green) {
+            return TransitOutcome::Denied(TransitDenial::Unavailable);
+        }
+        if self.ticks_since_grant.is_some_and(|ticks| ticks < transit.lockout) {
+            return TransitOutcome::Denied(TransitDenial::Lockout);
+        }
+        if self.state == StoplightState::Green {
+            self.green_extension = transit.max_extension;
+            self.ticks_since_grant = Some(0);
+            return TransitOutcome::Extended(transit.max_extension);
+        }
+        if self.pedestrian_hold() {
+            return TransitOutcome::Denied(TransitDenial::PedestrianInterval);
+        }
+        self.red_truncation = transit.max_early;
+        self.vehicle_call = true; // The bus is a vehicle call for an actuated head
+        self.ticks_since_grant = Some(0);
+        TransitOutcome::EarlyGreen(transit.max_early)
+    }
+
+    fn start_preemption(&mut self, request: PreemptRequest) {
+        if let Some(active) = self.preemption {
+            let current = active.cycle.request;
+            if request.approach == current.approach {
+                // Repeated or renewed request; a cleared one goes back to dwell
+                let preemption = self.preemption.as_mut().unwrap();
+                preemption.cycle.request.priority = current.priority.max(request.priority);
+                if preemption.phase == PreemptPhase::Recovery {
+                    preemption.phase = PreemptPhase::Entry;
+                }
+                return;
+            }
+            if request.priority <= current.priority {
+                return;
+            }
+            self.finish_preemption(PreemptOutcome::Superseded);
+        }
+        let target = if request.approach == self.approach { StoplightState::Green } else { StoplightState::Red };
+        let cycle = PreemptionCycle { request, entry_ticks: 0, dwell_ticks: 0, recovery_ticks: 0, outcome: PreemptOutcome::Completed };
+        self.preemption = Some(Preemption { phase: PreemptPhase::Entry, target, cycle });
+    }
+
+    fn finish_preemption(&mut self, outcome: PreemptOutcome) {
+        if let Some(preemption) = self.preemption.take() {
+            self.preemption_cycles.push(PreemptionCycle { outcome, ..preemption.cycle });
+        }
+    }
+
+    // Next state of a head under preemption. Yellow always runs its full
+    // time, and nothing turns Green during the all-red, the red revert or a
+    // pedestrian interval. A head preempted in Red also waits out a Yellow
+    // and all-red of its own timing, the time a conflicting head needs to
+    // clear from Green.
+    fn advance_preemption(&mut self) -> StoplightState {
+        let Some(preemption) = self.preemption.filter(|_| !self.held) else { return self.state };
+        let conflicts_cleared = preemption.cycle.entry_ticks > self.timing.yellow.saturating_add(self.timing.red_clearance);
+        let ready_for_green = self.red_interval == RedInterval::Rest && !self.pedestrian_hold() && conflicts_cleared;
+        let (phase, next_state) = match (preemption.phase, self.state) {
+            (_, StoplightState::Yellow) if self.timer_ticks_in_state >= self.timing.yellow => {
+                (preemption.phase, StoplightState::Red)
+            }
+            (_, StoplightState::Yellow) => (preemption.phase, StoplightState::Yellow),
+            (PreemptPhase::Entry, state) if state == preemption.target => (PreemptPhase::Dwell, state),
+            (PreemptPhase::Entry, StoplightState::Green) => (PreemptPhase::Entry, StoplightState::Yellow),
+            (PreemptPhase::Entry, StoplightState::Red) if ready_for_green => (PreemptPhase::Dwell, StoplightState::Green),
+            (PreemptPhase::Recovery, StoplightState::Green) => (PreemptPhase::Recovery, StoplightState::Yellow),
+            (PreemptPhase::Recovery, StoplightState::Red) if self.red_interval != RedInterval::Clearance => {
+                self.finish_preemption(PreemptOutcome::Completed);
+                return StoplightState::Red;
+            }
+            (phase, state) => (phase, state),
+        };
+        if let Some(preemption) = self.preemption.as_mut() {
+            preemption.phase = phase;
+        }
+        if next_state != self.state {
+            self.cause = Some(match phase {
+                PreemptPhase::Recovery => "preemption recovery",
+                _ => "preemption",
+            });
+        }
+        next_state
+    }
+
+    fn rail_role(&self) -> RailRole {
+        match &self.rail_crossing {
+            Some(crossing) if crossing.track_approach == self.approach => RailRole::Track,
+            Some(crossing) if crossing.dwell_approaches.contains(&self.approach) => RailRole::Dwell,
+            _ => RailRole::Hold,
+        }
+    }
+
+    fn start_rail(&mut self) {
+        if self.rail.is_some() {
+            return; // Gates already down
+        }
+        // The emergency vehicle is served again once the train has passed
+        if let Some(preemption) = self.preemption {
+            self.deferred_preempt = Some(preemption.cycle.request);
+            self.finish_preemption(PreemptOutcome::Superseded);
+        }
+        self.rail = Some(RailPreemption { phase: RailPhase::Entry, ticks: 0 });
+    }
+
+    fn finish_rail(&mut self) -> StoplightState {
+        if self.rail.take().is_none() {
+            return self.state;
+        }
+        // Normal timing resumes with a full interval; Yellow keeps running
+        if matches!(self.state, StoplightState::Red | StoplightState::Green) {
+            self.timer_ticks_in_state = 0;
+        }
+        match self.deferred_preempt.take() {
+            Some(request) if !self.fail_safe && !self.state.is_flashing() => {
+                self.start_preemption(request);
+                self.advance_preemption()
+            }
+            _ => self.state,
+        }
+    }
+
+    // Next state of a head under railroad preemption. Heads cannot see each
+    // other, so each times the others from its own Yellow and all-red, which
+    // assumes the heads of an intersection share them. A track head resting
+    // in Red waits out a Yellow and all-red in Entry, for conflicting heads
+    // to leave Green; the other heads stay Red for that wait, the track
+    // clearance green, and the Yellow and all-red after it.
+    fn advance_rail(&mut self) -> StoplightState {
+        let Some(rail) = self.rail.filter(|_| !self.held) else { return self.state };
+        let role = self.rail_role();
+        let track_clearance = self.rail_crossing.as_ref().map_or(0, |crossing| crossing.track_clearance);
+        let ready_for_green = self.red_interval == RedInterval::Rest && !self.pedestrian_hold();
+        let change = self.timing.yellow.saturating_add(self.timing.red_clearance); // From Green to an all-red that has run out
+        let conflicts_cleared = rail.ticks > change;
+        use RailPhase::*;
+        use StoplightState::{Green, Red, Yellow};
+        let (phase, next_state) = match (rail.phase, self.state) {
+            (TrackClearance, Yellow) if self.timer_ticks_in_state >= self.timing.yellow && role == RailRole::Track => {
+                (Dwell, Red)
+            }
+            (_, Yellow) if self.timer_ticks_in_state >= self.timing.yellow => (rail.phase, Red),
+            (_, Yellow) => (rail.phase, Yellow),
+            (Entry, Green) if role == RailRole::Track => (TrackClearance, Green),
+            (Entry, Red) if role == RailRole::Track && ready_for_green && conflicts_cleared => (TrackClearance, Green),
+            (Entry, Green) => (Entry, Yellow),
+            (Entry, Red) if role != RailRole::Track => (TrackClearance, Red),
+            (TrackClearance, Green) if rail.ticks >= track_clearance => (TrackClearance, Yellow),
+            (TrackClearance, Red) if rail.ticks > change.saturating_add(track_clearance).saturating_add(change) => {
+                (Dwell, Red)
+            }
+            (Dwell, Red) if role == RailRole::Dwell && ready_for_green => (Dwell, Green),
+            (Dwell, Green) if role != RailRole::Dwell => (Dwell, Yellow),
+            (phase, state) => (phase, state),
+        };
+        if phase != rail.phase {
+            self.rail = Some(RailPreemption { phase, ticks: 0 });
+            if next_state == self.state {
+                return self.advance_rail(); // The next phase may act at once
+            }
+        }
+        if next_state != self.state {
+            self.cause = Some(match phase {
+                Entry => "rail preemption",
+                TrackClearance => "track clearance",
+                Dwell => "rail dwell",
+            });
+        }
+        next_state
+    }
+
+    // An actuated head resting in Red only goes Green for a vehicle
+    fn green_wanted(&self) -> bool {
+        match self.timing.actuation {
+            Some(actuation) => actuation.rest == RestIn::Green || self.vehicle_call,
+            None => true,
+        }
+    }
+
+    fn red_interval_after(&self, ticks: u32) -> RedInterval {
+        if ticks < self.timing.red_clearance {
+            RedInterval::Clearance
+        } else if ticks < self.timing.red_revert {
+            RedInterval::Revert
+        } else {
+            RedInterval::Rest
+        }
+    }
+}
+
+fn flashing(mode: FlashMode) -> StoplightState {
+    match mode {
+        FlashMode::Red => StoplightState::FlashingRed,
+        FlashMode::Yellow => StoplightState::FlashingYellow,
+    }
+}
+
+impl StateMachine for StoplightFsm {
+    type State = StoplightState;
+    type Event = StoplightEvent;
+    type Context = PedestrianCall;
+
+    fn name(&self) -> &str {
+        "Stoplight"
+    }
+
+    fn state(&self) -> StoplightState {
+        self.state
+    }
+
+    fn next_state(&mut self, event: &StoplightEvent, call: &PedestrianCall) -> StoplightState {
+        self.pedestrian_call = *call;
+        self.cause = None;
+        if *event == StoplightEvent::TimerTick {
+            self.cycle_clock += 1; // The master cycle runs whatever the head does
+        }
+        match event {
+            StoplightEvent::FailSafe => {
+                self.fail_safe = true;
+                self.flash_request = None;
+                self.finish_preemption(PreemptOutcome::Cancelled);
+                self.deferred_preempt = None;
+                // Gates stay down; the track is cleared again after Resume
+                if let Some(rail) = self.rail.as_mut() {
+                    *rail = RailPreemption { phase: RailPhase::Entry, ticks: 0 };
+                }
+                StoplightState::Red
+            }
+            StoplightEvent::Resume => {
+                if self.fail_safe {
+                    self.fail_safe = false;
+                    self.timer_ticks_in_state = 0;
+                }
+                self.state
+            }
+            StoplightEvent::Hold => {
+                self.held = true;
+                self.cause = Some("all-red hold");
+                if let Some(preemption) = self.preemption.as_mut().filter(|p| p.phase == PreemptPhase::Dwell) {
+                    preemption.phase = PreemptPhase::Entry;
+                }
+                if let Some(rail) = self.rail.as_mut() {
+                    *rail = RailPreemption { phase: RailPhase::Entry, ticks: 0 };
+                }
+                if self.state.is_flashing() {
+                    self.state
+                } else {
+                    StoplightState::Red
+                }
+            }
+            StoplightEvent::Release => {
+                if self.held {
+                    self.held = false;
+                    self.timer_ticks_in_state = 0;
+                }
+                self.state
+            }
+            // Gate changes are tracked even while latched, held or flashing
+            StoplightEvent::GateDown => {
+                self.start_rail();
+                match self.state {
+                    _ if self.fail_safe => self.state,
+                    // Night flash gives way to the train, fault flash does not
+                    StoplightState::FlashingYellow => {
+                        self.flash_request = None;
+                        StoplightState::Yellow
+                    }
+                    StoplightState::FlashingRed => self.state,
+                    _ => self.advance_rail(),
+                }
+            }
+            StoplightEvent::GateUp => self.finish_rail(),
+            // Red at rest and flashing are safe points: a plan never changes a
+            // Green, Yellow, all-red or red revert already under way
+            StoplightEvent::ChangeTiming(timing) => {
+                let resting = self.state == StoplightState::Red && self.red_interval == RedInterval::Rest;
+                if resting || self.state.is_flashing() {
+                    self.timing = *timing;
+                    self.pending_timing = None;
+                } else {
+                    self.pending_timing = Some(*timing);
+                }
+                self.state
+            }
+            // Decided even while latched, so every request is on record
+            StoplightEvent::TransitRequest => {
+                let outcome = self.decide_transit();
+                self.transit_decisions.push(TransitDecision { state: self.state, outcome });
+                self.state
+            }
+            // Only an explicit Resume leaves fail-safe
+            _ if self.fail_safe => self.state,
+            // Anything else is taken in during a hold, and served after it
+            StoplightEvent::TimerTick if self.held => self.state,
+            StoplightEvent::Flash(FlashMode::Yellow) if self.held => {
+                self.flash_request = Some(FlashMode::Yellow);
+                self.state
+            }
+            StoplightEvent::Flash(mode) => {
+                // Night flash also waits out a pedestrian interval in progress
+                let steady_red = self.state == StoplightState::Red && !self.pedestrian_hold() && !self.is_preempted();
+                if *mode == FlashMode::Red {
+                    self.finish_preemption(PreemptOutcome::Cancelled);
+                    if let Some(rail) = self.rail.as_mut() {
+                        *rail = RailPreemption { phase: RailPhase::Entry, ticks: 0 };
+                    }
+                }
+                if *mode == FlashMode::Red || steady_red || self.state.is_flashing() {
+                    self.flash_request = None;
+                    flashing(*mode)
+                } else {
+                    self.flash_request = Some(*mode);
+                    self.state
+                }
+            }
+            StoplightEvent::DetectorCall => {
+                match self.state {
+                    StoplightState::Green => self.ticks_since_call = 0, // Extends the green
+                    StoplightState::Red | StoplightState::Yellow => self.vehicle_call = true,
+                    StoplightState::FlashingRed | StoplightState::FlashingYellow => {}
+                }
+                self.state
+            }
+            // Not served while flashing; the flash has to be ended first
+            StoplightEvent::Preempt(_) | StoplightEvent::PreemptClear(_) if self.state.is_flashing() => self.state,
+            StoplightEvent::Preempt(request) if self.rail.is_some() => {
+                // Served once the train has passed, the highest priority first
+                match &mut self.deferred_preempt {
+                    Some(deferred) if deferred.approach == request.approach => {
+                        deferred.priority = deferred.priority.max(request.priority)
+                    }
+                    Some(deferred) if deferred.priority >= request.priority => {}
+                    deferred => *deferred = Some(*request),
+                }
+                self.state
+            }
+            StoplightEvent::PreemptClear(approach) if self.rail.is_some() => {
+                if self.deferred_preempt.is_some_and(|deferred| deferred.approach == *approach) {
+                    self.deferred_preempt = None;
+                }
+                self.state
+            }
+            StoplightEvent::Preempt(request) => {
+                self.start_preemption(*request);
+                self.advance_preemption()
+            }
+            StoplightEvent::PreemptClear(approach) => {
+                match self.preemption {
+                    Some(preemption) if preemption.cycle.request.approach == *approach => {
+                        if self.state == StoplightState::Red && self.red_interval != RedInterval::Clearance {
+                            // Already Red: a full Red interval follows
+                            self.finish_preemption(PreemptOutcome::Completed);
+                            self.timer_ticks_in_state = 0;
+                            self.state
+                        } else {
+                            self.preemption.as_mut().unwrap().phase = PreemptPhase::Recovery;
+                            self.advance_preemption()
+                        }
+                    }
+                    _ => self.state,
+                }
+            }
+            StoplightEvent::ExitFlash => {
+                self.flash_request = None;
+                match self.state {
+                    StoplightState::FlashingRed => StoplightState::Red,
+                    StoplightState::FlashingYellow => StoplightState::Yellow,
+                    state => state,
+                }
+            }
+            StoplightEvent::TimerTick => {
+                self.timer_ticks_in_state += 1;
+                if let Some(ticks) = self.ticks_since_grant.as_mut() {
+                    *ticks += 1;
+                }
+                let mut next_state = self.state; // Default to current state
+
+                // Sub-states only move forward; a Red that started in Rest stays there
+                if self.state == StoplightState::Red && self.red_interval != RedInterval::Rest {
+                    self.red_interval = self.red_interval_after(self.timer_ticks_in_state);
+                    if self.red_interval == RedInterval::Rest {
+                        if let Some(timing) = self.pending_timing.take() {
+                            self.timing = timing;
+                        }
+                    }
+                }
+                if let Some(rail) = self.rail.as_mut() {
+                    if !self.state.is_flashing() {
+                        rail.ticks += 1;
+                        return self.advance_rail();
+                    }
+                }
+                if let Some(preemption) = self.preemption.as_mut() {
+                    let cycle = &mut preemption.cycle;
+                    match preemption.phase {
+                        PreemptPhase::Entry => cycle.entry_ticks += 1,
+                        PreemptPhase::Dwell => cycle.dwell_ticks += 1,
+                        PreemptPhase::Recovery => cycle.recovery_ticks += 1,
+                    }
+                    return self.advance_preemption();
+                }
+
+                match self.state {
+                    StoplightState::Red => {
+                        if self.red_interval == RedInterval::Clearance || self.pedestrian_hold() {
+                            // Nothing leaves Red during the all-red or the pedestrian interval
+                        } else if let Some(mode) = self.flash_request.take() {
+                            next_state = flashing(mode);
+                        } else if self.red_interval == RedInterval::Rest
+                            && self.timer_ticks_in_state >= self.timing.red.saturating_sub(self.red_truncation)
+                            && self.green_wanted()
+                        {
+                            if self.timer_ticks_in_state < self.timing.red {
+                                self.cause = Some("transit priority");
+                            }
+                            next_state = StoplightState::Green;
+                        }
+                    }
+                    StoplightState::Green => {
+                        if self.green_done() {
+                            if self.coordinated_green.is_some_and(|green| green != self.timing.green) {
+                                self.cause = self.timing.coordination.map(|c| match c.transition {
+                                    TransitionMode::Shortway => "shortway",
+                                    TransitionMode::Dwell => "dwell",
+                                });
+                            }
+                            next_state = StoplightState::Yellow;
+                        }
+                    }
+                    StoplightState::Yellow => {
+                        if self.timer_ticks_in_state >= self.timing.yellow {
+                            next_state = StoplightState::Red;
+                        }
+                    }
+                    StoplightState::FlashingRed | StoplightState::FlashingYellow => {}
+                }
+                next_state
+            }
+        }
+    }
+
+    fn enter(&mut self, state: StoplightState) {
+        if state == StoplightState::Red {
+            if let Some(timing) = self.pending_timing.take() {
+                self.timing = timing;
+            }
+            self.red_interval = match self.state {
+                StoplightState::Green | StoplightState::Yellow => self.red_interval_after(0),
+                _ => RedInterval::Rest,
+            };
+        }
+        // A grant lasts for the interval it was given in
+        self.green_extension = 0;
+        self.red_truncation = 0;
+        if state == StoplightState::Green {
+            self.coordinated_green = self.timing.coordination.map(|coordination| {
+                let error = coordination.position(self.cycle_clock);
+                self.sync_error = Some(error);
+                coordination.corrected_green(self.timing.green, error)
+            });
+            self.vehicle_call = false;
+            self.ticks_since_call = 0;
+            self.max_timer = 0;
+        }
+        self.state = state;
+        self.timer_ticks_in_state = 0; // Reset timer for new state
+    }
+
+    fn reason(&self) -> Option<&'static str> {
+        // The only transition possible while latched is the one into it
+        if self.fail_safe {
+            Some("fail-safe latched")
+        } else {
+            self.cause
+        }
+    }
+
+    // A call never changes the state by itself: the hold is checked on the
+    // next tick, and the grant is read off `pedestrian_hold` by the driver
+    fn on_context_change(&mut self, _old: &PedestrianCall, new: &PedestrianCall) -> Option<StoplightEvent> {
+        self.pedestrian_call = *new;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use StoplightState::*;
+
+    const IDLE: PedestrianCall = PedestrianCall::Idle;
+
+    #[test]
+    fn test_stoplight_cycle() {
+        let mut fsm = StoplightFsm::new();
+        assert_eq!(fsm.state, StoplightState::Red);
+
+        // Tick through Red state
+        for _ in 0..StoplightFsm::RED_DURATION {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, StoplightState::Green);
+        assert_eq!(fsm.timer_ticks_in_state, 0); // Timer should reset
+
+        // Tick through Green state
+        for _ in 0..StoplightFsm::GREEN_DURATION {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, StoplightState::Yellow);
+        assert_eq!(fsm.timer_ticks_in_state, 0);
+
+        // Tick through Yellow state
+        for _ in 0..StoplightFsm::YELLOW_DURATION {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, StoplightState::Red);
+        assert_eq!(fsm.timer_ticks_in_state, 0);
+    }
+
+    #[test]
+    fn test_stoplight_custom_timing() {
+        let timing = StoplightTiming { red: 2, green: 3, yellow: 2, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, timing);
+        let mut states = Vec::new();
+        for _ in 0..7 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+            states.push(fsm.state);
+        }
+        use StoplightState::*;
+        assert_eq!(states, vec![Green, Green, Yellow, Yellow, Red, Red, Green]);
+    }
+
+    #[test]
+    fn test_red_clearance_and_revert_sub_states() {
+        let timing = StoplightTiming { red: 2, green: 1, yellow: 1, red_clearance: 2, red_revert: 4, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Yellow, timing);
+        let mut intervals = Vec::new();
+        for _ in 0..7 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+            intervals.push((fsm.state, fsm.red_interval()));
+        }
+        use RedInterval::*;
+        use StoplightState::*;
+        // Red lasts red_revert ticks, longer than the configured red
+        assert_eq!(
+            intervals,
+            vec![
+                (Red, Some(Clearance)),
+                (Red, Some(Clearance)),
+                (Red, Some(Revert)),
+                (Red, Some(Revert)),
+                (Green, None),
+                (Yellow, None),
+                (Red, Some(Clearance)),
+            ]
+        );
+
+        // The initial Red has no clearance
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Red, timing);
+        assert_eq!(fsm.red_interval(), Some(Rest));
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.state, Green);
+    }
+
+    #[test]
+    fn test_flash_waits_for_red_clearance() {
+        let timing = StoplightTiming { red_clearance: 2, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Yellow, timing);
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &IDLE);
+        let mut states = Vec::new();
+        for _ in 0..4 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+            states.push(fsm.state);
+        }
+        use StoplightState::*;
+        assert_eq!(states, vec![Red, Red, FlashingYellow, FlashingYellow]);
+    }
+
+    #[test]
+    fn test_night_flash_waits_for_red_and_exits_through_yellow() {
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, StoplightTiming::default());
+        assert_eq!(fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &IDLE), None);
+        assert_eq!(fsm.flash_requested(), Some(FlashMode::Yellow));
+
+        let mut states = Vec::new();
+        for _ in 0..8 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+            states.push(fsm.state);
+        }
+        use StoplightState::*;
+        // Green and Yellow run out, then one tick of Red before flashing
+        assert_eq!(states, vec![Green, Green, Green, Yellow, Red, FlashingYellow, FlashingYellow, FlashingYellow]);
+
+        fsm.handle_event(StoplightEvent::ExitFlash, &IDLE);
+        assert_eq!(fsm.state, Yellow);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.state, Red);
+        assert_eq!(fsm.ticks_in_state(), 0);
+    }
+
+    #[test]
+    fn test_fault_flash_is_immediate_and_exits_through_red() {
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, StoplightTiming::default());
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Red), &IDLE);
+        assert_eq!(fsm.state, StoplightState::FlashingRed);
+        // Switching flash modes needs no Red in between
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &IDLE);
+        assert_eq!(fsm.state, StoplightState::FlashingYellow);
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Red), &IDLE);
+
+        fsm.handle_event(StoplightEvent::ExitFlash, &IDLE);
+        assert_eq!(fsm.state, StoplightState::Red);
+        for _ in 0..StoplightFsm::RED_DURATION {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, StoplightState::Green);
+
+        // Fail-safe overrides flash
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Red), &IDLE);
+        fsm.handle_event(StoplightEvent::FailSafe, &IDLE);
+        assert_eq!(fsm.state, StoplightState::Red);
+        assert_eq!(fsm.handle_event(StoplightEvent::Flash(FlashMode::Red), &IDLE), None);
+    }
+
+    #[test]
+    fn test_stoplight_fail_safe_latches_red() {
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, StoplightTiming::default());
+        let transition = fsm.handle_event(StoplightEvent::FailSafe, &IDLE).unwrap();
+        assert_eq!((transition.to, transition.reason), (StoplightState::Red, Some("fail-safe latched")));
+        for _ in 0..20 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &IDLE), None);
+        }
+        assert!(fsm.is_fail_safe());
+
+        // Resuming serves a full Red interval before Green
+        fsm.handle_event(StoplightEvent::Resume, &IDLE);
+        for _ in 1..StoplightFsm::RED_DURATION {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, StoplightState::Red);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.state, StoplightState::Green);
+    }
+
+    #[test]
+    fn test_hold_keeps_preemption_and_flash_request() {
+        let timing = StoplightTiming { yellow: 2, red_clearance: 1, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, timing);
+        let request = PreemptRequest { approach: 1, priority: 1 };
+        fsm.handle_event(StoplightEvent::Preempt(request), &IDLE);
+        assert_eq!(fsm.preemption(), Some((request, PreemptPhase::Dwell)));
+
+        // All-red at once; the preemption and a night flash wait for the release
+        let transition = fsm.handle_event(StoplightEvent::Hold, &IDLE).unwrap();
+        assert_eq!((transition.to, transition.reason), (StoplightState::Red, Some("all-red hold")));
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &IDLE);
+        for _ in 0..10 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &IDLE), None);
+        }
+        assert_eq!(fsm.preemption(), Some((request, PreemptPhase::Entry)));
+        assert_eq!(fsm.flash_requested(), Some(FlashMode::Yellow));
+
+        // Released, the preemption is served again from its entry, then the flash
+        fsm.handle_event(StoplightEvent::Release, &IDLE);
+        use StoplightState::*;
+        let mut states = Vec::new();
+        for _ in 0..4 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+            states.push(fsm.state);
+        }
+        assert_eq!(states, vec![Red, Red, Red, Green]);
+        fsm.handle_event(StoplightEvent::PreemptClear(1), &IDLE);
+        for _ in 0..4 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, FlashingYellow);
+        assert_eq!(fsm.preemption_cycles()[0].outcome, PreemptOutcome::Completed);
+    }
+
+    #[test]
+    fn test_red_held_for_pedestrian_interval() {
+        let timing = StoplightTiming { red: 2, green: 1, yellow: 1, red_clearance: 1, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Yellow, timing);
+        use PedestrianCall::*;
+
+        // Called during Yellow; granted once the all-red is over
+        fsm.handle_event(StoplightEvent::TimerTick, &Waiting);
+        assert_eq!((fsm.state, fsm.pedestrian_hold()), (StoplightState::Red, false));
+        fsm.handle_event(StoplightEvent::TimerTick, &Waiting);
+        assert!(fsm.pedestrian_hold());
+
+        // Red outlasts its timing while pedestrians cross
+        for _ in 0..10 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &Serving), None);
+        }
+        // Night flash waits for the crossing too
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &Serving);
+        assert_eq!(fsm.flash_requested(), Some(FlashMode::Yellow));
+        fsm.handle_event(StoplightEvent::ExitFlash, &Serving);
+
+        assert_eq!(fsm.on_context_change(&Serving, &Idle), None);
+        assert!(!fsm.pedestrian_hold());
+        fsm.handle_event(StoplightEvent::TimerTick, &Idle);
+        assert_eq!(fsm.state, StoplightState::Green);
+
+        // A call is not granted outside Red, nor while latched
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &Waiting).map(|t| t.to), Some(StoplightState::Yellow));
+        assert!(!fsm.pedestrian_hold());
+        fsm.handle_event(StoplightEvent::FailSafe, &Waiting);
+        assert!(!fsm.pedestrian_hold());
+    }
+
+    #[test]
+    fn test_actuated_green_extends_and_gaps_out() {
+        let actuation = Actuation { max_green: 12, passage: 3, rest: RestIn::Red };
+        let timing = StoplightTiming { red: 1, green: 3, actuation: Some(actuation), ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Red, timing);
+
+        // Rests in Red without a vehicle
+        for _ in 0..5 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &IDLE), None);
+        }
+        fsm.handle_event(StoplightEvent::DetectorCall, &IDLE);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.state, StoplightState::Green);
+        assert!(!fsm.has_vehicle_call());
+
+        // Calls on green ticks 3 and 5 carry it past the minimum of 3
+        let mut ended = None;
+        for tick in 1..=10 {
+            if tick == 3 || tick == 5 {
+                fsm.handle_event(StoplightEvent::DetectorCall, &IDLE);
+            }
+            if let Some(transition) = fsm.handle_event(StoplightEvent::TimerTick, &IDLE) {
+                ended = Some((tick, transition.reason));
+                break;
+            }
+        }
+        assert_eq!(ended, Some((7, Some("gap-out"))));
+    }
+
+    #[test]
+    fn test_actuated_green_maxes_out_and_rests_in_green() {
+        let actuation = Actuation { max_green: 5, passage: 2, rest: RestIn::Green };
+        let timing = StoplightTiming { green: 2, actuation: Some(actuation), ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, timing);
+
+        // Nobody else is waiting, so green stays however long the gap
+        for _ in 0..20 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &IDLE), None);
+        }
+
+        // Steady traffic cannot hold it past max green once the crosswalk calls
+        let mut ticks = 0;
+        let transition = loop {
+            ticks += 1;
+            fsm.handle_event(StoplightEvent::DetectorCall, &PedestrianCall::Waiting);
+            if let Some(transition) = fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Waiting) {
+                break transition;
+            }
+        };
+        assert_eq!((ticks, transition.to, transition.reason), (5, StoplightState::Yellow, Some("max-out")));
+
+        // Resting in Green needs no vehicle call to come back
+        for _ in 0..(1 + StoplightFsm::RED_DURATION) {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        assert_eq!(fsm.state, StoplightState::Green);
+    }
+
+    #[test]
+    fn test_preemption_of_own_approach() {
+        let timing = StoplightTiming { yellow: 2, red_clearance: 1, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Red, timing);
+        let request = PreemptRequest { approach: 1, priority: 1 };
+        use PedestrianCall::*;
+
+        // Pedestrians already crossing are cleared before the green, and
+        // conflicting heads get a Yellow and all-red
+        assert_eq!(fsm.handle_event(StoplightEvent::Preempt(request), &Serving), None);
+        assert_eq!(fsm.preemption(), Some((request, PreemptPhase::Entry)));
+        fsm.handle_event(StoplightEvent::TimerTick, &Serving);
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &Idle), None);
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &Idle), None);
+        let transition = fsm.handle_event(StoplightEvent::TimerTick, &Idle).unwrap();
+        assert_eq!((transition.to, transition.reason), (StoplightState::Green, Some("preemption")));
+
+        // Dwell in Green well past the green time, then recover through a full Yellow
+        for _ in 0..10 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &Idle), None);
+        }
+        let transition = fsm.handle_event(StoplightEvent::PreemptClear(1), &Idle).unwrap();
+        assert_eq!((transition.to, transition.reason), (StoplightState::Yellow, Some("preemption recovery")));
+        let mut states = Vec::new();
+        for _ in 0..3 {
+            fsm.handle_event(StoplightEvent::TimerTick, &Idle);
+            states.push((fsm.state, fsm.preemption().map(|(_, phase)| phase)));
+        }
+        use StoplightState::*;
+        assert_eq!(
+            states,
+            vec![(Yellow, Some(PreemptPhase::Recovery)), (Red, Some(PreemptPhase::Recovery)), (Red, None)]
+        );
+        assert_eq!(
+            fsm.preemption_cycles(),
+            &[PreemptionCycle { request, entry_ticks: 4, dwell_ticks: 10, recovery_ticks: 3, outcome: PreemptOutcome::Completed }]
+        );
+    }
+
+    #[test]
+    fn test_preemption_with_the_longest_all_red() {
+        // The conflicting heads' all-red never runs out, so neither does Entry
+        let timing = StoplightTiming { red_clearance: u32::MAX, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Red, timing);
+        let request = PreemptRequest { approach: 1, priority: 1 };
+        assert_eq!(fsm.handle_event(StoplightEvent::Preempt(request), &IDLE), None);
+        for _ in 0..20 {
+            assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &IDLE), None);
+        }
+        assert_eq!(fsm.preemption(), Some((request, PreemptPhase::Entry)));
+        fsm.handle_event(StoplightEvent::PreemptClear(1), &IDLE);
+        assert_eq!((fsm.state, fsm.preemption()), (StoplightState::Red, None));
+    }
+
+    #[test]
+    fn test_preemption_of_other_approach_and_priority() {
+        let timing = StoplightTiming { yellow: 2, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Green, timing).with_approach(2);
+        let low = PreemptRequest { approach: 1, priority: 1 };
+
+        // Green is cut short, Yellow is not
+        let transition = fsm.handle_event(StoplightEvent::Preempt(low), &IDLE).unwrap();
+        assert_eq!((transition.to, transition.reason), (StoplightState::Yellow, Some("preemption")));
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.state, StoplightState::Yellow);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.preemption(), Some((low, PreemptPhase::Entry)));
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.preemption(), Some((low, PreemptPhase::Dwell)));
+
+        // An equal priority elsewhere is ignored, a higher one takes over
+        fsm.handle_event(StoplightEvent::Preempt(PreemptRequest { approach: 3, priority: 1 }), &IDLE);
+        assert_eq!(fsm.preemption(), Some((low, PreemptPhase::Dwell)));
+        let high = PreemptRequest { approach: 2, priority: 5 };
+        fsm.handle_event(StoplightEvent::Preempt(high), &IDLE);
+        assert_eq!((fsm.state, fsm.preemption()), (StoplightState::Red, Some((high, PreemptPhase::Entry))));
+        run(&mut fsm, 3);
+        assert_eq!((fsm.state, fsm.preemption()), (StoplightState::Green, Some((high, PreemptPhase::Dwell))));
+        assert_eq!(fsm.preemption_cycles()[0].outcome, PreemptOutcome::Superseded);
+
+        // Clears for other approaches do nothing; fail-safe cancels
+        fsm.handle_event(StoplightEvent::PreemptClear(1), &IDLE);
+        assert!(fsm.preemption().is_some());
+        fsm.handle_event(StoplightEvent::FailSafe, &IDLE);
+        assert_eq!(fsm.preemption(), None);
+        assert_eq!(fsm.preemption_cycles()[1].outcome, PreemptOutcome::Cancelled);
+        assert_eq!(fsm.handle_event(StoplightEvent::Preempt(high), &IDLE), None);
+    }
+
+    #[test]
+    fn test_preempted_green_waits_for_conflicting_head() {
+        // Approach 1 resting in Red, approach 2 in Green, preempted together
+        let timing = StoplightTiming { yellow: 2, red_clearance: 1, ..StoplightTiming::default() };
+        let mut preempted = StoplightFsm::with_timing(Red, timing);
+        let mut conflicting = StoplightFsm::with_timing(Green, timing).with_approach(2);
+        let request = PreemptRequest { approach: 1, priority: 1 };
+        preempted.handle_event(StoplightEvent::Preempt(request), &IDLE);
+        conflicting.handle_event(StoplightEvent::Preempt(request), &IDLE);
+        let mut states = Vec::new();
+        for _ in 0..5 {
+            preempted.handle_event(StoplightEvent::TimerTick, &IDLE);
+            conflicting.handle_event(StoplightEvent::TimerTick, &IDLE);
+            if preempted.state == Green {
+                assert_eq!((conflicting.state, conflicting.red_interval), (Red, RedInterval::Rest));
+            }
+            states.push((preempted.state, conflicting.state));
+        }
+        // Green once the other head's Yellow and all-red have run out
+        assert_eq!(states, vec![(Red, Yellow), (Red, Red), (Red, Red), (Green, Red), (Green, Red)]);
+    }
+
+    fn transit_head(initial: StoplightState) -> StoplightFsm {
+        let timing = StoplightTiming { red: 6, green: 4, yellow: 1, red_clearance: 1, ..StoplightTiming::default() };
+        let transit = TransitPriority { max_extension: 3, max_early: 4, lockout: 10 };
+        StoplightFsm::with_timing(initial, timing).with_transit_priority(transit)
+    }
+
+    fn ticks_until_change(fsm: &mut StoplightFsm, call: &PedestrianCall) -> u32 {
+        let mut ticks = 1;
+        while fsm.handle_event(StoplightEvent::TimerTick, call).is_none() {
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_transit_green_extension_and_lockout() {
+        let mut fsm = transit_head(StoplightState::Green);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        assert_eq!(ticks_until_change(&mut fsm, &IDLE), 6); // 4 + 3 - 1 already gone
+
+        // Denied until the lockout has run out; Yellow is never extended
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        assert_eq!(ticks_until_change(&mut fsm, &IDLE), 1);
+        for _ in 0..3 {
+            fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        }
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        use TransitOutcome::*;
+        let outcomes: Vec<_> = fsm.transit_decisions().iter().map(|d| (d.state, d.outcome)).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (StoplightState::Green, Extended(3)),
+                (StoplightState::Yellow, Denied(TransitDenial::Unavailable)),
+                (StoplightState::Red, EarlyGreen(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transit_early_green_respects_minimums() {
+        let mut fsm = transit_head(StoplightState::Yellow);
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        let transition = loop {
+            if let Some(transition) = fsm.handle_event(StoplightEvent::TimerTick, &IDLE) {
+                break transition;
+            }
+        };
+        assert_eq!(transition.reason, Some("transit priority"));
+        assert_eq!(fsm.transit_decisions()[1].outcome, TransitOutcome::EarlyGreen(4));
+
+        // Red is 6, cut by 4, but not below the all-red or the pedestrian interval
+        let mut fsm = transit_head(StoplightState::Red);
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        assert_eq!(ticks_until_change(&mut fsm, &IDLE), 2);
+        let mut fsm = transit_head(StoplightState::Red);
+        fsm.handle_event(StoplightEvent::TransitRequest, &PedestrianCall::Serving);
+        assert_eq!(ticks_until_change(&mut fsm, &IDLE), 6);
+        assert_eq!(fsm.transit_decisions()[0].outcome, TransitOutcome::Denied(TransitDenial::PedestrianInterval));
+    }
+
+    #[test]
+    fn test_transit_denied_when_preempted_or_not_configured() {
+        let mut fsm = transit_head(StoplightState::Green);
+        fsm.handle_event(StoplightEvent::Preempt(PreemptRequest { approach: 2, priority: 1 }), &IDLE);
+        fsm.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        let mut plain = StoplightFsm::new();
+        plain.handle_event(StoplightEvent::TransitRequest, &IDLE);
+        assert_eq!(fsm.transit_decisions()[0].outcome, TransitOutcome::Denied(TransitDenial::Preempted));
+        assert_eq!(plain.transit_decisions()[0].outcome, TransitOutcome::Denied(TransitDenial::NotConfigured));
+        assert_eq!(plain.transit_decisions()[0].to_string(), "Red: denied, NotConfigured");
+    }
+
+    #[test]
+    fn test_timing_plan_changes_at_safe_points() {
+        let timing = StoplightTiming { yellow: 2, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Yellow, timing);
+        let plan = StoplightTiming { red: 3, green: 6, yellow: 3, ..StoplightTiming::default() };
+
+        // Not mid-Yellow: the running Yellow keeps its length
+        fsm.handle_event(StoplightEvent::ChangeTiming(plan), &IDLE);
+        assert_eq!((fsm.timing().yellow, fsm.pending_timing()), (2, Some(&plan)));
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!((fsm.state, fsm.timing(), fsm.pending_timing()), (StoplightState::Red, &plan, None));
+
+        // In Red it applies at once
+        let short = StoplightTiming { red: 2, ..plan };
+        fsm.handle_event(StoplightEvent::ChangeTiming(short), &IDLE);
+        assert_eq!(fsm.timing(), &short);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!(fsm.state, StoplightState::Green);
+
+        // Not during the all-red either: the running clearance keeps its length
+        let timing = StoplightTiming { red: 1, yellow: 1, red_clearance: 3, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(StoplightState::Yellow, timing);
+        fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+        assert_eq!((fsm.state, fsm.red_interval), (StoplightState::Red, RedInterval::Clearance));
+        let no_clearance = StoplightTiming { red_clearance: 0, ..timing };
+        fsm.handle_event(StoplightEvent::ChangeTiming(no_clearance), &IDLE);
+        assert_eq!((fsm.timing(), fsm.pending_timing()), (&timing, Some(&no_clearance)));
+        assert_eq!(run(&mut fsm, 2), vec![StoplightState::Red; 2]);
+        assert_eq!(fsm.pending_timing(), Some(&no_clearance));
+        // Applied once the three ticks of all-red are over
+        assert_eq!(run(&mut fsm, 1), vec![StoplightState::Green]);
+        assert_eq!((fsm.timing(), fsm.pending_timing()), (&no_clearance, None));
+    }
+
+    fn crossing() -> RailCrossing {
+        RailCrossing { track_approach: 1, track_clearance: 3, dwell_approaches: vec![2] }
+    }
+
+    fn head(initial: StoplightState, approach: u8) -> StoplightFsm {
+        let timing = StoplightTiming { yellow: 2, red_clearance: 1, ..StoplightTiming::default() };
+        StoplightFsm::with_timing(initial, timing).with_approach(approach).with_rail_crossing(crossing())
+    }
+
+    fn run(fsm: &mut StoplightFsm, ticks: usize) -> Vec<StoplightState> {
+        (0..ticks)
+            .map(|_| {
+                fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+                fsm.state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_track_clearance_then_dwell_in_red() {
+        // Green once conflicting heads have had a Yellow and all-red
+        let mut fsm = head(Red, 1);
+        assert_eq!(fsm.handle_event(StoplightEvent::GateDown, &IDLE), None);
+        assert_eq!(run(&mut fsm, 4), vec![Red, Red, Red, Green]);
+        assert_eq!((fsm.reason(), fsm.rail_preemption()), (Some("track clearance"), Some(RailPhase::TrackClearance)));
+
+        assert_eq!(run(&mut fsm, 5), vec![Green, Green, Yellow, Yellow, Red]);
+        assert_eq!(fsm.rail_preemption(), Some(RailPhase::Dwell));
+        // The track approach stays Red however long the train takes
+        assert_eq!(run(&mut fsm, 20), vec![Red; 20]);
+
+        assert_eq!(fsm.handle_event(StoplightEvent::GateUp, &IDLE), None);
+        assert!(!fsm.is_preempted());
+        assert_eq!(run(&mut fsm, StoplightFsm::RED_DURATION as usize).last(), Some(&Green));
+    }
+
+    #[test]
+    fn test_track_clearance_from_green_and_after_pedestrians() {
+        // Already Green: the clearance green is counted from the gate-down
+        let mut fsm = head(Green, 1);
+        run(&mut fsm, 3);
+        assert_eq!(fsm.handle_event(StoplightEvent::GateDown, &IDLE), None);
+        assert_eq!(run(&mut fsm, 3), vec![Green, Green, Yellow]);
+
+        // Pedestrians already crossing finish first
+        let mut fsm = head(Red, 1);
+        assert_eq!(fsm.handle_event(StoplightEvent::GateDown, &PedestrianCall::Serving), None);
+        assert_eq!(fsm.rail_preemption(), Some(RailPhase::Entry));
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Serving), None);
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Serving), None);
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Serving), None);
+        assert_eq!(fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Serving), None);
+        let transition = fsm.handle_event(StoplightEvent::TimerTick, &IDLE).unwrap();
+        assert_eq!(transition.to, Green);
+    }
+
+    #[test]
+    fn test_rail_heads_never_show_conflicting_greens() {
+        // Track approach resting in Red, dwell approach in Green, gates down together
+        let mut heads = [head(Red, 1), head(Green, 2)];
+        for fsm in heads.iter_mut() {
+            fsm.handle_event(StoplightEvent::GateDown, &IDLE);
+        }
+        let mut greens = Vec::new();
+        for tick in 1..=30 {
+            for fsm in heads.iter_mut() {
+                fsm.handle_event(StoplightEvent::TimerTick, &IDLE);
+            }
+            for (green, other) in [(&heads[0], &heads[1]), (&heads[1], &heads[0])] {
+                if green.state == Green {
+                    assert_eq!((other.state, other.red_interval), (Red, RedInterval::Rest), "tick {}", tick);
+                }
+            }
+            greens.push(heads.each_ref().map(|fsm| fsm.state == Green));
+        }
+        // Both are served: the track clearance first, then the dwell approach
+        assert!(greens.iter().any(|green| green[0]) && greens.last() == Some(&[false, true]));
+    }
+
+    #[test]
+    fn test_dwell_approach_waits_out_track_clearance() {
+        let mut fsm = head(Green, 2);
+        let transition = fsm.handle_event(StoplightEvent::GateDown, &IDLE).unwrap();
+        assert_eq!((transition.to, transition.reason), (Yellow, Some("rail preemption")));
+
+        // Yellow, then Red for the track head's wait, its clearance green,
+        // Yellow and all-red
+        let states = run(&mut fsm, 13);
+        assert_eq!(states, [vec![Yellow], vec![Red; 11], vec![Green]].concat());
+        assert_eq!(fsm.reason(), Some("rail dwell"));
+        assert_eq!(run(&mut fsm, 20), vec![Green; 20]);
+
+        // Normal timing resumes with a full green
+        fsm.handle_event(StoplightEvent::GateUp, &IDLE);
+        let states = run(&mut fsm, StoplightFsm::GREEN_DURATION as usize);
+        assert_eq!(states.last(), Some(&Yellow));
+    }
+
+    #[test]
+    fn test_other_approaches_hold_red() {
+        let mut fsm = head(Green, 3);
+        fsm.handle_event(StoplightEvent::GateDown, &IDLE);
+        run(&mut fsm, 2);
+        assert_eq!(run(&mut fsm, 30), vec![Red; 30]);
+
+        // No crossing configured: Red throughout as well
+        let mut fsm = StoplightFsm::new();
+        fsm.handle_event(StoplightEvent::GateDown, &IDLE);
+        assert_eq!(run(&mut fsm, 30), vec![Red; 30]);
+        assert_eq!(fsm.rail_preemption(), Some(RailPhase::Dwell));
+    }
+
+    #[test]
+    fn test_rail_overrides_emergency_preemption() {
+        let ambulance = PreemptRequest { approach: 3, priority: 2 };
+        let mut fsm = head(Red, 3);
+        fsm.handle_event(StoplightEvent::Preempt(ambulance), &IDLE);
+        assert_eq!(run(&mut fsm, 4), vec![Red, Red, Red, Green]);
+
+        // The emergency green is cut short for the train
+        let transition = fsm.handle_event(StoplightEvent::GateDown, &IDLE).unwrap();
+        assert_eq!((transition.to, transition.reason), (Yellow, Some("rail preemption")));
+        assert_eq!(fsm.preemption(), None);
+        assert_eq!(fsm.preemption_cycles()[0].outcome, PreemptOutcome::Superseded);
+
+        // Requests during the train wait for it, and are served after it
+        let fire = PreemptRequest { approach: 4, priority: 1 };
+        assert_eq!(fsm.handle_event(StoplightEvent::Preempt(fire), &IDLE), None);
+        run(&mut fsm, 10);
+        assert_eq!(fsm.state, Red);
+        assert_eq!(fsm.handle_event(StoplightEvent::GateUp, &IDLE), None);
+        assert_eq!(fsm.preemption(), Some((ambulance, PreemptPhase::Entry)));
+        assert_eq!(run(&mut fsm, 4), vec![Red, Red, Red, Green]);
+        assert_eq!((fsm.reason(), fsm.preemption()), (Some("preemption"), Some((ambulance, PreemptPhase::Dwell))));
+
+        // A vehicle that passed during the train is not served
+        let mut fsm = head(Red, 3);
+        fsm.handle_event(StoplightEvent::GateDown, &IDLE);
+        fsm.handle_event(StoplightEvent::Preempt(ambulance), &IDLE);
+        fsm.handle_event(StoplightEvent::PreemptClear(3), &IDLE);
+        fsm.handle_event(StoplightEvent::GateUp, &IDLE);
+        assert!(!fsm.is_preempted());
+    }
+
+    #[test]
+    fn test_rail_with_flash_and_fail_safe() {
+        // Night flash gives way to the train through a full Yellow
+        let mut fsm = head(Red, 1);
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &IDLE);
+        let transition = fsm.handle_event(StoplightEvent::GateDown, &IDLE).unwrap();
+        assert_eq!(transition.to, Yellow);
+        assert_eq!(run(&mut fsm, 2), vec![Yellow, Red]);
+
+        // Fault flash keeps flashing red
+        let mut fsm = head(Red, 1);
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Red), &IDLE);
+        assert_eq!(fsm.handle_event(StoplightEvent::GateDown, &IDLE), None);
+        assert_eq!(run(&mut fsm, 5), vec![FlashingRed; 5]);
+
+        // Fail-safe holds Red; after Resume the track is cleared again
+        let mut fsm = head(Red, 1);
+        fsm.handle_event(StoplightEvent::GateDown, &IDLE);
+        fsm.handle_event(StoplightEvent::FailSafe, &IDLE);
+        assert_eq!(run(&mut fsm, 5), vec![Red; 5]);
+        fsm.handle_event(StoplightEvent::Resume, &IDLE);
+        assert_eq!((fsm.state, fsm.rail_preemption()), (Red, Some(RailPhase::Entry)));
+        assert_eq!(run(&mut fsm, 4), vec![Red, Red, Red, Green]);
+
+        // Gate-up while latched ends it
+        fsm.handle_event(StoplightEvent::FailSafe, &IDLE);
+        fsm.handle_event(StoplightEvent::GateUp, &IDLE);
+        assert!(!fsm.is_preempted());
+    }
+
+    #[test]
+    fn test_rail_with_the_longest_yellow() {
+        // The track head's Yellow and the other heads' wait never run out
+        let timing = StoplightTiming { yellow: u32::MAX, red_clearance: 1, ..StoplightTiming::default() };
+        let mut track = StoplightFsm::with_timing(Green, timing).with_rail_crossing(crossing());
+        let mut dwell = StoplightFsm::with_timing(Red, timing).with_approach(2).with_rail_crossing(crossing());
+        track.handle_event(StoplightEvent::GateDown, &IDLE);
+        dwell.handle_event(StoplightEvent::GateDown, &IDLE);
+        assert_eq!(run(&mut track, 20), [vec![Green; 2], vec![Yellow; 18]].concat());
+        assert_eq!(run(&mut dwell, 20), vec![Red; 20]);
+        assert_eq!(dwell.rail_preemption(), Some(RailPhase::TrackClearance));
+
+        dwell.handle_event(StoplightEvent::GateUp, &IDLE);
+        assert!(!dwell.is_preempted());
+    }
+
+    #[test]
+    fn test_restarted_keeps_safety_state() {
+        // Gates down and a plan waiting: Red, with the track cleared again
+        let mut fsm = head(Green, 1);
+        fsm.handle_event(StoplightEvent::GateDown, &IDLE);
+        let plan = StoplightTiming { green: 9, ..*fsm.timing() };
+        fsm.handle_event(StoplightEvent::ChangeTiming(plan), &IDLE);
+        let restarted = fsm.restarted();
+        assert_eq!((restarted.state, restarted.rail_preemption()), (Red, Some(RailPhase::Entry)));
+        assert_eq!(restarted.timing(), &plan);
+
+        // Preempted, latched or flashing
+        let mut fsm = head(Green, 2);
+        let request = PreemptRequest { approach: 2, priority: 1 };
+        fsm.handle_event(StoplightEvent::Preempt(request), &IDLE);
+        assert_eq!(fsm.restarted().preemption(), Some((request, PreemptPhase::Entry)));
+        fsm.handle_event(StoplightEvent::FailSafe, &IDLE);
+        assert!(fsm.restarted().is_fail_safe());
+        fsm.handle_event(StoplightEvent::Resume, &IDLE);
+        fsm.handle_event(StoplightEvent::Flash(FlashMode::Yellow), &IDLE);
+        assert_eq!(fsm.restarted().state, FlashingYellow);
+    }
+
+    #[test]
+    fn test_restarted_from_green_clears_first() {
+        // A crash in Green restarts in the all-red, and the waiting
+        // pedestrian is still served
+        let timing = StoplightTiming { red_clearance: 2, ..StoplightTiming::default() };
+        let mut fsm = StoplightFsm::with_timing(Green, timing);
+        fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Waiting);
+        let mut restarted = fsm.restarted();
+        assert_eq!((restarted.state, restarted.red_interval()), (Red, Some(RedInterval::Clearance)));
+        assert_eq!(restarted.pedestrian_call, PedestrianCall::Waiting);
+        assert!(!restarted.pedestrian_hold());
+        for _ in 0..2 {
+            assert_eq!(restarted.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Waiting), None);
+        }
+        assert_eq!(restarted.red_interval(), Some(RedInterval::Rest));
+        assert!(restarted.pedestrian_hold());
+
+        // Red at rest stays at rest
+        let fsm = StoplightFsm::with_timing(Red, timing);
+        assert_eq!(fsm.restarted().red_interval(), Some(RedInterval::Rest));
+    }
+}
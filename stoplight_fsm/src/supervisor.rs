@@ -0,0 +1,687 @@
+// Supervision of a whole controller: starts the timer, stoplight, crosswalk
+// and safety monitor threads, stops them on demand, and always leaves the
+// intersection all-red with DontWalk before the machines shut down. A machine
+// that panics is restarted from a known state while the intersection is held
+// safe, and resynchronized with the other before normal operation resumes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::bus::{status_bus, Backpressure, Broadcast, StatusBus, Topic};
+use crate::clock::Clock;
+use crate::crosswalk::{CrosswalkFsm, CrosswalkState, PedestrianCall};
+use crate::event_log::EventLog;
+use crate::fsm::{Ack, Input, StateMachine};
+use crate::messages::{FromController, FromMonitor, FromStoplight, ToCrosswalk, ToMonitor, ToStoplight};
+use crate::monitor::{run_monitor, SafetyMonitor, Violation};
+use crate::stoplight::{StoplightEvent, StoplightFsm, StoplightState};
+use crate::threads::{flush, run_crosswalk, run_stoplight, run_timer, Outbox, TimerOptions};
+
+/// Asks a running [`Supervisor`] to stop. Cheap to clone and to hand to a
+/// signal handler.
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl StopHandle {
+    /// Stop at the start of the next tick.
+    pub fn stop(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    // The flag itself, e.g. for signal_hook::flag::register
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.flag.clone()
+    }
+}
+
+/// Asks the safety monitor of a running [`Supervisor`] to reset its latched
+/// fail-safe. Cheap to clone and to hand to a signal handling thread.
+#[derive(Debug, Clone)]
+pub struct ResetHandle {
+    monitor: Arc<Mutex<Option<mpsc::Sender<ToMonitor>>>>, // Taken once the controller has stopped
+}
+
+impl ResetHandle {
+    /// Queue a reset behind the status updates the monitor has not seen
+    /// yet. Its result comes out of [`Supervisor::wait`] as
+    /// [`FromMonitor::Reset`]; false when the controller has already stopped.
+    pub fn reset(&self) -> bool {
+        let monitor = self.monitor.lock().unwrap_or_else(PoisonError::into_inner);
+        monitor.as_ref().is_some_and(|monitor| monitor.send(ToMonitor::Reset).is_ok())
+    }
+
+    // Let the monitor end with the machines, once the timer has shut them down
+    fn close(&self) {
+        self.monitor.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+
+    fn is_open(&self) -> bool {
+        self.monitor.lock().unwrap_or_else(PoisonError::into_inner).is_some()
+    }
+}
+
+/// How a supervised controller ended.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FinalReport {
+    pub ticks: u32,          // Ticks run
+    pub stopped_early: bool, // On request, before the configured ticks ran out
+    pub stoplight: Option<StoplightState>,
+    pub crosswalk: Option<CrosswalkState>,
+    pub latched: Option<Violation>, // Safety violation still latched at the end
+    pub restarts: BTreeMap<&'static str, u32>, // Machines restarted after a panic, and how often
+    pub panicked: Vec<&'static str>, // Threads that panicked and were not restarted
+}
+
+impl FinalReport {
+    /// Whether the intersection ended all-red with DontWalk.
+    pub fn is_safe(&self) -> bool {
+        self.stoplight == Some(StoplightState::Red) && self.crosswalk == Some(CrosswalkState::DontWalk)
+    }
+}
+
+impl fmt::Display for FinalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let how = if self.stopped_early { "stopped early" } else { "finished" };
+        write!(f, "{} after {} ticks", how, self.ticks)?;
+        if let (Some(stoplight), Some(crosswalk)) = (self.stoplight, self.crosswalk) {
+            write!(f, ", Stoplight {:?}, Crosswalk {:?}", stoplight, crosswalk)?;
+        }
+        if !self.is_safe() {
+            write!(f, ", NOT in the safe state")?;
+        }
+        if let Some(violation) = self.latched {
+            write!(f, ", fail-safe latched: {}", violation)?;
+        }
+        for (name, restarts) in &self.restarts {
+            write!(f, ", {} restarted {} times", name, restarts)?;
+        }
+        for name in &self.panicked {
+            write!(f, ", {} thread panicked", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A running controller, see [`Supervisor::start`].
+#[derive(Debug)]
+pub struct Supervisor {
+    stop: StopHandle,
+    reset: ResetHandle,
+    reports: StatusBus<FromMonitor>,
+    timer: JoinHandle<u32>,
+    planned: u32, // Ticks configured
+    workers: Vec<(&'static str, JoinHandle<WorkerExit>)>, // Stoplight and crosswalk
+    monitor: JoinHandle<Option<Violation>>, // Returns the monitor's latch
+}
+
+// Restarts of one machine before the supervisor gives up and stops the controller
+const MAX_RESTARTS: u32 = 3;
+
+// How the thread running a machine ended
+#[derive(Debug)]
+struct WorkerExit {
+    restarts: u32,
+    failed: bool, // Panicked again after MAX_RESTARTS
+}
+
+impl Supervisor {
+    /// Start the timer, stoplight, crosswalk and safety monitor threads.
+    /// The machines report to the monitor on a channel of its own, which
+    /// never drops an update, and publish on a status broadcast for each
+    /// other; the monitor's reports come out of [`Supervisor::wait`]. A machine that
+    /// panics is restarted up to three times, after which the controller
+    /// stops.
+    pub fn start(
+        stoplight: StoplightFsm,
+        crosswalk: CrosswalkFsm,
+        options: TimerOptions,
+        clock: Arc<dyn Clock>,
+        log: EventLog,
+    ) -> Supervisor {
+        let stop = StopHandle::default();
+        let (tx_stoplight, rx_stoplight) = mpsc::channel::<ToStoplight>();
+        let (tx_crosswalk, rx_crosswalk) = mpsc::channel::<ToCrosswalk>();
+        let (tx_monitor, status_for_monitor) = status_bus();
+        let reset = ResetHandle { monitor: Arc::new(Mutex::new(Some(tx_monitor.clone()))) };
+        let status = StatusOutbox { monitor: tx_monitor, broadcast: Broadcast::new() };
+        let stoplight_states = status.broadcast.subscribe(&[Topic::StoplightState], Backpressure::CoalesceLatest);
+        let (tx_reports, reports) = status_bus();
+
+        let planned = options.ticks;
+        let timer = {
+            let (tx_stoplight, tx_crosswalk, flag) = (tx_stoplight.clone(), tx_crosswalk.clone(), stop.flag());
+            let reset = reset.clone();
+            thread::spawn(move || {
+                let run = || run_timer(&tx_stoplight, &tx_crosswalk, &options, clock.as_ref(), &flag);
+                let ticks = panic::catch_unwind(AssertUnwindSafe(run));
+                // Even when the timer itself panicked
+                shut_down_safely(&tx_stoplight, &tx_crosswalk);
+                reset.close();
+                ticks.unwrap_or_else(|payload| panic::resume_unwind(payload))
+            })
+        };
+        let monitor = Arc::new(Mutex::new(SafetyMonitor::new()));
+        let workers = vec![
+            {
+                let channel = (tx_stoplight.clone(), rx_stoplight);
+                let (status, tx_crosswalk, monitor) = (status.clone(), tx_crosswalk.clone(), monitor.clone());
+                let (log, stop) = (log.clone(), stop.clone());
+                let handle = thread::spawn(move || {
+                    supervise_stoplight(stoplight, channel, status, tx_crosswalk, &monitor, log, stop)
+                });
+                ("Stoplight", handle)
+            },
+            {
+                let channel = (tx_crosswalk.clone(), rx_crosswalk);
+                let (tx_stoplight, stop) = (tx_stoplight.clone(), stop.clone());
+                let handle = thread::spawn(move || {
+                    supervise_crosswalk(crosswalk, channel, status, stoplight_states, tx_stoplight, log, stop)
+                });
+                ("Crosswalk", handle)
+            },
+        ];
+        let monitor = thread::spawn(move || {
+            run_monitor(&monitor, status_for_monitor, tx_stoplight, tx_crosswalk, Some(tx_reports));
+            lock(&monitor).latched()
+        });
+        Supervisor { stop, reset, reports, timer, planned, workers, monitor }
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    pub fn reset_handle(&self) -> ResetHandle {
+        self.reset.clone()
+    }
+
+    /// Ask the safety monitor to reset its latched fail-safe, see
+    /// [`ResetHandle::reset`].
+    pub fn reset(&self) -> bool {
+        self.reset.reset()
+    }
+
+    /// Stop at the start of the next tick; `wait` then returns once the
+    /// intersection is all-red with DontWalk and every thread has ended.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Pass every monitor report to `on_report` until the controller has
+    /// stopped, then join its threads. A panicked thread is reported rather
+    /// than propagated.
+    pub fn wait<F: FnMut(&FromMonitor)>(self, mut on_report: F) -> FinalReport {
+        let (mut stoplight, mut crosswalk) = (None, None);
+        for report in self.reports.iter() {
+            match report {
+                FromMonitor::Stoplight(state) => stoplight = Some(state),
+                FromMonitor::Crosswalk(state) => crosswalk = Some(state),
+                _ => {}
+            }
+            on_report(&report);
+        }
+        if self.reset.is_open() {
+            // The monitor is gone while the machines still run
+            eprintln!("Supervisor: safety monitor disconnected, stopping the controller");
+            self.stop.stop();
+        }
+
+        let mut panicked = Vec::new();
+        let ticks = self.timer.join().unwrap_or_else(|_| {
+            panicked.push("Timer");
+            0
+        });
+        let mut restarts = BTreeMap::new();
+        for (name, handle) in self.workers {
+            match handle.join() {
+                Ok(exit) => {
+                    if exit.restarts > 0 {
+                        restarts.insert(name, exit.restarts);
+                    }
+                    if exit.failed {
+                        panicked.push(name);
+                    }
+                }
+                Err(_) => panicked.push(name),
+            }
+        }
+        let latched = match self.monitor.join() {
+            Ok(latched) => latched,
+            Err(_) => {
+                panicked.push("Monitor");
+                None
+            }
+        };
+        let stopped_early = self.stop.is_stopped() && ticks < self.planned;
+        FinalReport { ticks, stopped_early, stoplight, crosswalk, latched, restarts, panicked }
+    }
+}
+
+// Bring both machines to all-red and DontWalk, then shut them down. Stoplight
+// first, as the monitor does, so the crosswalk never shows Walk against Green.
+fn shut_down_safely(tx_stoplight: &mpsc::Sender<ToStoplight>, tx_crosswalk: &mpsc::Sender<ToCrosswalk>) {
+    let _ = tx_stoplight.send(ToStoplight::FailSafe);
+    flush(tx_stoplight, ToStoplight::Flush);
+    let _ = tx_crosswalk.send(ToCrosswalk::FailSafe);
+    flush(tx_crosswalk, ToCrosswalk::Flush);
+    let _ = tx_stoplight.send(ToStoplight::Shutdown);
+    let _ = tx_crosswalk.send(ToCrosswalk::Shutdown);
+}
+
+// Run the stoplight, restarting it in Red (or still flashing) whenever it
+// panics, from the last state it handled a message in and with the commands
+// it had not yet handled. The crosswalk is held in DontWalk until it has been told the new
+// stoplight state, and for as long as the monitor is latched.
+fn supervise_stoplight(
+    mut fsm: StoplightFsm,
+    (tx, rx): (mpsc::Sender<ToStoplight>, mpsc::Receiver<ToStoplight>),
+    status: StatusOutbox,
+    tx_crosswalk: mpsc::Sender<ToCrosswalk>,
+    monitor: &Mutex<SafetyMonitor>,
+    log: EventLog,
+    stop: StopHandle,
+) -> WorkerExit {
+    let mut restarts = 0;
+    loop {
+        let mut checkpoint = fsm.checkpoint();
+        let on_step = |fsm: &StoplightFsm| checkpoint = fsm.checkpoint();
+        let run = || run_stoplight(fsm, &rx, Some(status.clone()), Some(tx_crosswalk.clone()), log.clone(), on_step);
+        if panic::catch_unwind(AssertUnwindSafe(run)).is_ok() {
+            return WorkerExit { restarts, failed: false };
+        }
+        let _ = tx_crosswalk.send(ToCrosswalk::FailSafe);
+        flush(&tx_crosswalk, ToCrosswalk::Flush);
+        if restarts == MAX_RESTARTS {
+            stop.stop();
+            return WorkerExit { restarts, failed: true };
+        }
+        let Some(queued) = drain_queued(&rx, is_stoplight_command) else {
+            return WorkerExit { restarts, failed: false };
+        };
+        restarts += 1;
+        fsm = checkpoint.restarted();
+        eprintln!("Supervisor: Stoplight thread panicked, restarting in {:?} ({} of {})", fsm.state, restarts, MAX_RESTARTS);
+        // Not left to the monitor to notice
+        let latched = lock(monitor).latched().is_some();
+        if latched {
+            fsm.handle_event(StoplightEvent::FailSafe, &PedestrianCall::Idle);
+        }
+        for message in queued.kept {
+            let _ = tx.send(message);
+        }
+        let _ = tx_crosswalk.send(ToCrosswalk::StoplightState(fsm.state));
+        if !latched {
+            let _ = tx_crosswalk.send(ToCrosswalk::Resume);
+        }
+        if let Some(ack) = queued.ack {
+            ack.done();
+        }
+    }
+}
+
+// Run the crosswalk, restarting it in DontWalk whenever it panics. The
+// stoplight is held all-red, or left flashing, until the new crosswalk has
+// started from its current state; preemption and rail state carry on.
+fn supervise_crosswalk(
+    mut fsm: CrosswalkFsm,
+    (tx, rx): (mpsc::Sender<ToCrosswalk>, mpsc::Receiver<ToCrosswalk>),
+    status: StatusOutbox,
+    stoplight_states: StatusBus,
+    tx_stoplight: mpsc::Sender<ToStoplight>,
+    log: EventLog,
+    stop: StopHandle,
+) -> WorkerExit {
+    // Latest published stoplight state
+    let mut stoplight = StoplightState::Red;
+    let catch_up = |stoplight: &mut StoplightState| {
+        while let Ok(FromController::Stoplight(FromStoplight::StateUpdate(state))) =
+            stoplight_states.recv_timeout(Duration::ZERO)
+        {
+            *stoplight = state;
+        }
+    };
+    let mut restarts = 0;
+    loop {
+        let restart = fsm.restarted();
+        let run = || run_crosswalk(fsm, stoplight, &rx, Some(status.clone()), Some(tx_stoplight.clone()), log.clone());
+        if panic::catch_unwind(AssertUnwindSafe(run)).is_ok() {
+            return WorkerExit { restarts, failed: false };
+        }
+        let _ = tx_stoplight.send(ToStoplight::Hold);
+        flush(&tx_stoplight, ToStoplight::Flush);
+        if restarts == MAX_RESTARTS {
+            stop.stop();
+            return WorkerExit { restarts, failed: true };
+        }
+        let Some(queued) = drain_queued(&rx, is_crosswalk_command) else {
+            return WorkerExit { restarts, failed: false };
+        };
+        restarts += 1;
+        eprintln!("Supervisor: Crosswalk thread panicked, restarting in DontWalk ({} of {})", restarts, MAX_RESTARTS);
+        catch_up(&mut stoplight);
+        fsm = restart;
+        for message in queued.kept {
+            let _ = tx.send(message);
+        }
+        let _ = tx_stoplight.send(ToStoplight::Release);
+        if let Some(ack) = queued.ack {
+            ack.done();
+        }
+    }
+}
+
+// What a panicked machine had not handled, up to the first flush
+struct Queued<T> {
+    kept: Vec<T>,     // To be sent again, for the new machine
+    ack: Option<Ack>, // The flush, acknowledged once the new machine is set up
+}
+
+// Take what a panicked machine had not handled, up to the first flush,
+// keeping the messages `keep` picks and dropping the rest. The timer in
+// lockstep waits for the flush acknowledgement. None when a Shutdown was
+// queued.
+fn drain_queued<T, E, C>(rx: &mpsc::Receiver<T>, keep: impl Fn(&T) -> bool) -> Option<Queued<T>>
+where
+    T: Into<Input<E, C>>,
+{
+    let mut kept = Vec::new();
+    while let Ok(message) = rx.try_recv() {
+        if keep(&message) {
+            kept.push(message);
+            continue;
+        }
+        match message.into() {
+            Input::Flush(ack) => return Some(Queued { kept, ack: Some(ack) }),
+            Input::Shutdown => return None,
+            Input::Event(_) | Input::Context(_) => {}
+        }
+    }
+    Some(Queued { kept, ack: None })
+}
+
+// Messages a restarted stoplight still has to handle: the safety, preemption
+// and plan commands. Ticks, calls and requests belong to the time it was down.
+fn is_stoplight_command(message: &ToStoplight) -> bool {
+    use ToStoplight::*;
+    !matches!(message, TimerTick | DetectorCall | TransitRequest | Pedestrian(_) | Flush(_) | Shutdown)
+}
+
+fn is_crosswalk_command(message: &ToCrosswalk) -> bool {
+    use ToCrosswalk::*;
+    matches!(message, Preempt | PreemptClear | FailSafe | Resume)
+}
+
+// Where a machine reports its status: the safety monitor's own channel, so
+// the monitor sees every update however far behind it is, and the broadcast
+// the other machine subscribes to
+#[derive(Clone)]
+struct StatusOutbox {
+    monitor: mpsc::Sender<ToMonitor>,
+    broadcast: Broadcast,
+}
+
+impl Outbox for StatusOutbox {
+    type Message = FromController;
+    type Error = mpsc::SendError<ToMonitor>;
+
+    fn post(&self, message: FromController) -> Result<(), Self::Error> {
+        self.broadcast.publish(message.clone());
+        self.monitor.send(message.into())
+    }
+}
+
+// The shared monitor, also after a panic in it
+fn lock(monitor: &Mutex<SafetyMonitor>) -> MutexGuard<'_, SafetyMonitor> {
+    monitor.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{SystemClock, VirtualClock};
+    use crate::config::{ButtonSchedule, PreemptRun, TrainRun};
+    use crate::event_log::{MemorySink, TransitionRecord, TransitionSink};
+    use crate::stoplight::{RailCrossing, StoplightTiming};
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Instant;
+
+    // Panics the machine's thread on its first `limit` transitions into `to`
+    struct PanicOn {
+        machine: &'static str,
+        to: &'static str,
+        limit: usize,
+        count: AtomicUsize,
+    }
+
+    impl TransitionSink for PanicOn {
+        fn record(&self, record: &TransitionRecord) {
+            if record.machine == self.machine && record.to == self.to && self.count.fetch_add(1, Ordering::SeqCst) < self.limit {
+                panic!("{} panicked entering {}", self.machine, self.to);
+            }
+        }
+    }
+
+    // Run 60 ticks with `sink` panicking a machine, recording what was logged
+    fn run_with_panics(sink: PanicOn) -> (FinalReport, usize, Vec<TransitionRecord>) {
+        run_with_panics_on(sink, StoplightFsm::new(), timer_options(60, Duration::from_secs(1)))
+    }
+
+    fn run_with_panics_on(
+        sink: PanicOn,
+        stoplight: StoplightFsm,
+        options: TimerOptions,
+    ) -> (FinalReport, usize, Vec<TransitionRecord>) {
+        let clock = Arc::new(VirtualClock::auto());
+        let memory = Arc::new(MemorySink::new());
+        let log = EventLog::new(clock.clone()).with_sink(Arc::new(sink)).with_sink(memory.clone());
+        let supervisor = Supervisor::start(stoplight, CrosswalkFsm::new(), options, clock, log);
+        let mut violations = 0;
+        let report = supervisor.wait(|report| violations += usize::from(matches!(report, FromMonitor::Violation(_))));
+        (report, violations, memory.records())
+    }
+
+    fn entered(records: &[TransitionRecord], machine: &str, to: &str) -> usize {
+        records.iter().filter(|record| record.machine == machine && record.to == to).count()
+    }
+
+    fn timer_options(ticks: u32, tick_period: Duration) -> TimerOptions {
+        TimerOptions {
+            ticks,
+            tick_period,
+            buttons: ButtonSchedule::Every(6),
+            detectors: ButtonSchedule::Never,
+            buses: ButtonSchedule::Never,
+            preempt: Vec::new(),
+            trains: Vec::new(),
+            schedule: None,
+            lockstep: true,
+        }
+    }
+
+    #[test]
+    fn test_run_ends_in_safe_state() {
+        let clock = Arc::new(VirtualClock::auto());
+        let log = EventLog::new(clock.clone());
+        // Green at the end of the run
+        let options = timer_options(8, Duration::from_secs(1));
+        let supervisor = Supervisor::start(StoplightFsm::new(), CrosswalkFsm::new(), options, clock, log);
+        let mut violations = 0;
+        let report = supervisor.wait(|report| violations += usize::from(matches!(report, FromMonitor::Violation(_))));
+        assert_eq!(violations, 0);
+        assert_eq!((report.ticks, report.stopped_early), (8, false));
+        assert!(report.is_safe(), "{}", report);
+        assert_eq!(report.latched, None);
+        assert!(report.panicked.is_empty());
+    }
+
+    #[test]
+    fn test_stop_on_demand() {
+        let clock = Arc::new(SystemClock::new());
+        let log = EventLog::new(clock.clone());
+        let options = timer_options(100_000, Duration::from_millis(5));
+        let supervisor = Supervisor::start(StoplightFsm::new(), CrosswalkFsm::new(), options, clock, log);
+        let stop = supervisor.stop_handle();
+        let start = Instant::now();
+        let mut stopped = false;
+        let report = supervisor.wait(|report| {
+            // Stop once the crosswalk has served a call
+            if *report == FromMonitor::Crosswalk(CrosswalkState::Walk) && !stopped {
+                stopped = true;
+                stop.stop();
+            }
+        });
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(report.stopped_early && report.ticks < 100_000);
+        assert!(report.is_safe(), "{}", report);
+    }
+
+    #[test]
+    fn test_reset_resumes_after_a_violation() {
+        // Walk while Green from the start
+        let clock = Arc::new(SystemClock::new());
+        let log = EventLog::new(clock.clone());
+        let stoplight = StoplightFsm::with_timing(StoplightState::Green, StoplightTiming::default());
+        let crosswalk = CrosswalkFsm::with_timing(CrosswalkState::Walk, Default::default());
+        let options = timer_options(100_000, Duration::from_millis(5));
+        let supervisor = Supervisor::start(stoplight, crosswalk, options, clock, log);
+        let (reset, stop) = (supervisor.reset_handle(), supervisor.stop_handle());
+        let (mut violations, mut resets, mut states, mut sent) = (0, Vec::new(), (None, None), false);
+        let report = supervisor.wait(|report| {
+            match *report {
+                FromMonitor::Violation(_) => violations += 1,
+                FromMonitor::Reset(result) => resets.push(result),
+                FromMonitor::Stoplight(state) => states.0 = Some(state),
+                FromMonitor::Crosswalk(state) => states.1 = Some(state),
+                _ => {}
+            }
+            // Reset once latched and all-red with DontWalk, stop once Green again
+            if violations == 1 && !sent && states == (Some(StoplightState::Red), Some(CrosswalkState::DontWalk)) {
+                sent = reset.reset();
+            }
+            if !resets.is_empty() && states.0 == Some(StoplightState::Green) {
+                stop.stop();
+            }
+        });
+        assert_eq!((violations, resets), (1, vec![Ok(())]));
+        assert_eq!(report.latched, None);
+        assert!(report.stopped_early && report.is_safe(), "{}", report);
+        assert!(!reset.reset());
+    }
+
+    #[test]
+    fn test_crosswalk_restarted_after_panic() {
+        let (report, violations, records) =
+            run_with_panics(PanicOn { machine: "Crosswalk", to: "Walk", limit: 1, count: AtomicUsize::new(0) });
+        assert_eq!(violations, 0);
+        assert_eq!(report.restarts, BTreeMap::from([("Crosswalk", 1)]));
+        assert!(report.panicked.is_empty() && !report.stopped_early);
+        assert!(report.is_safe(), "{}", report);
+        // Held all-red, not flashing, while it restarted, then pedestrians served again
+        assert_eq!(entered(&records, "Stoplight", "FlashingRed"), 0);
+        assert!(entered(&records, "Crosswalk", "Walk") > 0);
+    }
+
+    #[test]
+    fn test_crosswalk_restart_keeps_preemption() {
+        // The preemption cuts the first Walk short, and the crosswalk panics on it
+        let mut options = timer_options(60, Duration::from_secs(1));
+        options.preempt = vec![PreemptRun { approach: 1, priority: 1, at: 10, clear: 30 }];
+        let sink = PanicOn { machine: "Crosswalk", to: "BlinkingDontWalk", limit: 1, count: AtomicUsize::new(0) };
+        let (report, violations, records) = run_with_panics_on(sink, StoplightFsm::new(), options);
+        assert_eq!(violations, 0);
+        assert_eq!(report.restarts, BTreeMap::from([("Crosswalk", 1)]));
+        assert!(report.is_safe(), "{}", report);
+        // Still served after the restart rather than cancelled
+        let preempted = |r: &TransitionRecord| r.to == "Green" && r.reason.as_deref() == Some("preemption");
+        assert_eq!(records.iter().filter(|r| preempted(r)).count(), 1);
+    }
+
+    #[test]
+    fn test_stoplight_restarted_after_panic() {
+        let (report, violations, records) =
+            run_with_panics(PanicOn { machine: "Stoplight", to: "Green", limit: 1, count: AtomicUsize::new(0) });
+        assert_eq!(violations, 0);
+        assert_eq!(report.restarts, BTreeMap::from([("Stoplight", 1)]));
+        assert!(report.is_safe(), "{}", report);
+        assert!(entered(&records, "Stoplight", "Green") > 0);
+        assert!(entered(&records, "Crosswalk", "Walk") > 0);
+    }
+
+    #[test]
+    fn test_stoplight_restart_from_green_runs_the_all_red() {
+        // An all-red longer than Red, and a panic out of the first Green
+        let timing = StoplightTiming { red_clearance: 8, ..StoplightTiming::default() };
+        let stoplight = StoplightFsm::with_timing(StoplightState::Green, timing);
+        let mut options = timer_options(40, Duration::from_secs(1));
+        options.buttons = ButtonSchedule::Never;
+        let sink = PanicOn { machine: "Stoplight", to: "Yellow", limit: 1, count: AtomicUsize::new(0) };
+        let (report, violations, records) = run_with_panics_on(sink, stoplight, options);
+        assert_eq!(violations, 0);
+        assert_eq!(report.restarts, BTreeMap::from([("Stoplight", 1)]));
+        assert!(report.is_safe(), "{}", report);
+        // The panic is on the fourth tick, at 3 s; the full all-red runs before the next Green
+        let first_green = records.iter().find(|record| record.machine == "Stoplight" && record.to == "Green");
+        assert!(first_green.is_some_and(|record| record.timestamp_ms >= 3_000 + 8_000), "{:?}", records);
+    }
+
+    #[test]
+    fn test_stoplight_restart_keeps_rail_preemption() {
+        // A head that may run green while the train passes, panicking on its dwell green
+        let crossing = RailCrossing { track_approach: 1, track_clearance: 3, dwell_approaches: vec![2] };
+        let stoplight = StoplightFsm::new().with_approach(2).with_rail_crossing(crossing);
+        let mut options = timer_options(60, Duration::from_secs(1));
+        options.trains = vec![TrainRun { gate_down: 1, gate_up: 40 }];
+        let sink = PanicOn { machine: "Stoplight", to: "Green", limit: 1, count: AtomicUsize::new(0) };
+        let (report, violations, records) = run_with_panics_on(sink, stoplight, options);
+        assert_eq!(violations, 0);
+        assert_eq!(report.restarts, BTreeMap::from([("Stoplight", 1)]));
+        assert!(report.is_safe(), "{}", report);
+        // Back to the dwell green after clearing the track again, not normal cycling
+        let during_train: Vec<_> = records
+            .iter()
+            .filter(|record| record.machine == "Stoplight" && record.timestamp_ms < 40_000)
+            .map(|record| (record.to.as_str(), record.reason.as_deref()))
+            .collect();
+        assert_eq!(during_train, vec![("Green", Some("rail dwell"))]);
+    }
+
+    #[test]
+    fn test_gives_up_after_repeated_panics() {
+        let (report, _, _) =
+            run_with_panics(PanicOn { machine: "Crosswalk", to: "Walk", limit: 10, count: AtomicUsize::new(0) });
+        assert_eq!(report.restarts, BTreeMap::from([("Crosswalk", MAX_RESTARTS)]));
+        assert_eq!(report.panicked, vec!["Crosswalk"]);
+        assert!(report.stopped_early);
+        assert_eq!(report.stoplight, Some(StoplightState::Red));
+    }
+
+    #[test]
+    fn test_monitor_sees_every_update() {
+        // Far more updates than any subscriber queue holds, none of them read yet
+        let (tx_monitor, rx_monitor) = status_bus();
+        let status = StatusOutbox { monitor: tx_monitor, broadcast: Broadcast::new() };
+        let latest = status.broadcast.subscribe(&[Topic::StoplightState], Backpressure::CoalesceLatest);
+        let states = [StoplightState::Green, StoplightState::Yellow, StoplightState::Red];
+        for &state in states.iter().cycle().take(10_000) {
+            status.post(FromStoplight::StateUpdate(state).into()).unwrap();
+        }
+        drop(status);
+        let seen: Vec<_> = rx_monitor.iter().collect();
+        assert_eq!(seen.len(), 10_000);
+        assert!(matches!(seen[..2], [ToMonitor::Stoplight(StoplightState::Green), ToMonitor::Stoplight(StoplightState::Yellow)]));
+        assert_eq!(latest.iter().count(), 1);
+    }
+}
@@ -0,0 +1,379 @@
+use std::fmt::{self, Debug};
+
+use crate::fsm::{StateMachine, Transition};
+use crate::graph::Diagram;
+
+type GuardFn<C> = Box<dyn Fn(u32, &C) -> bool + Send>;
+type ActionFn<S, E> = Box<dyn FnMut(&Transition<S>, &E) + Send>;
+
+/// Condition attached to a row of a [`TransitionTable`].
+///
+/// Guard functions receive the ticks spent in the current state and the
+/// machine's context.
+pub enum Guard<C> {
+    Always,
+    When(&'static str, GuardFn<C>),
+    Otherwise, // Taken when no other row for the same state and event matches
+}
+
+impl<C> Guard<C> {
+    fn label(&self) -> &str {
+        match self {
+            Guard::Always => "",
+            Guard::When(name, _) => name,
+            Guard::Otherwise => "else",
+        }
+    }
+}
+
+/// One `(state, event, guard, next state, action)` row of a transition table.
+pub struct Row<S, E, C> {
+    from: S,
+    event: E,
+    guard: Guard<C>,
+    to: S,
+    action: Option<ActionFn<S, E>>,
+}
+
+impl<S, E, C> Row<S, E, C> {
+    pub fn new(from: S, event: E, to: S) -> Self {
+        Row { from, event, guard: Guard::Always, to, action: None }
+    }
+
+    pub fn when(mut self, name: &'static str, guard: impl Fn(u32, &C) -> bool + Send + 'static) -> Self {
+        self.guard = Guard::When(name, Box::new(guard));
+        self
+    }
+
+    pub fn otherwise(mut self) -> Self {
+        self.guard = Guard::Otherwise;
+        self
+    }
+
+    // Action run whenever the row fires, including rows that stay in the same state
+    pub fn action(mut self, action: impl FnMut(&Transition<S>, &E) + Send + 'static) -> Self {
+        self.action = Some(Box::new(action));
+        self
+    }
+}
+
+/// Problem found while checking a transition table.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TableError {
+    // A row refers to a state or event that was not declared
+    Undeclared { row: usize, item: String },
+    // Two or more rows can fire for the same state and event
+    Overlap { state: String, event: String, rows: Vec<usize> },
+    // No row fires for some state and event (or only guarded rows without an `otherwise`)
+    Gap { state: String, event: String },
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Undeclared { row, item } => write!(f, "row {} uses undeclared {}", row, item),
+            TableError::Overlap { state, event, rows } => {
+                write!(f, "rows {:?} overlap for state {} on event {}", rows, state, event)
+            }
+            TableError::Gap { state, event } => write!(f, "no transition for state {} on event {}", state, event),
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+/// State/event table, as described in finite_state_machine.md, that builds a
+/// [`TableMachine`] at runtime.
+///
+/// Every declared state must handle every declared event, either through one
+/// unguarded row or through guarded rows closed by an `otherwise` row.
+pub struct TransitionTable<S, E, C> {
+    name: String,
+    states: Vec<S>,
+    events: Vec<E>,
+    tick_event: Option<E>,
+    rows: Vec<Row<S, E, C>>,
+}
+
+impl<S, E, C> TransitionTable<S, E, C>
+where
+    S: Copy + PartialEq + Debug,
+    E: PartialEq + Debug + Clone,
+{
+    pub fn new(name: &str, states: &[S], events: &[E]) -> Self {
+        TransitionTable {
+            name: name.to_string(),
+            states: states.to_vec(),
+            events: events.to_vec(),
+            tick_event: None,
+            rows: Vec::new(),
+        }
+    }
+
+    // Event that advances the ticks-in-state counter seen by guards
+    pub fn tick_event(mut self, event: E) -> Self {
+        self.tick_event = Some(event);
+        self
+    }
+
+    pub fn row(mut self, row: Row<S, E, C>) -> Self {
+        self.rows.push(row);
+        self
+    }
+
+    /// State diagram of the rows that change state, labelled with event and guard.
+    pub fn diagram(&self, initial: S) -> Diagram {
+        let mut diagram = Diagram::new(&self.name, format!("{:?}", initial));
+        for row in self.rows.iter().filter(|r| r.from != r.to) {
+            let label = match &row.guard {
+                Guard::When(name, _) => format!("{:?} [{}]", row.event, name),
+                _ => format!("{:?}", row.event),
+            };
+            diagram = diagram.edge(format!("{:?}", row.from), format!("{:?}", row.to), label);
+        }
+        diagram
+    }
+
+    /// Check that the table is deterministic and complete.
+    pub fn validate(&self) -> Result<(), Vec<TableError>> {
+        let mut errors = Vec::new();
+
+        for (index, row) in self.rows.iter().enumerate() {
+            for state in [row.from, row.to] {
+                if !self.states.contains(&state) {
+                    errors.push(TableError::Undeclared { row: index, item: format!("state {:?}", state) });
+                }
+            }
+            if !self.events.contains(&row.event) {
+                errors.push(TableError::Undeclared { row: index, item: format!("event {:?}", row.event) });
+            }
+        }
+
+        for state in &self.states {
+            for event in &self.events {
+                let matching: Vec<usize> = (0..self.rows.len())
+                    .filter(|&i| self.rows[i].from == *state && self.rows[i].event == *event)
+                    .collect();
+                let count = |pred: fn(&Guard<C>) -> bool| matching.iter().filter(|&&i| pred(&self.rows[i].guard)).count();
+                let always = count(|g| matches!(g, Guard::Always));
+                let otherwise = count(|g| matches!(g, Guard::Otherwise));
+
+                // Two guards with the same name always agree, so they overlap too
+                let mut names: Vec<&str> = matching.iter().map(|&i| self.rows[i].guard.label()).collect();
+                names.sort_unstable();
+                let duplicate_guard = names.windows(2).any(|w| w[0] == w[1] && !w[0].is_empty());
+
+                let overlap = always > 1 || otherwise > 1 || (always == 1 && matching.len() > 1) || duplicate_guard;
+                if overlap {
+                    errors.push(TableError::Overlap {
+                        state: format!("{:?}", state),
+                        event: format!("{:?}", event),
+                        rows: matching.clone(),
+                    });
+                } else if always == 0 && otherwise == 0 {
+                    errors.push(TableError::Gap { state: format!("{:?}", state), event: format!("{:?}", event) });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn build(self, initial: S) -> Result<TableMachine<S, E, C>, Vec<TableError>> {
+        self.validate()?;
+        if !self.states.contains(&initial) {
+            return Err(vec![TableError::Undeclared { row: 0, item: format!("initial state {:?}", initial) }]);
+        }
+        Ok(TableMachine {
+            name: self.name,
+            tick_event: self.tick_event,
+            rows: self.rows,
+            state: initial,
+            timer_ticks_in_state: 0,
+        })
+    }
+}
+
+/// State machine driven by a validated [`TransitionTable`].
+pub struct TableMachine<S, E, C> {
+    name: String,
+    tick_event: Option<E>,
+    rows: Vec<Row<S, E, C>>,
+    state: S,
+    timer_ticks_in_state: u32,
+}
+
+impl<S, E, C> TableMachine<S, E, C> {
+    pub fn ticks_in_state(&self) -> u32 {
+        self.timer_ticks_in_state
+    }
+}
+
+impl<S, E, C> StateMachine for TableMachine<S, E, C>
+where
+    S: Copy + PartialEq + Debug,
+    E: PartialEq + Debug,
+{
+    type State = S;
+    type Event = E;
+    type Context = C;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn state(&self) -> S {
+        self.state
+    }
+
+    fn next_state(&mut self, event: &E, ctx: &C) -> S {
+        if self.tick_event.as_ref() == Some(event) {
+            self.timer_ticks_in_state += 1;
+        }
+        let ticks = self.timer_ticks_in_state;
+        let state = self.state;
+        let candidates = || self.rows.iter().enumerate().filter(|(_, r)| r.from == state && r.event == *event);
+
+        // Unguarded and guarded rows first, `otherwise` only if none of them fire
+        let fired = candidates()
+            .find(|(_, r)| match &r.guard {
+                Guard::Always => true,
+                Guard::When(_, guard) => guard(ticks, ctx),
+                Guard::Otherwise => false,
+            })
+            .or_else(|| candidates().find(|(_, r)| matches!(r.guard, Guard::Otherwise)))
+            .map(|(i, _)| i);
+
+        match fired {
+            Some(index) => {
+                let row = &mut self.rows[index];
+                let transition = Transition { from: row.from, to: row.to, reason: None };
+                if let Some(action) = row.action.as_mut() {
+                    action(&transition, event);
+                }
+                transition.to
+            }
+            None => state, // Unreachable for a validated table
+        }
+    }
+
+    fn enter(&mut self, state: S) {
+        self.state = state;
+        self.timer_ticks_in_state = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crosswalk::PedestrianCall;
+    use crate::stoplight::{StoplightEvent, StoplightFsm, StoplightState};
+    use std::sync::{Arc, Mutex};
+
+    fn stoplight_table() -> TransitionTable<StoplightState, StoplightEvent, ()> {
+        use StoplightEvent::TimerTick;
+        use StoplightState::*;
+        TransitionTable::new("Stoplight", &[Red, Green, Yellow], &[TimerTick])
+            .tick_event(TimerTick)
+            .row(Row::new(Red, TimerTick, Green).when("red elapsed", |t, _| t >= StoplightFsm::RED_DURATION))
+            .row(Row::new(Red, TimerTick, Red).otherwise())
+            .row(Row::new(Green, TimerTick, Yellow).when("green elapsed", |t, _| t >= StoplightFsm::GREEN_DURATION))
+            .row(Row::new(Green, TimerTick, Green).otherwise())
+            .row(Row::new(Yellow, TimerTick, Red).when("yellow elapsed", |t, _| t >= StoplightFsm::YELLOW_DURATION))
+            .row(Row::new(Yellow, TimerTick, Yellow).otherwise())
+    }
+
+    #[test]
+    fn test_table_matches_hand_written_stoplight() {
+        let mut table = stoplight_table().build(StoplightState::Red).unwrap();
+        let mut fsm = StoplightFsm::new();
+        for _ in 0..40 {
+            let a = table.handle_event(StoplightEvent::TimerTick, &());
+            let b = fsm.handle_event(StoplightEvent::TimerTick, &PedestrianCall::Idle);
+            assert_eq!(a, b);
+            assert_eq!(table.ticks_in_state(), fsm.ticks_in_state());
+        }
+    }
+
+    #[test]
+    fn test_table_diagram() {
+        let diagram = stoplight_table().diagram(StoplightState::Red);
+        assert_eq!(diagram.edges.len(), 3);
+        assert_eq!(diagram.edges[0].label, "TimerTick [red elapsed]");
+    }
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    enum Turnstile {
+        Locked,
+        Unlocked,
+    }
+
+    #[derive(Debug, PartialEq, Clone)]
+    enum Input {
+        Coin,
+        Push,
+    }
+
+    #[test]
+    fn test_turnstile_table_runs_actions() {
+        use Input::*;
+        use Turnstile::*;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let unlock_log = Arc::clone(&log);
+        let lock_log = Arc::clone(&log);
+        let mut turnstile = TransitionTable::<Turnstile, Input, ()>::new("Turnstile", &[Locked, Unlocked], &[Coin, Push])
+            .row(Row::new(Locked, Coin, Unlocked).action(move |_, _| unlock_log.lock().unwrap().push("unlock")))
+            .row(Row::new(Locked, Push, Locked))
+            .row(Row::new(Unlocked, Coin, Unlocked))
+            .row(Row::new(Unlocked, Push, Locked).action(move |_, _| lock_log.lock().unwrap().push("lock")))
+            .build(Locked)
+            .unwrap();
+
+        assert_eq!(turnstile.handle_event(Push, &()), None);
+        assert_eq!(turnstile.handle_event(Coin, &()), Some(Transition { from: Locked, to: Unlocked, reason: None }));
+        assert_eq!(turnstile.handle_event(Coin, &()), None);
+        assert_eq!(turnstile.handle_event(Push, &()), Some(Transition { from: Unlocked, to: Locked, reason: None }));
+        assert_eq!(*log.lock().unwrap(), vec!["unlock", "lock"]);
+    }
+
+    #[test]
+    fn test_overlaps_and_gaps_are_reported() {
+        use Input::*;
+        use Turnstile::*;
+        let errors = TransitionTable::<Turnstile, Input, ()>::new("Turnstile", &[Locked, Unlocked], &[Coin, Push])
+            .row(Row::new(Locked, Coin, Unlocked))
+            .row(Row::new(Locked, Coin, Locked)) // Overlaps the row above
+            .row(Row::new(Locked, Push, Locked))
+            .row(Row::new(Unlocked, Push, Locked).when("pushed", |_, _| true)) // No `otherwise`
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                TableError::Overlap { state: "Locked".into(), event: "Coin".into(), rows: vec![0, 1] },
+                TableError::Gap { state: "Unlocked".into(), event: "Coin".into() },
+                TableError::Gap { state: "Unlocked".into(), event: "Push".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_guarded_rows_fall_back_to_otherwise() {
+        use StoplightEvent::TimerTick;
+        use StoplightState::*;
+        // Context decides whether Red may end, e.g. a pedestrian still crossing
+        let mut fsm = TransitionTable::<StoplightState, StoplightEvent, bool>::new("Hold", &[Red, Green], &[TimerTick])
+            .row(Row::new(Red, TimerTick, Green).when("clear", |_, clear| *clear))
+            .row(Row::new(Red, TimerTick, Red).otherwise())
+            .row(Row::new(Green, TimerTick, Red))
+            .build(Red)
+            .unwrap();
+        assert_eq!(fsm.handle_event(TimerTick, &false), None);
+        assert_eq!(fsm.handle_event(TimerTick, &true), Some(Transition { from: Red, to: Green, reason: None }));
+    }
+}
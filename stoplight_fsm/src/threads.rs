@@ -0,0 +1,602 @@
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::config::{ButtonSchedule, PreemptRun, TrainRun};
+use crate::crosswalk::{CrosswalkEvent, CrosswalkFsm, CrosswalkState, PedestrianCall};
+use crate::event_log::EventLog;
+use crate::fsm::{run_machine_on, Ack, Exit, StateMachine, Transition, Trigger};
+use crate::messages::{FromCrosswalk, FromStoplight, Recorded, ToCrosswalk, ToStoplight};
+use crate::schedule::PlanScheduler;
+use crate::stoplight::{StoplightEvent, StoplightFsm, StoplightState};
+
+/// Settings for [`timer_thread`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct TimerOptions {
+    pub ticks: u32,
+    pub tick_period: Duration,
+    pub buttons: ButtonSchedule,
+    pub detectors: ButtonSchedule, // Vehicle detector calls, sent to the stoplight
+    pub buses: ButtonSchedule,     // Transit priority requests
+    pub preempt: Vec<PreemptRun>,
+    pub trains: Vec<TrainRun>,
+    pub schedule: Option<PlanScheduler>, // Timing plans by time of day, from the clock
+    // Wait for the stoplight, then the crosswalk, to handle each tick before
+    // sending the next one. Makes message ordering reproducible.
+    pub lockstep: bool,
+}
+
+/// Where a controller thread or task sends its messages: a channel, or a
+/// [`crate::bus::Broadcast`] to any number of subscribers.
+pub trait Outbox {
+    type Message;
+    type Error: fmt::Display;
+
+    fn post(&self, message: Self::Message) -> Result<(), Self::Error>;
+}
+
+impl<T> Outbox for mpsc::Sender<T> {
+    type Message = T;
+    type Error = mpsc::SendError<T>;
+
+    fn post(&self, message: T) -> Result<(), Self::Error> {
+        self.send(message)
+    }
+}
+
+// Send a flush marker and wait until the receiving thread has handled everything before it
+pub(crate) fn flush<T>(tx: &mpsc::Sender<T>, marker: fn(Ack) -> T) -> bool {
+    let (ack_tx, ack_rx) = mpsc::channel();
+    tx.send(marker(ack_tx.into())).is_ok() && ack_rx.recv().is_ok()
+}
+
+// Inputs the timer sends the stoplight ahead of a tick
+struct TickInputs {
+    scheduler: Option<PlanScheduler>,
+    flashing: bool, // The active plan is a flash plan
+}
+
+impl TickInputs {
+    fn new(options: &TimerOptions) -> Self {
+        TickInputs { scheduler: options.schedule.clone(), flashing: false }
+    }
+
+    // A vehicle arriving since the last tick is seen by this one
+    fn stoplight(&mut self, options: &TimerOptions, tick: u32, now: Duration) -> Vec<ToStoplight> {
+        let mut inputs = Vec::new();
+        if let Some(plan) = self.scheduler.as_mut().and_then(|scheduler| scheduler.poll(now)) {
+            match plan.flash {
+                Some(mode) => inputs.push(ToStoplight::Flash(mode)),
+                None => {
+                    // Timing first, so a flash ends into the new plan
+                    inputs.push(ToStoplight::ChangeTiming(plan.timing));
+                    if self.flashing {
+                        inputs.push(ToStoplight::ExitFlash);
+                    }
+                }
+            }
+            self.flashing = plan.flash.is_some();
+        }
+        if options.detectors.pressed_at(tick) {
+            inputs.push(ToStoplight::DetectorCall);
+        }
+        if options.buses.pressed_at(tick) {
+            inputs.push(ToStoplight::TransitRequest);
+        }
+        for run in &options.preempt {
+            if run.at == tick {
+                inputs.push(ToStoplight::Preempt(run.request()));
+            } else if run.clear == tick {
+                inputs.push(ToStoplight::PreemptClear(run.approach));
+            }
+        }
+        for train in &options.trains {
+            if train.gate_down == tick {
+                inputs.push(ToStoplight::GateDown);
+            } else if train.gate_up == tick {
+                inputs.push(ToStoplight::GateUp);
+            }
+        }
+        inputs
+    }
+}
+
+// The messages of one tick, see TickSequence::next
+#[derive(Debug)]
+pub(crate) struct Tick {
+    pub(crate) stoplight: Vec<ToStoplight>,
+    pub(crate) crosswalk: Vec<ToCrosswalk>,
+}
+
+impl Tick {
+    // Send the tick's messages. With an `ack`, as in lockstep, the crosswalk's
+    // are held back until the stoplight has handled its own, so the
+    // stoplight's new state reaches the crosswalk before its TimerTick; a
+    // call placed on this tick may be granted at once, so the flush then goes
+    // round the crosswalk, the stoplight and the crosswalk again before `ack`
+    // is done. The machines pass it on between themselves: one round trip for
+    // the timer. A machine that has stopped drops `ack`.
+    pub(crate) fn send<S, C>(self, tx_stoplight: &S, tx_crosswalk: &C, ack: Option<Ack>) -> Result<(), &'static str>
+    where
+        S: Outbox<Message = ToStoplight> + Clone + Send + 'static,
+        C: Outbox<Message = ToCrosswalk> + Clone + Send + 'static,
+    {
+        for message in self.stoplight {
+            tx_stoplight.post(message).map_err(|_| "failed to send to the stoplight")?;
+        }
+        let Some(ack) = ack else {
+            for message in self.crosswalk {
+                tx_crosswalk.post(message).map_err(|_| "failed to send to the crosswalk")?;
+            }
+            return Ok(());
+        };
+        let (stoplight, crosswalk) = (tx_stoplight.clone(), tx_crosswalk.clone());
+        let grant = Ack::new(move || {
+            let _ = stoplight.post(ToStoplight::Flush(Ack::new(move || {
+                let _ = crosswalk.post(ToCrosswalk::Flush(ack));
+            })));
+        });
+        let crosswalk = tx_crosswalk.clone();
+        let messages = self.crosswalk;
+        let tick = Ack::new(move || {
+            for message in messages {
+                let _ = crosswalk.post(message);
+            }
+            let _ = crosswalk.post(ToCrosswalk::Flush(grant));
+        });
+        tx_stoplight.post(ToStoplight::Flush(tick)).map_err(|_| "failed to send to the stoplight")
+    }
+}
+
+// The timer's per-tick messages, shared by run_timer and the async driver's
+// timer_task, which each wait for a lockstep tick their own way
+pub(crate) struct TickSequence<'a> {
+    options: &'a TimerOptions,
+    inputs: TickInputs,
+}
+
+impl<'a> TickSequence<'a> {
+    pub(crate) fn new(options: &'a TimerOptions) -> Self {
+        TickSequence { options, inputs: TickInputs::new(options) }
+    }
+
+    // The messages of tick `tick`, in order
+    pub(crate) fn next(&mut self, tick: u32, now: Duration) -> Tick {
+        let mut stoplight = self.inputs.stoplight(self.options, tick, now);
+        stoplight.push(ToStoplight::TimerTick);
+        let mut crosswalk = vec![ToCrosswalk::TimerTick];
+        if self.options.buttons.pressed_at(tick) {
+            crosswalk.push(ToCrosswalk::ButtonPress);
+        }
+        Tick { stoplight, crosswalk }
+    }
+}
+
+// Timer thread function
+pub fn timer_thread(
+    tx_stoplight: mpsc::Sender<ToStoplight>,
+    tx_crosswalk: mpsc::Sender<ToCrosswalk>,
+    options: TimerOptions,
+    clock: Arc<dyn Clock>,
+) {
+    run_timer(&tx_stoplight, &tx_crosswalk, &options, clock.as_ref(), &AtomicBool::new(false));
+
+    // Simulation finished, send Shutdown signals
+    if let Err(e) = tx_stoplight.send(ToStoplight::Shutdown) {
+        eprintln!("Timer thread: failed to send Shutdown to stoplight: {}", e);
+    }
+    if let Err(e) = tx_crosswalk.send(ToCrosswalk::Shutdown) {
+        eprintln!("Timer thread: failed to send Shutdown to crosswalk: {}", e);
+    }
+}
+
+// Tick loop of timer_thread, also run by the supervisor. Stops early, at the
+// start of a tick, once `stop` is set. Returns the number of ticks sent.
+pub(crate) fn run_timer(
+    tx_stoplight: &mpsc::Sender<ToStoplight>,
+    tx_crosswalk: &mpsc::Sender<ToCrosswalk>,
+    options: &TimerOptions,
+    clock: &dyn Clock,
+    stop: &AtomicBool,
+) -> u32 {
+    let mut sequence = TickSequence::new(options);
+    for tick in 0..options.ticks {
+        if stop.load(Ordering::SeqCst) {
+            return tick;
+        }
+        let messages = sequence.next(tick, clock.now());
+        let sent = if options.lockstep {
+            let (ack_tx, ack_rx) = mpsc::channel();
+            messages
+                .send(tx_stoplight, tx_crosswalk, Some(ack_tx.into()))
+                .and_then(|()| ack_rx.recv().map_err(|_| "tick not acknowledged"))
+        } else {
+            messages.send(tx_stoplight, tx_crosswalk, None)
+        };
+        if let Err(failure) = sent {
+            eprintln!("Timer thread: {} on tick {}", failure, tick);
+            return tick;
+        }
+        clock.sleep(options.tick_period);
+    }
+    options.ticks
+}
+
+// Stoplight thread function. State updates go to `tx_main` as any message
+// built from FromStoplight, e.g. straight to the safety monitor or onto a
+// bus::Broadcast, followed by a record of each preemption cycle as it ends
+// and of each transit priority decision. The crosswalk gets every new state,
+// Preempt and PreemptClear around each preemption, and a WalkGranted when Red
+// starts being held for its call.
+pub fn stoplight_thread<O>(
+    fsm: StoplightFsm,
+    rx: mpsc::Receiver<ToStoplight>,
+    tx_main: Option<O>,
+    tx_crosswalk: Option<mpsc::Sender<ToCrosswalk>>,
+    log: EventLog,
+) where
+    O: Outbox,
+    O::Message: From<FromStoplight>,
+{
+    run_stoplight(fsm, &rx, tx_main, tx_crosswalk, log, |_| {});
+}
+
+// The body of stoplight_thread, on a receiver the supervisor keeps across
+// restarts. `checkpoint` sees the machine after every step, before it is reported.
+pub(crate) fn run_stoplight<O>(
+    mut fsm: StoplightFsm,
+    rx: &mpsc::Receiver<ToStoplight>,
+    tx_main: Option<O>,
+    tx_crosswalk: Option<mpsc::Sender<ToCrosswalk>>,
+    log: EventLog,
+    mut checkpoint: impl FnMut(&StoplightFsm),
+) -> Exit
+where
+    O: Outbox,
+    O::Message: From<FromStoplight>,
+{
+    let mut reports = StoplightReports::new("Stoplight thread", tx_main, tx_crosswalk, log);
+    reports.start(&fsm);
+    run_machine_on(&mut fsm, PedestrianCall::Idle, rx, |fsm, transition, trigger| {
+        checkpoint(fsm);
+        reports.step(fsm, transition, trigger)
+    })
+}
+
+// What the stoplight sends after each step, for stoplight_thread and the
+// async driver
+pub(crate) struct StoplightReports<M, C> {
+    name: &'static str, // Prefix of error messages
+    tx_main: Option<M>,
+    tx_crosswalk: Option<C>,
+    log: EventLog,
+    tick: u64,
+    granted: bool,
+    preempted: bool,
+    cycles_reported: usize,
+    decisions_reported: usize,
+}
+
+impl<M, C> StoplightReports<M, C>
+where
+    M: Outbox,
+    M::Message: From<FromStoplight>,
+    C: Outbox<Message = ToCrosswalk>,
+{
+    pub(crate) fn new(name: &'static str, tx_main: Option<M>, tx_crosswalk: Option<C>, log: EventLog) -> Self {
+        StoplightReports {
+            name,
+            tx_main,
+            tx_crosswalk,
+            log,
+            tick: 0,
+            granted: false,
+            preempted: false,
+            cycles_reported: 0,
+            decisions_reported: 0,
+        }
+    }
+
+    fn to_main(&self, message: FromStoplight, what: &str) {
+        if let Some(ref sender) = self.tx_main {
+            if let Err(e) = sender.post(message.into()) {
+                eprintln!("{}: failed to send {} to main: {}", self.name, what, e);
+            }
+        }
+    }
+
+    fn to_crosswalk(&self, message: ToCrosswalk, what: &str) {
+        if let Some(ref sender) = self.tx_crosswalk {
+            if let Err(e) = sender.post(message) {
+                eprintln!("{}: failed to send {} to crosswalk: {}", self.name, what, e);
+            }
+        }
+    }
+
+    // Initial state, to main and to the crosswalk
+    pub(crate) fn start(&self, fsm: &StoplightFsm) {
+        self.to_main(FromStoplight::StateUpdate(fsm.state), "initial state");
+        self.to_crosswalk(ToCrosswalk::StoplightState(fsm.state), "initial state");
+    }
+
+    pub(crate) fn step(
+        &mut self,
+        fsm: &StoplightFsm,
+        transition: Option<Transition<StoplightState>>,
+        trigger: Trigger<'_, StoplightEvent, PedestrianCall>,
+    ) {
+        if let Trigger::Event(StoplightEvent::TimerTick) = trigger {
+            self.tick += 1;
+        }
+        let message = Some(Recorded::Stoplight(ToStoplight::from_trigger(&trigger)));
+        self.log.step(fsm.name(), self.tick, message, transition.as_ref(), describe("Pedestrian", &trigger));
+
+        // If state changed, send update to main
+        if transition.is_some() {
+            self.to_main(FromStoplight::StateUpdate(fsm.state), "state update");
+        }
+        for cycle in &fsm.preemption_cycles()[self.cycles_reported..] {
+            self.to_main(FromStoplight::Preemption(*cycle), "preemption record");
+        }
+        self.cycles_reported = fsm.preemption_cycles().len();
+        for decision in &fsm.transit_decisions()[self.decisions_reported..] {
+            self.to_main(FromStoplight::TransitPriority(*decision), "transit decision");
+        }
+        self.decisions_reported = fsm.transit_decisions().len();
+        // Send current state to the crosswalk after every event; a call
+        // from the crosswalk alone cannot change it
+        let grant = fsm.pedestrian_hold() && !self.granted;
+        self.granted = fsm.pedestrian_hold();
+        if matches!(trigger, Trigger::Event(_)) || transition.is_some() {
+            self.to_crosswalk(ToCrosswalk::StoplightState(fsm.state), "state");
+        }
+        // A new preemption is announced before any grant, which the crosswalk then ignores
+        if fsm.is_preempted() != self.preempted {
+            self.preempted = !self.preempted;
+            let message = if self.preempted { ToCrosswalk::Preempt } else { ToCrosswalk::PreemptClear };
+            self.to_crosswalk(message, "preemption");
+        }
+        // After the state, so the crosswalk sees Red before the grant
+        if grant {
+            self.to_crosswalk(ToCrosswalk::WalkGranted, "WalkGranted");
+        }
+    }
+}
+
+// Crosswalk thread function. Its pedestrian call goes to `tx_stoplight`
+// whenever it changes.
+pub fn crosswalk_thread<O>(
+    fsm: CrosswalkFsm,
+    rx: mpsc::Receiver<ToCrosswalk>,
+    tx_main: Option<O>,
+    tx_stoplight: Option<mpsc::Sender<ToStoplight>>,
+    log: EventLog,
+) where
+    O: Outbox,
+    O::Message: From<FromCrosswalk>,
+{
+    // Default to Red, will be updated by the first message from stoplight_thread
+    run_crosswalk(fsm, StoplightState::Red, &rx, tx_main, tx_stoplight, log);
+}
+
+// The body of crosswalk_thread, starting from a known stoplight state
+pub(crate) fn run_crosswalk<O>(
+    mut fsm: CrosswalkFsm,
+    stoplight: StoplightState,
+    rx: &mpsc::Receiver<ToCrosswalk>,
+    tx_main: Option<O>,
+    tx_stoplight: Option<mpsc::Sender<ToStoplight>>,
+    log: EventLog,
+) -> Exit
+where
+    O: Outbox,
+    O::Message: From<FromCrosswalk>,
+{
+    let mut reports = CrosswalkReports::new("Crosswalk thread", &fsm, tx_main, tx_stoplight, log);
+    reports.start(&fsm);
+    run_machine_on(&mut fsm, stoplight, rx, |fsm, transition, trigger| reports.step(fsm, transition, trigger))
+}
+
+// What the crosswalk sends after each step, for crosswalk_thread and the
+// async driver
+pub(crate) struct CrosswalkReports<M, S> {
+    name: &'static str,
+    tx_main: Option<M>,
+    tx_stoplight: Option<S>,
+    log: EventLog,
+    tick: u64,
+    call: PedestrianCall, // Last call sent to the stoplight
+}
+
+impl<M, S> CrosswalkReports<M, S>
+where
+    M: Outbox,
+    M::Message: From<FromCrosswalk>,
+    S: Outbox<Message = ToStoplight>,
+{
+    pub(crate) fn new(
+        name: &'static str,
+        fsm: &CrosswalkFsm,
+        tx_main: Option<M>,
+        tx_stoplight: Option<S>,
+        log: EventLog,
+    ) -> Self {
+        CrosswalkReports { name, tx_main, tx_stoplight, log, tick: 0, call: fsm.pedestrian_call() }
+    }
+
+    fn to_main(&self, message: FromCrosswalk, what: &str) {
+        if let Some(ref sender) = self.tx_main {
+            if let Err(e) = sender.post(message.into()) {
+                eprintln!("{}: failed to send {} to main: {}", self.name, what, e);
+            }
+        }
+    }
+
+    fn to_stoplight(&self, what: &str) {
+        if let Some(ref sender) = self.tx_stoplight {
+            if let Err(e) = sender.post(ToStoplight::Pedestrian(self.call)) {
+                eprintln!("{}: failed to send {} to stoplight: {}", self.name, what, e);
+            }
+        }
+    }
+
+    // Initial call to the stoplight and state to main
+    pub(crate) fn start(&self, fsm: &CrosswalkFsm) {
+        self.to_stoplight("initial call");
+        self.to_main(FromCrosswalk::StateUpdate(fsm.state), "initial state");
+    }
+
+    pub(crate) fn step(
+        &mut self,
+        fsm: &CrosswalkFsm,
+        transition: Option<Transition<CrosswalkState>>,
+        trigger: Trigger<'_, CrosswalkEvent, StoplightState>,
+    ) {
+        if let Trigger::Event(CrosswalkEvent::TimerTick) = trigger {
+            self.tick += 1;
+        }
+        let message = Some(Recorded::Crosswalk(ToCrosswalk::from_trigger(&trigger)));
+        self.log.step(fsm.name(), self.tick, message, transition.as_ref(), describe("StoplightState", &trigger));
+
+        // If FSM state changed, send update to main
+        if transition.is_some() {
+            self.to_main(FromCrosswalk::StateUpdate(fsm.state), "state update");
+        }
+        // If the call changed, let the stoplight know
+        if fsm.pedestrian_call() != self.call {
+            self.call = fsm.pedestrian_call();
+            self.to_stoplight("call");
+        }
+    }
+}
+
+// Name of the message behind a transition, for the event log
+fn describe<E: Debug, C: Debug>(context: &str, trigger: &Trigger<'_, E, C>) -> String {
+    match trigger {
+        Trigger::Event(event) => format!("{:?}", event),
+        Trigger::Context(state) => format!("{}({:?})", context, state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::event_log::{MemorySink, TransitionRecord};
+    use std::thread;
+
+    fn timer_options(ticks: u32, buttons: ButtonSchedule) -> TimerOptions {
+        TimerOptions {
+            ticks,
+            tick_period: Duration::from_secs(1),
+            buttons,
+            detectors: ButtonSchedule::Never,
+            buses: ButtonSchedule::Never,
+            preempt: Vec::new(),
+            trains: Vec::new(),
+            schedule: None,
+            lockstep: true,
+        }
+    }
+
+    // Run all three threads on a virtual clock and collect the transition log
+    fn simulate(options: TimerOptions) -> (Vec<TransitionRecord>, Duration) {
+        let clock = Arc::new(VirtualClock::auto());
+        let sink = Arc::new(MemorySink::new());
+        let log = EventLog::new(clock.clone()).with_sink(sink.clone());
+        let (tx_stoplight, rx_stoplight) = mpsc::channel();
+        let (tx_crosswalk, rx_crosswalk) = mpsc::channel();
+
+        let timer = {
+            let (tx_stoplight, tx_crosswalk, clock) = (tx_stoplight.clone(), tx_crosswalk.clone(), clock.clone());
+            thread::spawn(move || timer_thread(tx_stoplight, tx_crosswalk, options, clock))
+        };
+        let stoplight = {
+            let log = log.clone();
+            let tx_main = None::<mpsc::Sender<FromStoplight>>;
+            thread::spawn(move || stoplight_thread(StoplightFsm::new(), rx_stoplight, tx_main, Some(tx_crosswalk), log))
+        };
+        let crosswalk = thread::spawn(move || {
+            let tx_main = None::<mpsc::Sender<FromCrosswalk>>;
+            crosswalk_thread(CrosswalkFsm::new(), rx_crosswalk, tx_main, Some(tx_stoplight), log)
+        });
+
+        for handle in [timer, stoplight, crosswalk] {
+            handle.join().unwrap();
+        }
+        (sink.records(), clock.now())
+    }
+
+    #[test]
+    fn test_virtual_clock_simulation_is_reproducible() {
+        // A day of one-second ticks
+        let ticks = 24 * 60 * 60;
+        let buttons = ButtonSchedule::Random { probability: 0.1, seed: 7 };
+        let (first, elapsed) = simulate(timer_options(ticks, buttons.clone()));
+        let (second, _) = simulate(timer_options(ticks, buttons));
+        assert_eq!(elapsed, Duration::from_secs(u64::from(ticks)));
+        assert_eq!(first, second);
+        assert!(first.iter().any(|r| r.to == "Walk"));
+    }
+
+    #[test]
+    fn test_transition_log_in_lockstep() {
+        let (records, _) = simulate(timer_options(12, ButtonSchedule::At(vec![2])));
+        let summary: Vec<String> = records
+            .iter()
+            .map(|r| format!("{} {} {} {} {} {:?}", r.timestamp_ms, r.tick, r.machine, r.to, r.event, r.reason))
+            .collect();
+        // The call is granted within its own tick, and Red is held past its
+        // five ticks until the crosswalk is back to DontWalk
+        assert_eq!(
+            summary,
+            vec![
+                "2000 3 Crosswalk Walk WalkGranted None",
+                "5000 6 Crosswalk BlinkingDontWalk TimerTick None",
+                "7000 8 Crosswalk DontWalk TimerTick None",
+                "8000 9 Stoplight Green TimerTick None",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_schedule_on_virtual_clock() {
+        use crate::schedule::{Schedule, ScheduleEntry, TimingPlan};
+        use crate::stoplight::{FlashMode, StoplightTiming};
+
+        let entry = |at: &str, plan: &str| ScheduleEntry { days: Vec::new(), at: at.parse().unwrap(), plan: plan.into() };
+        let schedule = Schedule {
+            start: "2024-01-01 05:59".parse().unwrap(),
+            holidays: Vec::new(),
+            entries: vec![entry("06:00", "night"), entry("06:10", "peak")],
+        };
+        let peak = StoplightTiming { green: 8, yellow: 2, ..StoplightTiming::default() };
+        let plans = [
+            TimingPlan { name: "night".into(), timing: StoplightTiming::default(), flash: Some(FlashMode::Yellow) },
+            TimingPlan { name: "peak".into(), timing: peak, flash: None },
+        ];
+        // One tick per minute
+        let options = TimerOptions {
+            tick_period: Duration::from_secs(60),
+            schedule: Some(PlanScheduler::new(schedule, plans)),
+            ..timer_options(30, ButtonSchedule::Never)
+        };
+        let (records, _) = simulate(options);
+        let summary: Vec<String> = records.iter().map(|r| format!("{} {} {}", r.tick, r.machine, r.to)).collect();
+        // Night flash starts from a steady Red at 06:00; at 06:10 it ends
+        // through the peak Yellow, and the peak green follows
+        assert_eq!(
+            summary,
+            vec![
+                "1 Stoplight FlashingYellow",
+                "11 Stoplight Yellow",
+                "13 Stoplight Red",
+                "18 Stoplight Green",
+                "26 Stoplight Yellow",
+                "28 Stoplight Red",
+            ]
+        );
+    }
+}